@@ -0,0 +1,45 @@
+//! Fixture data shared by the benchmarks.
+
+// Each benchmark uses only some of these helpers
+#![allow(dead_code)]
+
+use std::path::Path;
+
+pub const HEADER: [&str; 4] = ["Id", "Name", "Description", "Price"];
+
+pub fn header() -> Vec<String> {
+    HEADER.map(String::from).to_vec()
+}
+
+/// A product row with a quoted, comma-containing field, so quoting is exercised
+pub fn fixture_row(id: usize) -> Vec<String> {
+    vec![
+        id.to_string(),
+        format!("Product {id}"),
+        "A product, with \"quotes\" and commas".to_string(),
+        format!("{}.99", id % 1000),
+    ]
+}
+
+pub fn fixture_rows(count: usize) -> Vec<Vec<String>> {
+    (0..count).map(fixture_row).collect()
+}
+
+/// Writes fixture rows to `path` until it holds at least `target_bytes`, without
+/// keeping them in memory, and returns the file size
+pub fn write_fixture(path: &Path, target_bytes: u64) -> u64 {
+    let mut writer = csv::Writer::from_path(path).unwrap();
+    writer.write_record(HEADER).unwrap();
+    let mut id = 0;
+    loop {
+        for _ in 0..10_000 {
+            writer.write_record(fixture_row(id)).unwrap();
+            id += 1;
+        }
+        writer.flush().unwrap();
+        let written = std::fs::metadata(path).unwrap().len();
+        if written >= target_bytes {
+            return written;
+        }
+    }
+}
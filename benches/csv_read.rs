@@ -0,0 +1,45 @@
+//! Compares buffered reading with the memory-mapped path used by `--mmap`, both
+//! through `csv_io::read_csv`, the function the binary reads CSV inputs with.
+//!
+//! The input size defaults to 64 MiB and can be raised for multi-GB runs:
+//!
+//! ```bash
+//! CSV_COMBINE_BENCH_MB=4096 cargo bench --bench csv_read
+//! ```
+
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use csv_combine::csv_io::{read_csv, CsvReadOptions};
+use std::hint::black_box;
+use tempfile::TempDir;
+
+fn bench_csv_read(c: &mut Criterion) {
+    let megabytes: u64 = std::env::var("CSV_COMBINE_BENCH_MB")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(64);
+
+    let test_dir = TempDir::new().unwrap();
+    let csv_path = test_dir.path().join("bench.csv");
+    let bytes = common::write_fixture(&csv_path, megabytes * 1024 * 1024);
+
+    let mut group = c.benchmark_group("csv_read");
+    group.throughput(Throughput::Bytes(bytes));
+    group.sample_size(10);
+
+    for (name, mmap) in [("from_path", false), ("mmap", true)] {
+        let options = CsvReadOptions {
+            mmap,
+            ..Default::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(read_csv(&csv_path, &options).unwrap()).len())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_csv_read);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! Compares `csv_io::write_combined_csv`, the function the binary writes output
+//! CSVs with, without and with the large write buffer of `--write-buffer-size`.
+//!
+//! The output defaults to 2 million rows and can be resized:
+//!
+//! ```bash
+//! CSV_COMBINE_BENCH_ROWS=10000000 cargo bench --bench csv_write
+//! ```
+
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use csv_combine::csv_io::{write_combined_csv, CsvWriteOptions};
+use tempfile::TempDir;
+
+/// Buffer size of the `buffered` case, 1 MiB
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+fn bench_csv_write(c: &mut Criterion) {
+    let count: usize = std::env::var("CSV_COMBINE_BENCH_ROWS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(2_000_000);
+    let header = common::header();
+    let rows = common::fixture_rows(count);
+
+    let test_dir = TempDir::new().unwrap();
+    let csv_path = test_dir.path().join("bench.csv");
+
+    let mut group = c.benchmark_group("csv_write");
+    group.throughput(Throughput::Elements(count as u64));
+    group.sample_size(10);
+
+    for (name, buffer_size) in [("unbuffered", None), ("buffered", Some(BUFFER_SIZE))] {
+        let options = CsvWriteOptions {
+            buffer_size,
+            ..Default::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| write_combined_csv(&csv_path, &header, &rows, &options).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_csv_write);
+criterion_main!(benches);
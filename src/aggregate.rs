@@ -0,0 +1,139 @@
+/// Separator between the values joined by `concat`
+const CONCAT_SEPARATOR: &str = "; ";
+
+/// How `--aggregate` combines a column of rows collapsed by `--dedup-key`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFn {
+    Sum,
+    Max,
+    Min,
+    First,
+    Last,
+    Concat,
+}
+
+/// An `--aggregate` entry: `COLUMN:FUNC`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateRule {
+    pub column: String,
+    pub function: AggregateFn,
+}
+
+impl AggregateRule {
+    /// Parses `COLUMN:FUNC` where FUNC is sum, max, min, first, last or concat
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let Some((column, function)) = spec.rsplit_once(':') else {
+            return Err(format!("expected COLUMN:FUNC, got '{}'", spec));
+        };
+
+        let column = column.trim();
+        if column.is_empty() {
+            return Err(format!("missing column name in '{}'", spec));
+        }
+
+        let function = match function.trim().to_lowercase().as_str() {
+            "sum" => AggregateFn::Sum,
+            "max" => AggregateFn::Max,
+            "min" => AggregateFn::Min,
+            "first" => AggregateFn::First,
+            "last" => AggregateFn::Last,
+            "concat" => AggregateFn::Concat,
+            other => {
+                return Err(format!(
+                    "unknown function '{}' in '{}' (expected sum, max, min, first, last or concat)",
+                    other, spec
+                ));
+            }
+        };
+
+        Ok(Self {
+            column: column.to_string(),
+            function,
+        })
+    }
+}
+
+/// Folds a collapsed row's `value` into the kept row's `current` value. Empty
+/// values are ignored by every function. Returns `false`, leaving `current`
+/// unchanged, when `sum` meets a value that is not a number.
+pub fn fold(current: &mut String, value: &str, function: AggregateFn) -> bool {
+    let value = value.trim();
+    if value.is_empty() || function == AggregateFn::First {
+        return true;
+    }
+    if current.trim().is_empty() {
+        *current = value.to_string();
+        return true;
+    }
+
+    match function {
+        AggregateFn::Sum => match sum(current.trim(), value) {
+            Some(total) => *current = total,
+            None => return false,
+        },
+        AggregateFn::Max | AggregateFn::Min => {
+            let greater = match (current.trim().parse::<f64>(), value.parse::<f64>()) {
+                (Ok(current), Ok(value)) => value > current,
+                // Dates in ISO format and other text compare as strings
+                _ => value > current.trim(),
+            };
+            if greater == (function == AggregateFn::Max) && value != current.trim() {
+                *current = value.to_string();
+            }
+        }
+        AggregateFn::Last => *current = value.to_string(),
+        AggregateFn::Concat => {
+            current.push_str(CONCAT_SEPARATOR);
+            current.push_str(value);
+        }
+        AggregateFn::First => {}
+    }
+    true
+}
+
+/// Adds two numbers, keeping integers integral
+fn sum(a: &str, b: &str) -> Option<String> {
+    if let (Ok(a), Ok(b)) = (a.parse::<i64>(), b.parse::<i64>())
+        && let Some(total) = a.checked_add(b)
+    {
+        return Some(total.to_string());
+    }
+
+    let total = a.parse::<f64>().ok()? + b.parse::<f64>().ok()?;
+    total.is_finite().then(|| total.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folded(values: &[&str], function: AggregateFn) -> String {
+        let mut current = values[0].to_string();
+        for value in &values[1..] {
+            fold(&mut current, value, function);
+        }
+        current
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(folded(&["2", "3", "", "1.5"], AggregateFn::Sum), "6.5");
+        assert_eq!(folded(&["10", "9", "100"], AggregateFn::Max), "100");
+        assert_eq!(folded(&["10", "9", "100"], AggregateFn::Min), "9");
+        assert_eq!(
+            folded(&["2024-03-01", "2023-12-31"], AggregateFn::Min),
+            "2023-12-31"
+        );
+        assert_eq!(folded(&["a", "b", ""], AggregateFn::First), "a");
+        assert_eq!(folded(&["a", "b", ""], AggregateFn::Last), "b");
+        assert_eq!(
+            folded(&["", "late", "", "fragile"], AggregateFn::Concat),
+            "late; fragile"
+        );
+
+        let mut current = "2".to_string();
+        assert!(!fold(&mut current, "two", AggregateFn::Sum));
+        assert_eq!(current, "2");
+        assert!(AggregateRule::parse("Qty:avg").is_err());
+    }
+}
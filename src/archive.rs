@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+
+/// Archive format of `--archive`
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ArchiveFormat {
+    /// Uncompressed tarball
+    Tar,
+    /// Deflate-compressed zip
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+/// Packs `files` into an archive at `path`, each stored under its file name,
+/// then removes them. The files are left in place when packing fails.
+pub fn pack(path: &Path, format: ArchiveFormat, files: &[PathBuf]) -> Result<()> {
+    let archive = File::create(path)
+        .with_context(|| format!("Failed to create archive {}", path.display()))?;
+    let entry_name = |file: &PathBuf| {
+        file.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    match format {
+        ArchiveFormat::Tar => {
+            let mut builder = tar::Builder::new(archive);
+            for file in files {
+                builder.append_path_with_name(file, entry_name(file))?;
+            }
+            builder.finish()?;
+        }
+        ArchiveFormat::Zip => {
+            let mut writer = zip::ZipWriter::new(archive);
+            let options =
+                SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+            for file in files {
+                writer.start_file(entry_name(file), options)?;
+                std::io::copy(&mut File::open(file)?, &mut writer)?;
+            }
+            writer.finish()?;
+        }
+    }
+
+    for file in files {
+        std::fs::remove_file(file)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pack_removes_packed_files() {
+        let test_dir = TempDir::new().unwrap();
+        let files: Vec<PathBuf> = ["out_part1.csv", "out_part2.csv"]
+            .iter()
+            .map(|name| test_dir.path().join(name))
+            .collect();
+
+        for format in [ArchiveFormat::Tar, ArchiveFormat::Zip] {
+            for file in &files {
+                fs::write(file, "Name\nAlice\n").unwrap();
+            }
+            let path = test_dir.path().join(format!("out.{}", format.extension()));
+            pack(&path, format, &files).unwrap();
+
+            assert!(files.iter().all(|file| !file.exists()));
+            let names: Vec<String> = match format {
+                ArchiveFormat::Tar => tar::Archive::new(File::open(&path).unwrap())
+                    .entries()
+                    .unwrap()
+                    .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+                    .collect(),
+                ArchiveFormat::Zip => zip::ZipArchive::new(File::open(&path).unwrap())
+                    .unwrap()
+                    .file_names()
+                    .map(|name| name.unwrap().into_owned())
+                    .collect(),
+            };
+            assert_eq!(names, vec!["out_part1.csv", "out_part2.csv"]);
+        }
+    }
+}
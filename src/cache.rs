@@ -0,0 +1,213 @@
+use anyhow::Result;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+pub const INDEX_FILE: &str = ".csv_combine_cache.json";
+/// Parsed results live next to the index. They use a `.cache` extension so a later
+/// scan of the same directory never mistakes them for inputs.
+const RESULTS_DIR: &str = ".csv_combine_cache";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheIndex {
+    /// Fingerprint of the read options the entries were parsed with
+    options: String,
+    entries: HashMap<String, CacheEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    mtime_nanos: u128,
+    size: u64,
+    content_hash: String,
+    location: String,
+}
+
+/// Remembers parsed file contents across runs, keyed by path, mtime and size
+pub struct FileCache {
+    dir: PathBuf,
+    index: CacheIndex,
+}
+
+impl FileCache {
+    /// Loads the cache from `dir`. Entries parsed with different read options are discarded.
+    pub fn load(dir: impl AsRef<Path>, options_fingerprint: &str) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        let index = std::fs::read_to_string(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str::<CacheIndex>(&json).ok())
+            .filter(|index| index.options == options_fingerprint)
+            .unwrap_or_else(|| CacheIndex {
+                options: options_fingerprint.to_string(),
+                entries: HashMap::new(),
+            });
+
+        Self { dir, index }
+    }
+
+    /// Returns the cached contents of `path` if it has not changed since it was cached
+    pub fn get(&self, path: &Path) -> Option<Vec<Vec<String>>> {
+        let entry = self.index.entries.get(&cache_key(path))?;
+        let (mtime_nanos, size) = file_stamp(path)?;
+        if entry.mtime_nanos != mtime_nanos || entry.size != size {
+            debug!("Cache entry for {} is stale", path.display());
+            return None;
+        }
+
+        match read_result(&self.dir.join(&entry.location)) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                warn!("Ignoring unreadable cache entry for {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Stores the parsed contents of `path`, replacing any previous entry
+    pub fn put(&mut self, path: &Path, data: &[Vec<String>]) -> Result<()> {
+        let Some((mtime_nanos, size)) = file_stamp(path) else {
+            return Ok(());
+        };
+
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let content_hash = format!("{:x}", hasher.finish());
+        let location = format!("{}/{}.cache", RESULTS_DIR, content_hash);
+
+        let results_dir = self.dir.join(RESULTS_DIR);
+        std::fs::create_dir_all(&results_dir)?;
+        write_result(&self.dir.join(&location), data)?;
+
+        let entry = CacheEntry {
+            mtime_nanos,
+            size,
+            content_hash,
+            location,
+        };
+        if let Some(old) = self.index.entries.insert(cache_key(path), entry.clone())
+            && old.location != entry.location
+            && !self.index.entries.values().any(|e| e.location == old.location)
+        {
+            let _ = std::fs::remove_file(self.dir.join(old.location));
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(
+            self.dir.join(INDEX_FILE),
+            serde_json::to_string_pretty(&self.index)?,
+        )?;
+        Ok(())
+    }
+}
+
+fn cache_key(path: &Path) -> String {
+    std::fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string()
+}
+
+fn file_stamp(path: &Path) -> Option<(u128, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((mtime.as_nanos(), metadata.len()))
+}
+
+fn write_result(path: &Path, data: &[Vec<String>]) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_path(path)?;
+    for row in data {
+        // A single empty field would be read back as an empty record, so mark empty rows
+        if row.is_empty() {
+            writer.write_record(["\u{0}"])?;
+        } else {
+            writer.write_record(row)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_result(path: &Path) -> Result<Vec<Vec<String>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+
+    let mut data = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+        if record.len() == 1 && &record[0] == "\u{0}" {
+            data.push(Vec::new());
+        } else {
+            data.push(record.iter().map(|s| s.to_string()).collect());
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn sample() -> Vec<Vec<String>> {
+        vec![
+            vec!["Name".to_string(), "Note".to_string()],
+            vec!["Alice".to_string(), "says \"hi\", twice".to_string()],
+            vec!["Bob".to_string(), "".to_string()],
+        ]
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let test_dir = TempDir::new().unwrap();
+        let input = test_dir.path().join("input.csv");
+        fs::write(&input, "ignored").unwrap();
+
+        let mut cache = FileCache::load(test_dir.path(), "opts");
+        assert!(cache.get(&input).is_none());
+        cache.put(&input, &sample()).unwrap();
+        cache.save().unwrap();
+
+        let cache = FileCache::load(test_dir.path(), "opts");
+        assert_eq!(cache.get(&input), Some(sample()));
+
+        // Different read options invalidate everything
+        let cache = FileCache::load(test_dir.path(), "other opts");
+        assert!(cache.get(&input).is_none());
+    }
+
+    #[test]
+    fn test_cache_invalidates_changed_files() {
+        let test_dir = TempDir::new().unwrap();
+        let input = test_dir.path().join("input.csv");
+        fs::write(&input, "v1").unwrap();
+
+        let mut cache = FileCache::load(test_dir.path(), "opts");
+        cache.put(&input, &sample()).unwrap();
+        assert!(cache.get(&input).is_some());
+
+        fs::write(&input, "version two").unwrap();
+        assert!(cache.get(&input).is_none());
+    }
+
+    #[test]
+    fn test_cache_keeps_empty_header_row() {
+        let test_dir = TempDir::new().unwrap();
+        let input = test_dir.path().join("empty.csv");
+        fs::write(&input, "").unwrap();
+
+        let mut cache = FileCache::load(test_dir.path(), "opts");
+        cache.put(&input, &[Vec::new()]).unwrap();
+        assert_eq!(cache.get(&input), Some(vec![Vec::new()]));
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::NaiveDate;
+use clap::ValueEnum;
+
+/// Date formats accepted by `--schema COLUMN:date`, written back as `%Y-%m-%d`
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%Y/%m/%d", "%m/%d/%Y", "%m-%d-%Y", "%d.%m.%Y"];
+
+/// Type a column is declared to have in `--schema`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeclaredType {
+    Int,
+    Float,
+    Bool,
+    Date,
+    Text,
+}
+
+impl DeclaredType {
+    fn name(self) -> &'static str {
+        match self {
+            DeclaredType::Int => "int",
+            DeclaredType::Float => "float",
+            DeclaredType::Bool => "bool",
+            DeclaredType::Date => "date",
+            DeclaredType::Text => "text",
+        }
+    }
+}
+
+/// A `--schema` entry: `COLUMN:TYPE`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeSpec {
+    pub column: String,
+    pub column_type: DeclaredType,
+}
+
+impl TypeSpec {
+    /// Parses `COLUMN:TYPE` where TYPE is int, float, bool, date or text
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let Some((column, column_type)) = spec.rsplit_once(':') else {
+            return Err(format!("expected COLUMN:TYPE, got '{}'", spec));
+        };
+
+        let column = column.trim();
+        if column.is_empty() {
+            return Err(format!("missing column name in '{}'", spec));
+        }
+
+        let column_type = match column_type.trim().to_lowercase().as_str() {
+            "int" | "integer" => DeclaredType::Int,
+            "float" | "number" => DeclaredType::Float,
+            "bool" | "boolean" => DeclaredType::Bool,
+            "date" => DeclaredType::Date,
+            "text" | "string" => DeclaredType::Text,
+            other => {
+                return Err(format!(
+                    "unknown type '{}' in '{}' (expected int, float, bool, date or text)",
+                    other, spec
+                ));
+            }
+        };
+
+        Ok(Self {
+            column: column.to_string(),
+            column_type,
+        })
+    }
+}
+
+/// What `--on-type-error` does with rows holding a value that cannot be coerced
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum OnTypeError {
+    /// Move the row to a rejects file next to the output
+    #[default]
+    Reject,
+    /// Drop the row
+    Skip,
+    /// Stop the run with an error
+    Fail,
+}
+
+/// Coerces a non-empty cell to its canonical form, or `None` when it is not of that type
+pub fn coerce(value: &str, column_type: DeclaredType) -> Option<String> {
+    let value = value.trim();
+    match column_type {
+        DeclaredType::Int => match value.parse::<i64>() {
+            Ok(int) => Some(int.to_string()),
+            // Spreadsheet exports often write whole numbers as "30.0"
+            Err(_) => value
+                .parse::<f64>()
+                .ok()
+                .filter(|float| {
+                    float.is_finite() && float.fract() == 0.0 && float.abs() < i64::MAX as f64
+                })
+                .map(|float| (float as i64).to_string()),
+        },
+        // Rust also parses "inf" and "NaN", which are not numbers in a CSV
+        DeclaredType::Float => value
+            .parse::<f64>()
+            .ok()
+            .filter(|float| float.is_finite())
+            .map(|float| float.to_string()),
+        DeclaredType::Bool => match value.to_lowercase().as_str() {
+            "true" | "t" | "yes" | "y" | "1" => Some("true".to_string()),
+            "false" | "f" | "no" | "n" | "0" => Some("false".to_string()),
+            _ => None,
+        },
+        DeclaredType::Date => DATE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+            .map(|date| date.format("%Y-%m-%d").to_string()),
+        DeclaredType::Text => Some(value.to_string()),
+    }
+}
+
+/// Rows split by `enforce_types`
+#[derive(Debug, Default)]
+pub struct Enforced {
+    pub accepted: Vec<Vec<String>>,
+    /// Rejected rows, each followed by a description of its first bad value
+    pub rejected: Vec<Vec<String>>,
+    /// Number of bad values per declared column, in `--schema` order
+    pub failures: Vec<(String, usize)>,
+}
+
+/// Coerces the declared columns of every row. Empty cells are left empty, and
+/// declared columns missing from `header` are ignored.
+pub fn enforce_types(header: &[String], rows: Vec<Vec<String>>, specs: &[TypeSpec]) -> Enforced {
+    let columns: Vec<(usize, &TypeSpec)> = specs
+        .iter()
+        .filter_map(|spec| {
+            header
+                .iter()
+                .position(|col| *col == spec.column)
+                .map(|idx| (idx, spec))
+        })
+        .collect();
+
+    let mut enforced = Enforced {
+        failures: columns
+            .iter()
+            .map(|(_, spec)| (spec.column.clone(), 0))
+            .collect(),
+        ..Default::default()
+    };
+    for mut row in rows {
+        let mut error = None;
+        for (position, &(idx, spec)) in columns.iter().enumerate() {
+            let Some(cell) = row.get_mut(idx) else {
+                continue;
+            };
+            if cell.trim().is_empty() {
+                continue;
+            }
+            match coerce(cell, spec.column_type) {
+                Some(value) => *cell = value,
+                None => {
+                    enforced.failures[position].1 += 1;
+                    error.get_or_insert_with(|| {
+                        format!(
+                            "{}: '{}' is not a valid {}",
+                            spec.column,
+                            cell,
+                            spec.column_type.name()
+                        )
+                    });
+                }
+            }
+        }
+
+        match error {
+            Some(error) => {
+                row.push(error);
+                enforced.rejected.push(row);
+            }
+            None => enforced.accepted.push(row),
+        }
+    }
+
+    enforced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coerce() {
+        assert_eq!(coerce(" 30.0 ", DeclaredType::Int), Some("30".to_string()));
+        assert_eq!(coerce("30.5", DeclaredType::Int), None);
+        assert_eq!(coerce("9.50", DeclaredType::Float), Some("9.5".to_string()));
+        assert_eq!(coerce("NaN", DeclaredType::Float), None);
+        assert_eq!(coerce("Yes", DeclaredType::Bool), Some("true".to_string()));
+        assert_eq!(
+            coerce("03/01/2024", DeclaredType::Date),
+            Some("2024-03-01".to_string())
+        );
+        assert_eq!(coerce("next week", DeclaredType::Date), None);
+        assert!(TypeSpec::parse("age:decimal").is_err());
+    }
+
+    #[test]
+    fn test_enforce_types_rejects_rows() {
+        let header = vec!["name".to_string(), "age".to_string()];
+        let rows = vec![
+            vec!["Alice".to_string(), "30".to_string()],
+            vec!["Bob".to_string(), "thirty".to_string()],
+            vec!["Carol".to_string(), "".to_string()],
+        ];
+        let specs = [
+            TypeSpec::parse("age:int").unwrap(),
+            TypeSpec::parse("missing:date").unwrap(),
+        ];
+
+        let enforced = enforce_types(&header, rows, &specs);
+        assert_eq!(enforced.accepted.len(), 2);
+        assert_eq!(
+            enforced.rejected,
+            vec![vec!["Bob", "thirty", "age: 'thirty' is not a valid int"]]
+        );
+        assert_eq!(enforced.failures, vec![("age".to_string(), 1)]);
+    }
+}
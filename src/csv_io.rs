@@ -0,0 +1,342 @@
+//! Reading CSV files into rows of strings and writing them back out, as the
+//! `csv_combine` binary does for every CSV input and output. Benchmarks measure
+//! these functions directly.
+
+use crate::encoding::EncodingWriter;
+use anyhow::{Context, Result};
+use log::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// How [`read_csv`] reads a file
+#[derive(Debug, Default, Clone)]
+pub struct CsvReadOptions {
+    /// Header for headerless files, every record is then data
+    pub header: Option<Vec<String>>,
+    /// Read every record as data, ragged rows included, leaving the caller to
+    /// pick the header
+    pub raw_rows: bool,
+    /// Columns kept, all when empty
+    pub read_columns: Vec<String>,
+    /// Skip records of the wrong width or invalid UTF-8 instead of failing
+    pub skip_bad_rows: bool,
+    /// Read through a memory map instead of a buffered reader
+    pub mmap: bool,
+    /// Field delimiter, `,` when unset
+    pub delimiter: Option<u8>,
+}
+
+/// How [`create_csv_writer`] and [`write_combined_csv`] write a file
+#[derive(Debug, Default, Clone)]
+pub struct CsvWriteOptions {
+    /// Record terminator, the csv default of `\n` when unset
+    pub terminator: Option<csv::Terminator>,
+    /// Comment lines written before the header, prefix included
+    pub comment: Vec<String>,
+    /// Encoding of the file, UTF-8 when unset
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+    /// Replace unrepresentable characters with `?` instead of failing
+    pub lossy_encoding: bool,
+    /// Fail on rows shorter than the header instead of padding them
+    pub strict: bool,
+    /// Bytes buffered between the csv writer and the file
+    pub buffer_size: Option<usize>,
+}
+
+/// Reads a `.csv` or `.csv.gz` file, the header being the first row
+pub fn read_csv(path: &Path, options: &CsvReadOptions) -> Result<Vec<Vec<String>>> {
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .has_headers(options.header.is_none() && !options.raw_rows)
+        .flexible(options.skip_bad_rows || options.raw_rows)
+        .delimiter(options.delimiter.unwrap_or(b','));
+
+    if is_gzipped_csv(path) {
+        let decoder = flate2::read::GzDecoder::new(std::fs::File::open(path)?);
+        read_csv_records(path, builder.from_reader(decoder), options)
+    } else if options.mmap {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the mapping is read-only and dropped before returning. Inputs are
+        // not expected to be modified by another process while a combine is running.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        read_csv_records(path, builder.from_reader(&mmap[..]), options)
+    } else {
+        read_csv_records(path, builder.from_path(path)?, options)
+    }
+}
+
+/// Reads the header, unless one is given or `raw_rows` is set, and every record
+/// of `reader`. `path` only names the file in messages.
+pub fn read_csv_records<R: std::io::Read>(
+    path: &Path,
+    mut reader: csv::Reader<R>,
+    options: &CsvReadOptions,
+) -> Result<Vec<Vec<String>>> {
+    let mut data = Vec::new();
+
+    // Read and include the header
+    if let Some(header) = &options.header {
+        data.push(header.clone());
+    } else if options.raw_rows {
+        // Every record is read as data, the header is picked afterwards
+    } else if let Ok(headers) = reader.headers() {
+        let header_row: Vec<String> = headers.iter().map(|s| s.to_string()).collect();
+        data.push(header_row);
+    }
+
+    let projection = data
+        .first()
+        .and_then(|header| column_projection(header, &options.read_columns));
+    if let (Some(header), Some(projection)) = (data.first_mut(), &projection) {
+        *header = project_row(header.iter().map(String::as_str), projection);
+    }
+
+    if options.skip_bad_rows && !options.raw_rows {
+        return read_csv_records_skipping_bad(path, reader, data, projection.as_deref(), options);
+    }
+
+    // Read all data rows
+    for result in reader.records() {
+        let record = result?;
+        let row: Vec<String> = match &projection {
+            Some(projection) => project_row(record.iter(), projection),
+            None => record.iter().map(|s| s.to_string()).collect(),
+        };
+        data.push(row);
+    }
+
+    Ok(data)
+}
+
+/// Indices of the `columns` present in `header`, in file order, or `None`
+/// when every column is kept
+pub fn column_projection(header: &[String], columns: &[String]) -> Option<Vec<usize>> {
+    if columns.is_empty() {
+        return None;
+    }
+    Some(
+        header
+            .iter()
+            .enumerate()
+            .filter(|(_, col)| columns.contains(col))
+            .map(|(idx, _)| idx)
+            .collect(),
+    )
+}
+
+/// Keeps only the projected fields of a record
+pub fn project_row<'a>(fields: impl Iterator<Item = &'a str>, projection: &[usize]) -> Vec<String> {
+    let fields: Vec<&str> = fields.collect();
+    projection
+        .iter()
+        .map(|&idx| fields.get(idx).copied().unwrap_or_default().to_string())
+        .collect()
+}
+
+/// Reads the remaining records of a flexible reader, skipping records whose field
+/// count differs from the header or that are not valid UTF-8
+fn read_csv_records_skipping_bad<R: std::io::Read>(
+    path: &Path,
+    mut reader: csv::Reader<R>,
+    mut data: Vec<Vec<String>>,
+    projection: Option<&[usize]>,
+    options: &CsvReadOptions,
+) -> Result<Vec<Vec<String>>> {
+    // The file's header sets the expected width (ours may be narrowed by --read-columns).
+    // Without a header row the first record does, like csv does.
+    let mut expected_len = if options.header.is_none() {
+        reader.headers().ok().map(|header| header.len())
+    } else {
+        None
+    };
+    let mut skipped = 0;
+
+    for result in reader.byte_records() {
+        let record = result?;
+        let line = record.position().map_or(0, |pos| pos.line());
+        let width = *expected_len.get_or_insert(record.len());
+
+        let row: Option<Vec<String>> = record
+            .iter()
+            .map(|field| std::str::from_utf8(field).ok().map(str::to_string))
+            .collect();
+
+        match row {
+            Some(row) if row.len() == width => match projection {
+                Some(projection) => {
+                    data.push(project_row(row.iter().map(String::as_str), projection))
+                }
+                None => data.push(row),
+            },
+            _ => {
+                let content: Vec<String> = record
+                    .iter()
+                    .map(|field| String::from_utf8_lossy(field).to_string())
+                    .collect();
+                warn!(
+                    "Skipping bad row at line {} of {} ({} fields, expected {}): {}",
+                    line,
+                    path.display(),
+                    record.len(),
+                    width,
+                    content.join(",")
+                );
+                skipped += 1;
+            }
+        }
+    }
+
+    if skipped > 0 {
+        warn!("Skipped {} bad rows in {}", skipped, path.display());
+    }
+
+    Ok(data)
+}
+
+/// True for `.csv.gz` files, which are read as CSV through a gzip decoder
+pub fn is_gzipped_csv(file_path: &Path) -> bool {
+    file_path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().to_lowercase().ends_with(".csv.gz"))
+}
+
+/// Writes `header` and `data` to a new CSV, padding short rows as [`write_row`] does
+pub fn write_combined_csv(
+    output_path: impl AsRef<Path>,
+    header: &[String],
+    data: &[Vec<String>],
+    options: &CsvWriteOptions,
+) -> Result<()> {
+    let output_path = output_path.as_ref();
+    let mut writer = create_csv_writer(output_path, header, options)?;
+
+    // Write all data rows
+    let mut padded = Vec::new();
+    for (idx, row) in data.iter().enumerate() {
+        let was_padded =
+            write_row(&mut writer, row, header.len(), options.strict).with_context(|| {
+                format!(
+                    "Failed to write data row {} of {}",
+                    idx + 1,
+                    output_path.display()
+                )
+            })?;
+        if was_padded {
+            padded.push(idx + 1);
+        }
+    }
+    if let Some(first) = padded.first() {
+        warn!(
+            "Padded {} rows of {} shorter than its header, the first being data row {}",
+            padded.len(),
+            output_path.display(),
+            first
+        );
+    }
+
+    writer
+        .flush()
+        .with_context(|| format!("Failed to write {}", output_path.display()))?;
+    Ok(())
+}
+
+/// Writes a data row, padding a row shorter than the header with empty cells so
+/// the output stays rectangular, and returns whether it was padded. With
+/// `strict` a short row is an error instead.
+pub fn write_row(
+    writer: &mut csv::Writer<OutputFile>,
+    row: &[String],
+    width: usize,
+    strict: bool,
+) -> Result<bool> {
+    if row.len() >= width {
+        writer.write_record(row)?;
+        return Ok(false);
+    }
+    if strict {
+        return Err(anyhow::anyhow!(
+            "the row has {} of the header's {} columns (--strict)",
+            row.len(),
+            width
+        ));
+    }
+
+    let padding = std::iter::repeat_n("", width - row.len());
+    writer.write_record(row.iter().map(String::as_str).chain(padding))?;
+    Ok(true)
+}
+
+/// An output CSV, transcoded as `--output-encoding` says
+pub type OutputFile = EncodingWriter<BufWriter<File>>;
+
+/// Creates an output CSV and writes its comment lines and header
+pub fn create_csv_writer(
+    output_path: impl AsRef<Path>,
+    header: &[String],
+    options: &CsvWriteOptions,
+) -> Result<csv::Writer<OutputFile>> {
+    let mut builder = csv::WriterBuilder::new();
+    if let Some(terminator) = options.terminator {
+        builder.terminator(terminator);
+    }
+    // Without --write-buffer-size the zero-capacity buffer passes the csv writer's own
+    // 8 KiB chunks straight through
+    let file = BufWriter::with_capacity(
+        options.buffer_size.unwrap_or(0),
+        File::create(output_path.as_ref())?,
+    );
+    let mut file = EncodingWriter::new(file, options.encoding, options.lossy_encoding);
+
+    // The csv writer has no notion of comments, so they go straight to the file
+    let line_end: &[u8] = match options.terminator {
+        Some(csv::Terminator::Any(byte)) => &[byte],
+        Some(csv::Terminator::CRLF) => b"\r\n",
+        _ => b"\n",
+    };
+    for line in &options.comment {
+        file.write_all(line.as_bytes())?;
+        file.write_all(line_end)?;
+    }
+    let mut writer = builder.from_writer(file);
+
+    // Write header
+    writer.write_record(header)?;
+    Ok(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_csv_projects_and_skips_bad_rows() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("data.csv");
+        fs::write(&path, "id;name;price\n1;Apple;0.5\n2;Pear\n3;Plum;0.7\n").unwrap();
+
+        let options = CsvReadOptions {
+            read_columns: vec!["price".to_string(), "id".to_string()],
+            skip_bad_rows: true,
+            delimiter: Some(b';'),
+            ..Default::default()
+        };
+        assert_eq!(
+            read_csv(&path, &options).unwrap(),
+            vec![vec!["id", "price"], vec!["1", "0.5"], vec!["3", "0.7"]]
+        );
+
+        let mmap = CsvReadOptions {
+            mmap: true,
+            ..options
+        };
+        assert_eq!(read_csv(&path, &mmap).unwrap().len(), 3);
+        let strict = CsvReadOptions {
+            delimiter: Some(b';'),
+            ..Default::default()
+        };
+        assert!(read_csv(&path, &strict).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+
+/// Formats tried, in order, when no `--datetime-format` is given
+const DEFAULT_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%m/%d/%Y %H:%M:%S",
+    "%m/%d/%Y %H:%M",
+];
+
+/// Timezone that values without an explicit offset are interpreted in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceZone {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+/// A `--normalize-datetime COLUMN[:ZONE]` specification
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatetimeSpec {
+    pub column: String,
+    pub zone: SourceZone,
+}
+
+impl DatetimeSpec {
+    /// Parses `COLUMN[:ZONE]` where ZONE is `utc` (default), `local` or an offset like `+05:30`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (column, zone) = match spec.rsplit_once(':') {
+            // Offsets such as "+05:30" contain a colon themselves
+            Some((column, minutes))
+                if minutes.len() == 2
+                    && minutes.chars().all(|c| c.is_ascii_digit())
+                    && column.contains(':') =>
+            {
+                let (column, hours) = column.rsplit_once(':').unwrap_or((column, ""));
+                (column, format!("{}:{}", hours, minutes))
+            }
+            Some((column, zone)) => (column, zone.to_string()),
+            None => (spec, "utc".to_string()),
+        };
+
+        let column = column.trim();
+        if column.is_empty() {
+            return Err(format!("missing column name in '{}'", spec));
+        }
+
+        let zone = match zone.trim().to_lowercase().as_str() {
+            "utc" | "z" => SourceZone::Utc,
+            "local" => SourceZone::Local,
+            offset => SourceZone::Fixed(parse_offset(offset).ok_or_else(|| {
+                format!(
+                    "invalid timezone '{}' in '{}' (expected utc, local or +HH:MM)",
+                    zone, spec
+                )
+            })?),
+        };
+
+        Ok(Self {
+            column: column.to_string(),
+            zone,
+        })
+    }
+}
+
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    let (sign, rest) = match offset.chars().next()? {
+        '+' => (1, &offset[1..]),
+        '-' => (-1, &offset[1..]),
+        _ => return None,
+    };
+    let digits: String = rest.chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parses a cell into UTC, returning `None` when it does not match any format
+pub fn parse_to_utc(value: &str, zone: SourceZone, format: Option<&str>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    let formats: Vec<&str> = match format {
+        Some(format) => vec![format],
+        None => {
+            // Values that already carry an offset need no zone
+            if let Ok(parsed) = DateTime::parse_from_rfc3339(value) {
+                return Some(parsed.with_timezone(&Utc));
+            }
+            DEFAULT_FORMATS.to_vec()
+        }
+    };
+
+    for format in formats {
+        if let Ok(parsed) = DateTime::parse_from_str(value, format) {
+            return Some(parsed.with_timezone(&Utc));
+        }
+        if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+            let utc = match zone {
+                SourceZone::Utc => Some(naive.and_utc()),
+                SourceZone::Local => Local
+                    .from_local_datetime(&naive)
+                    .earliest()
+                    .map(|dt| dt.with_timezone(&Utc)),
+                SourceZone::Fixed(offset) => offset
+                    .from_local_datetime(&naive)
+                    .single()
+                    .map(|dt| dt.with_timezone(&Utc)),
+            };
+            if utc.is_some() {
+                return utc;
+            }
+        }
+    }
+
+    None
+}
+
+/// Rewrites the configured columns to UTC ISO-8601, returning the number of
+/// non-empty cells that could not be parsed (those are left unchanged)
+pub fn normalize_datetimes(
+    header: &[String],
+    rows: &mut [Vec<String>],
+    specs: &[DatetimeSpec],
+    format: Option<&str>,
+) -> usize {
+    let mut failures = 0;
+
+    for spec in specs {
+        let Some(col_idx) = header.iter().position(|col| *col == spec.column) else {
+            continue;
+        };
+
+        for row in rows.iter_mut() {
+            let Some(cell) = row.get_mut(col_idx) else {
+                continue;
+            };
+            if cell.trim().is_empty() {
+                continue;
+            }
+            match parse_to_utc(cell, spec.zone, format) {
+                Some(utc) => *cell = utc.to_rfc3339_opts(SecondsFormat::Secs, true),
+                None => failures += 1,
+            }
+        }
+    }
+
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec_defaults_to_utc() {
+        let spec = DatetimeSpec::parse("Timestamp").unwrap();
+        assert_eq!(spec.column, "Timestamp");
+        assert_eq!(spec.zone, SourceZone::Utc);
+    }
+
+    #[test]
+    fn test_parse_spec_with_offset() {
+        let spec = DatetimeSpec::parse("Timestamp:+05:30").unwrap();
+        assert_eq!(spec.column, "Timestamp");
+        assert_eq!(
+            spec.zone,
+            SourceZone::Fixed(FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap())
+        );
+
+        assert!(DatetimeSpec::parse("Timestamp:mars").is_err());
+    }
+
+    #[test]
+    fn test_normalize_datetimes_to_utc() {
+        let header = vec!["Region".to_string(), "Timestamp".to_string()];
+        let mut rows = vec![
+            vec!["east".to_string(), "2024-03-01 09:30:00".to_string()],
+            vec!["west".to_string(), "2024-03-01T06:30:00-08:00".to_string()],
+            vec!["north".to_string(), "not a date".to_string()],
+            vec!["south".to_string(), "".to_string()],
+        ];
+        let specs = vec![DatetimeSpec::parse("Timestamp:-05:00").unwrap()];
+
+        let failures = normalize_datetimes(&header, &mut rows, &specs, None);
+
+        assert_eq!(failures, 1);
+        assert_eq!(rows[0][1], "2024-03-01T14:30:00Z");
+        assert_eq!(rows[1][1], "2024-03-01T14:30:00Z");
+        assert_eq!(rows[2][1], "not a date");
+        assert_eq!(rows[3][1], "");
+    }
+
+    #[test]
+    fn test_normalize_datetimes_custom_format() {
+        let header = vec!["When".to_string()];
+        let mut rows = vec![vec!["01.03.2024 09:30".to_string()]];
+        let specs = vec![DatetimeSpec::parse("When:utc").unwrap()];
+
+        let failures = normalize_datetimes(&header, &mut rows, &specs, Some("%d.%m.%Y %H:%M"));
+
+        assert_eq!(failures, 0);
+        assert_eq!(rows[0][0], "2024-03-01T09:30:00Z");
+    }
+}
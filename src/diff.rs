@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use csv_combine::map_rows_to_header;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Rows of a `--diff` comparison, in the order they appear in their file
+#[derive(Debug, Default, PartialEq)]
+pub struct RowDiff {
+    /// Rows of the new output that the old file does not have
+    pub added: Vec<Vec<String>>,
+    /// Rows of the old file that the new output does not have
+    pub removed: Vec<Vec<String>>,
+}
+
+fn row_hash(row: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    row.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Reads a previous output and maps its rows to `header` by column name, so
+/// reordered columns still compare equal. Columns it lacks compare as empty.
+pub fn read_previous(path: &Path, header: &[String]) -> Result<Vec<Vec<String>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .with_context(|| format!("Failed to read --diff file {}", path.display()))?;
+    let old_header: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|col| col.trim().to_string())
+        .collect();
+    let rows = reader
+        .records()
+        .map(|record| Ok(record?.iter().map(String::from).collect()))
+        .collect::<Result<Vec<Vec<String>>>>()?;
+    Ok(map_rows_to_header(&old_header, header, &rows))
+}
+
+/// Compares two row sets by row hash. Repeated rows count separately, so a row
+/// written twice in `new` but once in `old` is added once.
+pub fn diff_rows(old: &[Vec<String>], new: &[Vec<String>]) -> RowDiff {
+    let mut counts: HashMap<u64, isize> = HashMap::new();
+    for row in old {
+        *counts.entry(row_hash(row)).or_insert(0) += 1;
+    }
+
+    let mut diff = RowDiff::default();
+    for row in new {
+        let count = counts.entry(row_hash(row)).or_insert(0);
+        if *count > 0 {
+            *count -= 1;
+        } else {
+            diff.added.push(row.clone());
+        }
+    }
+
+    // Whatever `new` did not use up was removed, keeping the old file's order
+    for row in old {
+        let count = counts.entry(row_hash(row)).or_insert(0);
+        if *count > 0 {
+            *count -= 1;
+            diff.removed.push(row.clone());
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn rows(values: &[&[&str]]) -> Vec<Vec<String>> {
+        values
+            .iter()
+            .map(|row| row.iter().map(|s| s.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_rows() {
+        let old = rows(&[&["Alice", "1"], &["Bob", "2"], &["Bob", "2"]]);
+        let new = rows(&[&["Bob", "2"], &["Carol", "3"], &["Alice", "1"]]);
+
+        assert_eq!(
+            diff_rows(&old, &new),
+            RowDiff {
+                added: rows(&[&["Carol", "3"]]),
+                removed: rows(&[&["Bob", "2"]]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_previous_maps_columns_by_name() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("old.csv");
+        fs::write(&path, "id,name\n1,Alice\n").unwrap();
+
+        let header = vec!["name".to_string(), "id".to_string(), "city".to_string()];
+        assert_eq!(
+            read_previous(&path, &header).unwrap(),
+            rows(&[&["Alice", "1", ""]])
+        );
+    }
+}
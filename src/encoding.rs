@@ -0,0 +1,135 @@
+//! A writer transcoding UTF-8 output to the `--output-encoding` character set.
+
+use encoding_rs::{Encoder, EncoderResult, Encoding};
+use std::io::{self, Write};
+
+/// Parses an `--output-encoding` label such as `windows-1252` or `latin1`
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    let encoding = Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| format!("unknown encoding '{}'", label))?;
+    // UTF-16 and "replacement" decode only, their encoders write UTF-8
+    if encoding.output_encoding() != encoding {
+        return Err(format!("{} is not supported for output", encoding.name()));
+    }
+    Ok(encoding)
+}
+
+/// Transcodes the UTF-8 written to it into `encoding` before passing it on.
+/// Characters the encoding cannot represent are an error, or `?` when `lossy`.
+pub struct EncodingWriter<W: Write> {
+    inner: W,
+    encoding: Option<&'static Encoding>,
+    encoder: Option<Encoder>,
+    lossy: bool,
+    /// Bytes of a character split across two writes
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    /// Wraps `inner`, passing bytes through unchanged when `encoding` is `None`
+    pub fn new(inner: W, encoding: Option<&'static Encoding>, lossy: bool) -> Self {
+        Self {
+            inner,
+            encoding,
+            encoder: encoding.map(Encoding::new_encoder),
+            lossy,
+            pending: Vec::new(),
+        }
+    }
+
+    fn encode(&mut self, mut text: &str, last: bool) -> io::Result<()> {
+        let Some(encoder) = self.encoder.as_mut() else {
+            return self.inner.write_all(text.as_bytes());
+        };
+
+        let mut output = vec![0; 4096];
+        loop {
+            let (result, read, written) =
+                encoder.encode_from_utf8_without_replacement(text, &mut output, last);
+            self.inner.write_all(&output[..written])?;
+            text = &text[read..];
+            match result {
+                EncoderResult::InputEmpty => return Ok(()),
+                EncoderResult::OutputFull => {}
+                EncoderResult::Unmappable(_) if self.lossy => self.inner.write_all(b"?")?,
+                EncoderResult::Unmappable(c) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "'{}' cannot be written as {} (use --output-encoding-lossy to replace it)",
+                            c,
+                            encoder.encoding().name()
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.encoder.is_none() {
+            return self.inner.write(buf);
+        }
+
+        self.pending.extend_from_slice(buf);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            // An incomplete character at the end waits for the next write
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        };
+        let pending = std::mem::take(&mut self.pending);
+        let (text, rest) = pending.split_at(valid);
+        self.pending = rest.to_vec();
+        // Checked above
+        self.encode(std::str::from_utf8(text).unwrap_or_default(), false)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(encoding) = self.encoding {
+            // Stateful encodings such as ISO-2022-JP return to their initial state
+            self.encode("", true)?;
+            self.encoder = Some(encoding.new_encoder());
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcodes_split_characters() {
+        let encoding = parse_encoding("windows-1252").unwrap();
+        let mut writer = EncodingWriter::new(Vec::new(), Some(encoding), false);
+
+        let bytes = "café,€5\n".as_bytes();
+        // Split inside the two-byte "é"
+        writer.write_all(&bytes[..4]).unwrap();
+        writer.write_all(&bytes[4..]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.inner, b"caf\xe9,\x805\n");
+    }
+
+    #[test]
+    fn test_unmappable_characters() {
+        let encoding = parse_encoding("latin1").unwrap();
+
+        let mut strict = EncodingWriter::new(Vec::new(), Some(encoding), false);
+        let err = strict.write_all("日本".as_bytes()).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("cannot be written as windows-1252")
+        );
+
+        let mut lossy = EncodingWriter::new(Vec::new(), Some(encoding), true);
+        lossy.write_all("a日b".as_bytes()).unwrap();
+        assert_eq!(lossy.inner, b"a?b");
+
+        assert!(parse_encoding("utf-16le").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use arrow::ipc::reader::{FileReader, StreamReader};
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use std::fs::File;
+use std::path::Path;
+
+/// Reads an Arrow IPC file (`.arrow`, Feather v2 `.feather`) or stream into rows of
+/// strings, header first. Nulls become empty strings, other values use Arrow's display format.
+pub fn read_arrow_file(path: &Path) -> Result<Vec<Vec<String>>> {
+    let (header, batches) = match FileReader::try_new(File::open(path)?, None) {
+        Ok(reader) => (field_names(&reader.schema()), collect(reader)?),
+        // `.arrow` is also used for the streaming format, which has no footer
+        Err(_) => {
+            let reader = StreamReader::try_new(File::open(path)?, None)?;
+            (field_names(&reader.schema()), collect(reader)?)
+        }
+    };
+
+    let options = FormatOptions::default().with_null("");
+    let mut data = vec![header];
+    for batch in &batches {
+        let formatters = batch
+            .columns()
+            .iter()
+            .map(|column| ArrayFormatter::try_new(column.as_ref(), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in 0..batch.num_rows() {
+            data.push(
+                formatters
+                    .iter()
+                    .map(|formatter| formatter.value(row).to_string())
+                    .collect(),
+            );
+        }
+    }
+    Ok(data)
+}
+
+fn field_names(schema: &arrow::datatypes::Schema) -> Vec<String> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .collect()
+}
+
+fn collect(
+    reader: impl Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+) -> Result<Vec<RecordBatch>> {
+    Ok(reader.collect::<Result<Vec<_>, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
+    use arrow::ipc::writer::FileWriter;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_read_arrow_file() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("data.feather");
+
+        let batch = RecordBatch::try_from_iter([
+            ("id", Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef),
+            (
+                "name",
+                Arc::new(StringArray::from(vec![Some("Alice"), None])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        let mut writer =
+            FileWriter::try_new(File::create(&path).unwrap(), &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            read_arrow_file(&path).unwrap(),
+            vec![vec!["id", "name"], vec!["1", "Alice"], vec!["2", ""]]
+        );
+    }
+}
@@ -0,0 +1,313 @@
+/// Comparison operators of a `--where` expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// One side of a comparison
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// A column of the row, a bare word or a `"quoted name"`
+    Column(String),
+    /// A `'quoted string'` or number
+    Literal(String),
+}
+
+/// A parsed `--where` expression, e.g. `Age > 18 AND Status == 'Active'`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Compare(Operand, CompareOp, Operand),
+    Not(Box<Predicate>),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Text(String),
+    Op(CompareOp),
+    Open,
+    Close,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        match c {
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        // A doubled quote stands for itself
+                        Some(q) if q == c && chars.peek() == Some(&c) => {
+                            chars.next();
+                            text.push(c);
+                        }
+                        Some(q) if q == c => break,
+                        Some(other) => text.push(other),
+                        None => return Err(format!("unterminated {} in '{}'", c, expr)),
+                    }
+                }
+                tokens.push(if c == '\'' {
+                    Token::Text(text)
+                } else {
+                    Token::Quoted(text)
+                });
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let eq = chars.next_if_eq(&'=').is_some();
+                let op = match (c, eq) {
+                    ('=', _) => CompareOp::Eq,
+                    ('!', true) => CompareOp::Ne,
+                    ('<', false) if chars.next_if_eq(&'>').is_some() => CompareOp::Ne,
+                    ('<', eq) => {
+                        if eq {
+                            CompareOp::Le
+                        } else {
+                            CompareOp::Lt
+                        }
+                    }
+                    ('>', eq) => {
+                        if eq {
+                            CompareOp::Ge
+                        } else {
+                            CompareOp::Gt
+                        }
+                    }
+                    _ => return Err(format!("expected != in '{}'", expr)),
+                };
+                tokens.push(Token::Op(op));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()'\"=!<>".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over `or := and (OR and)*`, `and := not (AND not)*`,
+/// `not := NOT not | ( or ) | operand op operand`
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    expr: &'a str,
+}
+
+impl Parser<'_> {
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Predicate, String> {
+        let mut left = self.and()?;
+        while self.keyword("or") {
+            left = Predicate::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Predicate, String> {
+        let mut left = self.not()?;
+        while self.keyword("and") {
+            left = Predicate::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Predicate, String> {
+        if self.keyword("not") {
+            return Ok(Predicate::Not(Box::new(self.not()?)));
+        }
+        if self.tokens.get(self.pos) == Some(&Token::Open) {
+            self.pos += 1;
+            let inner = self.or()?;
+            if self.tokens.get(self.pos) != Some(&Token::Close) {
+                return Err(format!("missing ) in '{}'", self.expr));
+            }
+            self.pos += 1;
+            return Ok(inner);
+        }
+
+        let left = self.operand()?;
+        let Some(Token::Op(op)) = self.tokens.get(self.pos).cloned() else {
+            return Err(format!(
+                "expected a comparison after {:?} in '{}'",
+                left, self.expr
+            ));
+        };
+        self.pos += 1;
+        Ok(Predicate::Compare(left, op, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let operand = match self.tokens.get(self.pos) {
+            Some(Token::Word(word)) if word.parse::<f64>().is_ok() => {
+                Operand::Literal(word.clone())
+            }
+            Some(Token::Word(word)) => Operand::Column(word.clone()),
+            Some(Token::Quoted(name)) => Operand::Column(name.clone()),
+            Some(Token::Text(text)) => Operand::Literal(text.clone()),
+            _ => return Err(format!("expected a column or value in '{}'", self.expr)),
+        };
+        self.pos += 1;
+        Ok(operand)
+    }
+}
+
+impl Predicate {
+    /// Parses a `--where` expression: comparisons (`==`, `!=`, `<`, `<=`, `>`,
+    /// `>=`) of columns and values joined by AND, OR and NOT, with parentheses.
+    /// Bare words and `"quoted names"` are columns; numbers and `'quoted text'`
+    /// are values.
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(expr)?,
+            pos: 0,
+            expr,
+        };
+        let predicate = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(format!(
+                "unexpected {:?} in '{}'",
+                parser.tokens[parser.pos], expr
+            ));
+        }
+        Ok(predicate)
+    }
+
+    /// Column names the expression reads
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns = Vec::new();
+        self.visit_columns(&mut columns);
+        columns
+    }
+
+    fn visit_columns<'a>(&'a self, columns: &mut Vec<&'a str>) {
+        match self {
+            Predicate::Compare(left, _, right) => {
+                for operand in [left, right] {
+                    if let Operand::Column(name) = operand {
+                        columns.push(name);
+                    }
+                }
+            }
+            Predicate::Not(inner) => inner.visit_columns(columns),
+            Predicate::And(left, right) | Predicate::Or(left, right) => {
+                left.visit_columns(columns);
+                right.visit_columns(columns);
+            }
+        }
+    }
+
+    /// Evaluates the expression, reading columns through `column`. Operands that
+    /// both parse as numbers compare numerically, others as text.
+    pub fn matches<'r>(&self, column: &impl Fn(&str) -> &'r str) -> bool {
+        match self {
+            Predicate::Compare(left, op, right) => {
+                compare(value(left, column), *op, value(right, column))
+            }
+            Predicate::Not(inner) => !inner.matches(column),
+            Predicate::And(left, right) => left.matches(column) && right.matches(column),
+            Predicate::Or(left, right) => left.matches(column) || right.matches(column),
+        }
+    }
+}
+
+fn value<'a, 'r: 'a>(operand: &'a Operand, column: &impl Fn(&str) -> &'r str) -> &'a str {
+    match operand {
+        Operand::Column(name) => column(name).trim(),
+        Operand::Literal(text) => text,
+    }
+}
+
+fn compare(left: &str, op: CompareOp, right: &str) -> bool {
+    let ordering = match (left.parse::<f64>(), right.parse::<f64>()) {
+        (Ok(left), Ok(right)) => left.partial_cmp(&right),
+        _ => Some(left.cmp(right)),
+    };
+    // NaN is unequal to everything
+    let Some(ordering) = ordering else {
+        return op == CompareOp::Ne;
+    };
+    match op {
+        CompareOp::Eq => ordering.is_eq(),
+        CompareOp::Ne => ordering.is_ne(),
+        CompareOp::Lt => ordering.is_lt(),
+        CompareOp::Le => ordering.is_le(),
+        CompareOp::Gt => ordering.is_gt(),
+        CompareOp::Ge => ordering.is_ge(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(expr: &str, row: &[(&'static str, &'static str)]) -> bool {
+        Predicate::parse(expr).unwrap().matches(&|name| {
+            row.iter()
+                .find(|(col, _)| *col == name)
+                .map_or("", |(_, value)| value)
+        })
+    }
+
+    #[test]
+    fn test_predicate_matches() {
+        let row = [("Age", "30"), ("Status", "Active"), ("Zip Code", "02134")];
+        assert!(matches("Age > 18 AND Status == 'Active'", &row));
+        assert!(!matches("Age > 100 OR Status != 'Active'", &row));
+        // Numbers compare numerically, so 30 > 9 even though "30" < "9"
+        assert!(matches("Age > 9", &row));
+        assert!(matches("\"Zip Code\" == 2134", &row));
+        assert!(matches("NOT (Age < 18 or Status = 'Closed')", &row));
+        // A missing column reads as empty
+        assert!(matches("Region == ''", &row));
+    }
+
+    #[test]
+    fn test_predicate_parse_errors() {
+        assert!(Predicate::parse("Age >").is_err());
+        assert!(Predicate::parse("Age > 18 Status").is_err());
+        assert!(Predicate::parse("(Age > 18").is_err());
+        assert!(Predicate::parse("Name == 'Bob").is_err());
+        assert_eq!(
+            Predicate::parse("a == 1 and (b < 'x' or \"c d\" >= e)")
+                .unwrap()
+                .columns(),
+            vec!["a", "b", "c d", "e"]
+        );
+    }
+}
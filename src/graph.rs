@@ -0,0 +1,82 @@
+//! Graphviz rendering of header compatibility.
+
+use crate::headers::{
+    MergeStrategy, SimilarityMetric, group_files_by, shared_column_count, similarity_score,
+};
+use std::fmt::Write;
+
+/// Renders a DOT graph where nodes are files and edges connect compatible pairs,
+/// labelled with their `metric` score. Each group, formed as `strategy` says, is
+/// drawn as its own cluster. Pairs sharing fewer than `min_shared` columns get
+/// no edge.
+pub fn compatibility_dot(
+    names: &[String],
+    headers: &[Vec<String>],
+    threshold: f64,
+    min_shared: usize,
+    strategy: MergeStrategy,
+    metric: SimilarityMetric,
+) -> String {
+    let mut dot = String::new();
+    let _ = writeln!(dot, "graph compatibility {{");
+    let _ = writeln!(dot, "    node [shape=box];");
+
+    let groups = group_files_by(headers, threshold, min_shared, strategy, metric);
+    for (group_idx, group) in groups.iter().enumerate() {
+        let _ = writeln!(dot, "    subgraph cluster_{} {{", group_idx);
+        let _ = writeln!(dot, "        label=\"group {}\";", group_idx + 1);
+        for &file_idx in group {
+            let _ = writeln!(
+                dot,
+                "        n{} [label=\"{}\"];",
+                file_idx,
+                escape(&names[file_idx])
+            );
+        }
+        let _ = writeln!(dot, "    }}");
+    }
+
+    for i in 0..headers.len() {
+        for j in (i + 1)..headers.len() {
+            let score = similarity_score(&headers[i], &headers[j], metric);
+            if score >= threshold && shared_column_count(&headers[i], &headers[j]) >= min_shared {
+                let _ = writeln!(dot, "    n{} -- n{} [label=\"{:.2}\"];", i, j, score);
+            }
+        }
+    }
+
+    let _ = writeln!(dot, "}}");
+    dot
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compatibility_dot() {
+        let names = vec![
+            "a.csv".to_string(),
+            "b.csv".to_string(),
+            "c \"x\".csv".to_string(),
+        ];
+        let headers = vec![
+            vec!["Name".to_string(), "Age".to_string()],
+            vec!["Name".to_string(), "Age".to_string(), "City".to_string()],
+            vec!["Product".to_string()],
+        ];
+
+        let dot = compatibility_dot(&names, &headers, 0.5, 0, MergeStrategy::Representative, SimilarityMetric::Jaccard);
+
+        assert!(dot.starts_with("graph compatibility {"));
+        assert!(dot.contains("n0 -- n1 [label=\"0.67\"];"));
+        assert!(!dot.contains("n0 -- n2"));
+        assert!(dot.contains("n2 [label=\"c \\\"x\\\".csv\"];"));
+        assert!(dot.contains("subgraph cluster_1"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+}
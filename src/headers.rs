@@ -0,0 +1,682 @@
+//! Header compatibility, grouping and merging.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// Overlap at which two headers are considered compatible by default
+pub const DEFAULT_OVERLAP_THRESHOLD: f64 = 0.5;
+
+/// Returns a hex hash identifying a header, used to name output files
+pub fn generate_header_hash(header: &[String]) -> String {
+    let mut hasher = DefaultHasher::new();
+    header.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+/// Returns the share of columns two headers have in common (intersection over union)
+pub fn header_overlap_score(header1: &[String], header2: &[String]) -> f64 {
+    let set1: HashSet<&String> = header1.iter().collect();
+    let set2: HashSet<&String> = header2.iter().collect();
+
+    let intersection: HashSet<_> = set1.intersection(&set2).collect();
+    let union: HashSet<_> = set1.union(&set2).collect();
+
+    if union.is_empty() {
+        return 0.0;
+    }
+
+    (intersection.len() as f64) / (union.len() as f64)
+}
+
+/// How [`similarity_score`] compares two headers' column sets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SimilarityMetric {
+    /// Shared columns over all columns of either header. A small header that is a
+    /// subset of a large one scores low.
+    #[default]
+    Jaccard,
+    /// Shared columns over the columns of the smaller header, so any subset scores 1
+    Overlap,
+    /// Twice the shared columns over the two header sizes, between the other two
+    Dice,
+}
+
+impl FromStr for SimilarityMetric {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "jaccard" => Ok(SimilarityMetric::Jaccard),
+            "overlap" => Ok(SimilarityMetric::Overlap),
+            "dice" => Ok(SimilarityMetric::Dice),
+            _ => Err(format!(
+                "unknown similarity metric '{}' (expected jaccard, overlap or dice)",
+                value
+            )),
+        }
+    }
+}
+
+/// Returns the similarity of two headers' column sets under `metric`, from 0 to 1
+pub fn similarity_score(header1: &[String], header2: &[String], metric: SimilarityMetric) -> f64 {
+    if metric == SimilarityMetric::Jaccard {
+        return header_overlap_score(header1, header2);
+    }
+
+    let set1: HashSet<&String> = header1.iter().collect();
+    let set2: HashSet<&String> = header2.iter().collect();
+    let shared = set1.intersection(&set2).count() as f64;
+    let denominator = match metric {
+        SimilarityMetric::Overlap => set1.len().min(set2.len()) as f64,
+        _ => (set1.len() + set2.len()) as f64 / 2.0,
+    };
+    if denominator == 0.0 {
+        return 0.0;
+    }
+    shared / denominator
+}
+
+/// Returns how many columns two headers have in common
+pub fn shared_column_count(header1: &[String], header2: &[String]) -> usize {
+    let set1: HashSet<&String> = header1.iter().collect();
+    header2.iter().collect::<HashSet<_>>().intersection(&set1).count()
+}
+
+/// How [`group_files_by`] decides that two headers belong together
+#[derive(Debug, Clone, Copy)]
+struct Compatibility {
+    threshold: f64,
+    min_shared: usize,
+    metric: SimilarityMetric,
+}
+
+impl Compatibility {
+    /// Whether two headers score at least `threshold` and share at least
+    /// `min_shared` columns
+    fn check(self, header1: &[String], header2: &[String]) -> bool {
+        similarity_score(header1, header2, self.metric) >= self.threshold
+            && shared_column_count(header1, header2) >= self.min_shared
+    }
+}
+
+/// Returns true when the headers overlap by at least [`DEFAULT_OVERLAP_THRESHOLD`]
+pub fn headers_are_compatible(header1: &[String], header2: &[String]) -> bool {
+    // Files are compatible if they have >= 50% overlap
+    header_overlap_score(header1, header2) >= DEFAULT_OVERLAP_THRESHOLD
+}
+
+/// What a header is compared against when deciding whether it joins a group
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// The group's first member
+    Representative,
+    /// Every member; overlapping any one of them is enough
+    AnyMember,
+    /// The merged header of the group so far: the union of its members' columns,
+    /// grown as each one joins
+    #[default]
+    Centroid,
+}
+
+impl FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "representative" => Ok(MergeStrategy::Representative),
+            "any-member" => Ok(MergeStrategy::AnyMember),
+            "centroid" => Ok(MergeStrategy::Centroid),
+            _ => Err(format!(
+                "unknown merge strategy '{}' (expected representative, any-member or centroid)",
+                value
+            )),
+        }
+    }
+}
+
+/// How [`merge_headers_by`] orders the merged columns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColumnOrder {
+    /// The order columns are first seen in
+    #[default]
+    FirstSeen,
+    /// Columns in the most headers first, ties in first-seen order
+    Frequency,
+}
+
+impl FromStr for ColumnOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "first-seen" => Ok(ColumnOrder::FirstSeen),
+            "frequency" => Ok(ColumnOrder::Frequency),
+            _ => Err(format!(
+                "unknown column order '{}' (expected first-seen or frequency)",
+                value
+            )),
+        }
+    }
+}
+
+/// Groups headers by compatibility and returns the clusters as indices into `headers`.
+///
+/// Each header joins the first existing group whose merged header so far (the
+/// union of its members' columns) overlaps it by at least `threshold`, otherwise
+/// it starts a new group. Group and member order follow the input order. Use
+/// [`group_files_by`] with [`MergeStrategy::Representative`] to compare against
+/// each group's first member instead.
+pub fn group_files(headers: &[Vec<String>], threshold: f64) -> Vec<Vec<usize>> {
+    group_files_by(headers, threshold, 0, MergeStrategy::default(), SimilarityMetric::Jaccard)
+}
+
+/// Like [`group_files`], comparing each header against existing groups as
+/// `strategy` says and scoring pairs with `metric`. A header must also share at
+/// least `min_shared` columns with what it is compared against, so two tiny
+/// files do not group on one column.
+pub fn group_files_by(
+    headers: &[Vec<String>],
+    threshold: f64,
+    min_shared: usize,
+    strategy: MergeStrategy,
+    metric: SimilarityMetric,
+) -> Vec<Vec<usize>> {
+    let compatibility = Compatibility {
+        threshold,
+        min_shared,
+        metric,
+    };
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    // Running column union of each group, only kept for `Centroid`, with its
+    // columns as a set so growing it does not rescan the union
+    let mut centroids: Vec<(Vec<String>, HashSet<String>)> = Vec::new();
+
+    for i in 0..headers.len() {
+        let header = &headers[i];
+
+        // Try to add to an existing compatible group
+        let compatible = groups.iter().enumerate().position(|(group_idx, group)| {
+            match strategy {
+                MergeStrategy::Representative => compatibility.check(header, &headers[group[0]]),
+                MergeStrategy::AnyMember => group
+                    .iter()
+                    .any(|&member| compatibility.check(header, &headers[member])),
+                MergeStrategy::Centroid => compatibility.check(header, &centroids[group_idx].0),
+            }
+        });
+
+        match compatible {
+            Some(group_idx) => {
+                groups[group_idx].push(i);
+                if strategy == MergeStrategy::Centroid {
+                    let (columns, seen) = &mut centroids[group_idx];
+                    for col in header {
+                        if seen.insert(col.clone()) {
+                            columns.push(col.clone());
+                        }
+                    }
+                }
+            }
+            // Create a new group if not compatible with any existing group
+            None => {
+                groups.push(vec![i]);
+                if strategy == MergeStrategy::Centroid {
+                    centroids.push((header.clone(), header.iter().cloned().collect()));
+                }
+            }
+        }
+    }
+
+    groups
+}
+
+/// Pairwise overlap scores of a set of headers and the threshold that separates them best
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdSuggestion {
+    /// Overlap score of every pair of headers, ascending
+    pub scores: Vec<f64>,
+    /// Lowest and highest score of the largest gap between consecutive scores
+    pub gap: (f64, f64),
+    /// Midpoint of `gap`: pairs above it are grouped, pairs below it are not
+    pub threshold: f64,
+}
+
+/// Suggests a grouping threshold from the largest gap in the pairwise `metric`
+/// scores, which is where "natural" clusters separate.
+///
+/// Returns `None` when there are fewer than two distinct scores to split.
+pub fn suggest_threshold(headers: &[Vec<String>], metric: SimilarityMetric) -> Option<ThresholdSuggestion> {
+    let mut scores = Vec::new();
+    for i in 0..headers.len() {
+        for j in i + 1..headers.len() {
+            scores.push(similarity_score(&headers[i], &headers[j], metric));
+        }
+    }
+    scores.sort_by(f64::total_cmp);
+
+    let gap = scores
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .filter(|(low, high)| high > low)
+        .max_by(|a, b| (a.1 - a.0).total_cmp(&(b.1 - b.0)))?;
+
+    Some(ThresholdSuggestion {
+        threshold: (gap.0 + gap.1) / 2.0,
+        scores,
+        gap,
+    })
+}
+
+/// Merges headers into a superset of their columns, in first-seen order
+pub fn merge_headers(headers: &[Vec<String>]) -> Vec<String> {
+    let mut merged = Vec::new();
+    let mut seen = HashSet::new();
+
+    // Add all unique headers while preserving order
+    // Start with the first header to maintain column order preference
+    for header_set in headers {
+        for col in header_set {
+            if !seen.contains(col) {
+                seen.insert(col.clone());
+                merged.push(col.clone());
+            }
+        }
+    }
+
+    merged
+}
+
+/// Like [`merge_headers`], ordering the merged columns as `order` says
+pub fn merge_headers_by(headers: &[Vec<String>], order: ColumnOrder) -> Vec<String> {
+    let mut merged = merge_headers(headers);
+    if order == ColumnOrder::Frequency {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for header in headers {
+            for col in header.iter().collect::<HashSet<_>>() {
+                *counts.entry(col.as_str()).or_insert(0) += 1;
+            }
+        }
+        // Stable, so ties keep their first-seen order
+        let counts: Vec<usize> = merged.iter().map(|col| counts[col.as_str()]).collect();
+        let mut ranked: Vec<(usize, String)> = counts.into_iter().zip(merged).collect();
+        ranked.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+        merged = ranked.into_iter().map(|(_, col)| col).collect();
+    }
+    merged
+}
+
+/// Maps rows from `old_header` to `new_header`, leaving missing columns empty
+pub fn map_rows_to_header(
+    old_header: &[String],
+    new_header: &[String],
+    rows: &[Vec<String>],
+) -> Vec<Vec<String>> {
+    // Create a mapping from old column names to their indices
+    let old_col_map: HashMap<&String, usize> = old_header
+        .iter()
+        .enumerate()
+        .map(|(idx, col)| (col, idx))
+        .collect();
+
+    let mut mapped_rows = Vec::new();
+
+    for row in rows {
+        let mut new_row = vec![String::new(); new_header.len()];
+
+        for (new_idx, col_name) in new_header.iter().enumerate() {
+            if let Some(&old_idx) = old_col_map.get(col_name)
+                && old_idx < row.len()
+            {
+                new_row[new_idx] = row[old_idx].clone();
+            }
+            // If column doesn't exist in old header, leave it as empty string
+        }
+
+        mapped_rows.push(new_row);
+    }
+
+    mapped_rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_header_hash() {
+        let header1 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+        let header2 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+        let header3 = vec!["Name".to_string(), "Age".to_string()];
+
+        let hash1 = generate_header_hash(&header1);
+        let hash2 = generate_header_hash(&header2);
+        let hash3 = generate_header_hash(&header3);
+
+        // Same headers should produce same hash
+        assert_eq!(hash1, hash2);
+        // Different headers should produce different hash
+        assert_ne!(hash1, hash3);
+        // Hash should be a hex string
+        assert!(hash1.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_headers_are_compatible_exact_match() {
+        let header1 = vec!["Name".to_string(), "Age".to_string()];
+        let header2 = vec!["Name".to_string(), "Age".to_string()];
+
+        assert!(headers_are_compatible(&header1, &header2));
+    }
+
+    #[test]
+    fn test_headers_are_compatible_superset() {
+        let header1 = vec!["Name".to_string(), "Age".to_string()];
+        let header2 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+
+        // 2 common out of 3 total = 66.7% overlap - should be compatible
+        assert!(headers_are_compatible(&header1, &header2));
+    }
+
+    #[test]
+    fn test_headers_are_compatible_partial_overlap() {
+        let header1 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+        let header2 = vec!["Name".to_string(), "Age".to_string(), "Country".to_string()];
+
+        // 2 common out of 4 total = 50% overlap - should be compatible
+        assert!(headers_are_compatible(&header1, &header2));
+    }
+
+    #[test]
+    fn test_headers_are_not_compatible() {
+        let header1 = vec!["Name".to_string(), "Age".to_string()];
+        let header2 = vec!["Product".to_string(), "Price".to_string()];
+
+        // 0 common out of 4 total = 0% overlap - not compatible
+        assert!(!headers_are_compatible(&header1, &header2));
+    }
+
+    #[test]
+    fn test_headers_are_not_compatible_low_overlap() {
+        let header1 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+        let header2 = vec!["Name".to_string(), "Product".to_string(), "Price".to_string()];
+
+        // 1 common out of 5 total = 20% overlap - not compatible
+        assert!(!headers_are_compatible(&header1, &header2));
+    }
+
+    #[test]
+    fn test_merge_headers_identical() {
+        let headers = vec![
+            vec!["Name".to_string(), "Age".to_string()],
+            vec!["Name".to_string(), "Age".to_string()],
+        ];
+
+        let merged = merge_headers(&headers);
+        assert_eq!(merged, vec!["Name", "Age"]);
+    }
+
+    #[test]
+    fn test_merge_headers_superset() {
+        let headers = vec![
+            vec!["Name".to_string(), "Age".to_string()],
+            vec!["Name".to_string(), "Age".to_string(), "City".to_string()],
+        ];
+
+        let merged = merge_headers(&headers);
+        assert_eq!(merged, vec!["Name", "Age", "City"]);
+    }
+
+    #[test]
+    fn test_merge_headers_different_order() {
+        let headers = vec![
+            vec!["Name".to_string(), "Age".to_string(), "City".to_string()],
+            vec!["Age".to_string(), "Name".to_string(), "Country".to_string()],
+        ];
+
+        let merged = merge_headers(&headers);
+        // Should preserve order from first header, then add new columns
+        assert_eq!(merged, vec!["Name", "Age", "City", "Country"]);
+    }
+
+    #[test]
+    fn test_merge_headers_by_frequency() {
+        let headers = vec![
+            vec!["Notes".to_string(), "Name".to_string()],
+            vec!["Name".to_string(), "Age".to_string(), "Fax".to_string()],
+            vec!["Age".to_string(), "Name".to_string()],
+        ];
+
+        assert_eq!(
+            merge_headers_by(&headers, ColumnOrder::Frequency),
+            vec!["Name", "Age", "Notes", "Fax"]
+        );
+        assert_eq!(merge_headers_by(&headers, ColumnOrder::FirstSeen), merge_headers(&headers));
+    }
+
+    #[test]
+    fn test_map_rows_to_header_same_headers() {
+        let old_header = vec!["Name".to_string(), "Age".to_string()];
+        let new_header = vec!["Name".to_string(), "Age".to_string()];
+        let rows = vec![
+            vec!["Alice".to_string(), "30".to_string()],
+            vec!["Bob".to_string(), "25".to_string()],
+        ];
+
+        let mapped = map_rows_to_header(&old_header, &new_header, &rows);
+
+        assert_eq!(mapped.len(), 2);
+        assert_eq!(mapped[0], vec!["Alice", "30"]);
+        assert_eq!(mapped[1], vec!["Bob", "25"]);
+    }
+
+    #[test]
+    fn test_map_rows_to_header_with_new_columns() {
+        let old_header = vec!["Name".to_string(), "Age".to_string()];
+        let new_header = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+        let rows = vec![
+            vec!["Alice".to_string(), "30".to_string()],
+            vec!["Bob".to_string(), "25".to_string()],
+        ];
+
+        let mapped = map_rows_to_header(&old_header, &new_header, &rows);
+
+        assert_eq!(mapped.len(), 2);
+        assert_eq!(mapped[0], vec!["Alice", "30", ""]);
+        assert_eq!(mapped[1], vec!["Bob", "25", ""]);
+    }
+
+    #[test]
+    fn test_map_rows_to_header_reordered_columns() {
+        let old_header = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+        let new_header = vec!["City".to_string(), "Name".to_string(), "Age".to_string()];
+        let rows = vec![vec!["Alice".to_string(), "30".to_string(), "NYC".to_string()]];
+
+        let mapped = map_rows_to_header(&old_header, &new_header, &rows);
+
+        assert_eq!(mapped.len(), 1);
+        assert_eq!(mapped[0], vec!["NYC", "Alice", "30"]);
+    }
+
+    #[test]
+    fn test_map_rows_to_header_mixed_columns() {
+        let old_header = vec!["Name".to_string(), "Age".to_string()];
+        let new_header = vec![
+            "Name".to_string(),
+            "Age".to_string(),
+            "City".to_string(),
+            "Country".to_string(),
+        ];
+        let rows = vec![vec!["Alice".to_string(), "30".to_string()]];
+
+        let mapped = map_rows_to_header(&old_header, &new_header, &rows);
+
+        assert_eq!(mapped.len(), 1);
+        assert_eq!(mapped[0], vec!["Alice", "30", "", ""]);
+    }
+
+    #[test]
+    fn test_header_overlap_score() {
+        let header1 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+        let header2 = vec!["Name".to_string(), "Age".to_string(), "Country".to_string()];
+
+        assert_eq!(header_overlap_score(&header1, &header2), 0.5);
+        assert_eq!(header_overlap_score(&header1, &header1), 1.0);
+        assert_eq!(header_overlap_score(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn test_similarity_metrics() {
+        let small = vec!["Name".to_string(), "Age".to_string()];
+        let large: Vec<String> = ["Name", "Age", "City", "Country", "Phone", "Email"]
+            .iter()
+            .map(|col| col.to_string())
+            .collect();
+
+        assert_eq!(similarity_score(&small, &large, SimilarityMetric::Jaccard), 2.0 / 6.0);
+        assert_eq!(similarity_score(&small, &large, SimilarityMetric::Overlap), 1.0);
+        assert_eq!(similarity_score(&small, &large, SimilarityMetric::Dice), 0.5);
+        assert_eq!(similarity_score(&[], &large, SimilarityMetric::Overlap), 0.0);
+
+        // The subset only groups under the overlap coefficient
+        let headers = vec![large, small];
+        let groups = |metric| group_files_by(&headers, 0.6, 0, MergeStrategy::Representative, metric);
+        assert_eq!(groups(SimilarityMetric::Jaccard), vec![vec![0], vec![1]]);
+        assert_eq!(groups(SimilarityMetric::Dice), vec![vec![0], vec![1]]);
+        assert_eq!(groups(SimilarityMetric::Overlap), vec![vec![0, 1]]);
+    }
+
+    #[test]
+    fn test_group_files() {
+        let headers = vec![
+            vec!["Name".to_string(), "Age".to_string()],
+            vec!["Product".to_string(), "Price".to_string()],
+            vec!["Name".to_string(), "Age".to_string(), "City".to_string()],
+            vec!["Product".to_string(), "Price".to_string(), "SKU".to_string()],
+            vec!["Unrelated".to_string()],
+        ];
+
+        let groups = group_files(&headers, DEFAULT_OVERLAP_THRESHOLD);
+        assert_eq!(groups, vec![vec![0, 2], vec![1, 3], vec![4]]);
+
+        // The last header overlaps the merged Name, Age, City, Email by more than half,
+        // but only a third of the group's first member
+        let headers = vec![
+            vec!["Name".to_string(), "Age".to_string()],
+            vec!["Name".to_string(), "Age".to_string(), "City".to_string(), "Email".to_string()],
+            vec!["Name".to_string(), "City".to_string(), "Email".to_string()],
+        ];
+        assert_eq!(group_files(&headers, DEFAULT_OVERLAP_THRESHOLD), vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn test_group_files_threshold() {
+        let headers = vec![
+            vec!["Name".to_string(), "Age".to_string(), "City".to_string()],
+            vec!["Name".to_string(), "Age".to_string(), "Country".to_string()],
+        ];
+
+        // 50% overlap
+        assert_eq!(group_files(&headers, 0.5), vec![vec![0, 1]]);
+        assert_eq!(group_files(&headers, 0.75), vec![vec![0], vec![1]]);
+        assert!(group_files(&[], 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_group_files_min_shared_columns() {
+        let header = |cols: &[&str]| cols.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let headers = vec![
+            header(&["id", "qty"]),
+            header(&["id", "price"]),
+            header(&["id", "qty", "price"]),
+        ];
+
+        // The two-column files overlap by 50% on id alone
+        assert_eq!(shared_column_count(&headers[0], &headers[1]), 1);
+        assert_eq!(
+            group_files_by(&headers, 1.0 / 3.0, 0, MergeStrategy::Representative, SimilarityMetric::Jaccard),
+            vec![vec![0, 1, 2]]
+        );
+        assert_eq!(
+            group_files_by(&headers, 1.0 / 3.0, 2, MergeStrategy::Representative, SimilarityMetric::Jaccard),
+            vec![vec![0, 2], vec![1]]
+        );
+    }
+
+    #[test]
+    fn test_group_files_by_strategy() {
+        let header = |cols: &[&str]| cols.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        // c overlaps b and the union of a and b by 0.5, but shares nothing with a
+        let headers = vec![
+            header(&["id", "name", "email"]),
+            header(&["id", "name", "email", "phone", "fax", "city"]),
+            header(&["phone", "fax", "city"]),
+        ];
+
+        assert_eq!(
+            group_files_by(&headers, 0.5, 0, MergeStrategy::Representative, SimilarityMetric::Jaccard),
+            vec![vec![0, 1], vec![2]]
+        );
+        assert_eq!(
+            group_files_by(&headers, 0.5, 0, MergeStrategy::AnyMember, SimilarityMetric::Jaccard),
+            vec![vec![0, 1, 2]]
+        );
+        assert_eq!(
+            group_files_by(&headers, 0.5, 0, MergeStrategy::Centroid, SimilarityMetric::Jaccard),
+            vec![vec![0, 1, 2]]
+        );
+
+        // c overlaps b by 0.6, but only 0.5 of the union of a and b
+        let headers = vec![
+            header(&["1", "2", "3", "4"]),
+            header(&["2", "3", "4", "5"]),
+            header(&["3", "4", "5", "6"]),
+        ];
+        assert_eq!(
+            group_files_by(&headers, 0.6, 0, MergeStrategy::AnyMember, SimilarityMetric::Jaccard),
+            vec![vec![0, 1, 2]]
+        );
+        assert_eq!(
+            group_files_by(&headers, 0.6, 0, MergeStrategy::Centroid, SimilarityMetric::Jaccard),
+            vec![vec![0, 1], vec![2]]
+        );
+
+        // The default compares against the merged header, so c joins a group
+        // whose first member it barely overlaps
+        let headers = vec![
+            header(&["id", "name"]),
+            header(&["id", "name", "phone", "city"]),
+            header(&["name", "phone", "city"]),
+        ];
+        assert_eq!(
+            group_files_by(&headers, 0.5, 0, MergeStrategy::Representative, SimilarityMetric::Jaccard),
+            vec![vec![0, 1], vec![2]]
+        );
+        assert_eq!(
+            group_files_by(&headers, 0.5, 0, MergeStrategy::default(), SimilarityMetric::Jaccard),
+            vec![vec![0, 1, 2]]
+        );
+
+        assert_eq!("any-member".parse(), Ok(MergeStrategy::AnyMember));
+        assert!("first".parse::<MergeStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_suggest_threshold() {
+        let header = |cols: &[&str]| cols.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let headers = vec![
+            header(&["id", "name", "email", "phone"]),
+            header(&["id", "name", "email", "fax"]),
+            header(&["sku", "price", "qty", "id"]),
+        ];
+
+        let suggestion = suggest_threshold(&headers, SimilarityMetric::Jaccard).unwrap();
+        assert_eq!(suggestion.scores.len(), 3);
+        assert_eq!(suggestion.gap, (1.0 / 7.0, 0.6));
+        assert!((suggestion.threshold - (1.0 / 7.0 + 0.6) / 2.0).abs() < 1e-9);
+        assert_eq!(group_files(&headers, suggestion.threshold), vec![vec![0, 1], vec![2]]);
+
+        assert!(suggest_threshold(&headers[..2], SimilarityMetric::Jaccard).is_none());
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::collections::HashMap;
+
+/// Which keys appear in the output of `--join-on`
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum JoinType {
+    /// Only keys present in every file
+    Inner,
+    /// Keys from the first file in the group
+    Left,
+    /// Every key from any file
+    #[default]
+    Outer,
+}
+
+/// How repeated keys within a single file are handled by `--join-on`
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum DuplicateKeys {
+    /// Keep the first row for the key
+    #[default]
+    First,
+    /// Keep the last row for the key
+    Last,
+    /// Abort the run
+    Error,
+}
+
+/// What `--prefix-columns-with` puts before each joined file's non-key columns
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColumnPrefix {
+    /// The file's path relative to the input, without extension, `/` replaced by `_`
+    Source,
+    /// The file name without extension
+    Filename,
+}
+
+/// Renames every column of `header` but `key` to `<prefix>_<column>`
+pub fn prefix_columns(header: &[String], key: &str, prefix: &str) -> Vec<String> {
+    header
+        .iter()
+        .map(|col| {
+            if col == key {
+                col.clone()
+            } else {
+                format!("{}_{}", prefix, col)
+            }
+        })
+        .collect()
+}
+
+/// Expands each column of `merged` but `key` into the prefixed columns of the
+/// `headers` (already passed through [`prefix_columns`]) it came from, in order.
+/// A column none of them has, such as a pinned one, is kept as is.
+pub fn prefixed_header(
+    merged: &[String],
+    key: &str,
+    headers: &[(&str, Vec<String>)],
+) -> Vec<String> {
+    let mut expanded = Vec::new();
+    for col in merged {
+        let prefixed: Vec<String> = headers
+            .iter()
+            .map(|(prefix, _)| format!("{}_{}", prefix, col))
+            .filter(|name| col != key && headers.iter().any(|(_, header)| header.contains(name)))
+            .collect();
+        if prefixed.is_empty() {
+            expanded.push(col.clone());
+        } else {
+            expanded.extend(prefixed);
+        }
+    }
+    expanded
+}
+
+/// One joined input: its name (for messages) and rows already mapped to the merged header
+pub struct JoinInput<'a> {
+    pub name: String,
+    pub rows: &'a [Vec<String>],
+}
+
+/// Joins the inputs on `key_idx`, producing one row per key.
+///
+/// When several files provide a value for the same column the first non-empty
+/// value (in group order) wins.
+pub fn join_rows(
+    key_idx: usize,
+    width: usize,
+    inputs: &[JoinInput],
+    join_type: JoinType,
+    duplicates: DuplicateKeys,
+) -> Result<Vec<Vec<String>>> {
+    let mut key_order: Vec<String> = Vec::new();
+    let mut per_file: Vec<HashMap<String, &Vec<String>>> = Vec::new();
+
+    for (file_idx, input) in inputs.iter().enumerate() {
+        let mut rows_by_key: HashMap<String, &Vec<String>> = HashMap::new();
+
+        for row in input.rows {
+            let key = row.get(key_idx).cloned().unwrap_or_default();
+            if rows_by_key.contains_key(&key) {
+                match duplicates {
+                    DuplicateKeys::First => continue,
+                    DuplicateKeys::Last => {}
+                    DuplicateKeys::Error => {
+                        return Err(anyhow::anyhow!(
+                            "Duplicate join key '{}' in {}",
+                            key,
+                            input.name
+                        ));
+                    }
+                }
+            } else if join_type != JoinType::Left || file_idx == 0 {
+                key_order.push(key.clone());
+            }
+            rows_by_key.insert(key, row);
+        }
+
+        per_file.push(rows_by_key);
+    }
+
+    // Keys can be discovered by several files, keep the first occurrence only
+    let mut seen = std::collections::HashSet::new();
+    key_order.retain(|key| seen.insert(key.clone()));
+
+    let mut joined = Vec::new();
+    for key in key_order {
+        if join_type == JoinType::Inner && !per_file.iter().all(|rows| rows.contains_key(&key)) {
+            continue;
+        }
+
+        let mut row = vec![String::new(); width];
+        for rows_by_key in &per_file {
+            if let Some(source) = rows_by_key.get(&key) {
+                for (cell, value) in row.iter_mut().zip(source.iter()) {
+                    if cell.is_empty() {
+                        cell.clone_from(value);
+                    }
+                }
+            }
+        }
+        row[key_idx] = key;
+        joined.push(row);
+    }
+
+    Ok(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(values: &[&[&str]]) -> Vec<Vec<String>> {
+        values
+            .iter()
+            .map(|row| row.iter().map(|v| v.to_string()).collect())
+            .collect()
+    }
+
+    // Merged header: id, name, amount
+    fn inputs() -> (Vec<Vec<String>>, Vec<Vec<String>>) {
+        let customers = rows(&[&["1", "Alice", ""], &["2", "Bob", ""], &["3", "Carol", ""]]);
+        let orders = rows(&[&["1", "", "10"], &["3", "", "30"], &["4", "", "40"]]);
+        (customers, orders)
+    }
+
+    #[test]
+    fn test_join_outer() {
+        let (customers, orders) = inputs();
+        let inputs = [
+            JoinInput {
+                name: "customers.csv".to_string(),
+                rows: &customers,
+            },
+            JoinInput {
+                name: "orders.csv".to_string(),
+                rows: &orders,
+            },
+        ];
+
+        let joined = join_rows(0, 3, &inputs, JoinType::Outer, DuplicateKeys::First).unwrap();
+
+        assert_eq!(
+            joined,
+            rows(&[
+                &["1", "Alice", "10"],
+                &["2", "Bob", ""],
+                &["3", "Carol", "30"],
+                &["4", "", "40"],
+            ])
+        );
+    }
+
+    #[test]
+    fn test_join_inner_and_left() {
+        let (customers, orders) = inputs();
+        let inputs = [
+            JoinInput {
+                name: "customers.csv".to_string(),
+                rows: &customers,
+            },
+            JoinInput {
+                name: "orders.csv".to_string(),
+                rows: &orders,
+            },
+        ];
+
+        let inner = join_rows(0, 3, &inputs, JoinType::Inner, DuplicateKeys::First).unwrap();
+        assert_eq!(inner, rows(&[&["1", "Alice", "10"], &["3", "Carol", "30"]]));
+
+        let left = join_rows(0, 3, &inputs, JoinType::Left, DuplicateKeys::First).unwrap();
+        assert_eq!(
+            left,
+            rows(&[
+                &["1", "Alice", "10"],
+                &["2", "Bob", ""],
+                &["3", "Carol", "30"]
+            ])
+        );
+    }
+
+    #[test]
+    fn test_prefixed_header() {
+        let header = |cols: &[&str]| {
+            cols.iter()
+                .map(|col| col.to_string())
+                .collect::<Vec<String>>()
+        };
+        let customers = prefix_columns(&header(&["id", "amount"]), "id", "customers");
+        let orders = prefix_columns(&header(&["amount", "id", "date"]), "id", "orders");
+        assert_eq!(customers, vec!["id", "customers_amount"]);
+        assert_eq!(orders, vec!["orders_amount", "id", "orders_date"]);
+
+        let merged = header(&["Region", "id", "amount", "date"]);
+        let headers = [("customers", customers), ("orders", orders)];
+        assert_eq!(
+            prefixed_header(&merged, "id", &headers),
+            vec![
+                "Region",
+                "id",
+                "customers_amount",
+                "orders_amount",
+                "orders_date"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_duplicate_keys() {
+        let data = rows(&[&["1", "first"], &["1", "second"]]);
+        let inputs = [JoinInput {
+            name: "dupes.csv".to_string(),
+            rows: &data,
+        }];
+
+        let first = join_rows(0, 2, &inputs, JoinType::Outer, DuplicateKeys::First).unwrap();
+        assert_eq!(first, rows(&[&["1", "first"]]));
+
+        let last = join_rows(0, 2, &inputs, JoinType::Outer, DuplicateKeys::Last).unwrap();
+        assert_eq!(last, rows(&[&["1", "second"]]));
+
+        let error = join_rows(0, 2, &inputs, JoinType::Outer, DuplicateKeys::Error);
+        assert!(error.unwrap_err().to_string().contains("dupes.csv"));
+    }
+}
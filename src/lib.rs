@@ -0,0 +1,24 @@
+//! Grouping and merging of CSV/Excel headers, shared by the `csv_combine` binary.
+//!
+//! The header functions work on headers and rows already in memory and never touch
+//! the filesystem, so they can be reused by embedders that read data themselves.
+//! [`csv_io`] holds the CSV reading and writing the binary does, so benchmarks
+//! measure it, and [`encoding`] the output transcoding it writes through.
+//! [`combine_files`] chains them into a whole run and returns a [`RunResult`].
+
+pub mod csv_io;
+pub mod encoding;
+pub mod graph;
+pub mod headers;
+pub mod run;
+pub mod synonyms;
+
+pub use graph::compatibility_dot;
+pub use headers::{
+    generate_header_hash, group_files, group_files_by, header_overlap_score,
+    headers_are_compatible, map_rows_to_header, merge_headers, merge_headers_by,
+    shared_column_count, similarity_score, suggest_threshold, ColumnOrder, MergeStrategy, SimilarityMetric,
+    ThresholdSuggestion, DEFAULT_OVERLAP_THRESHOLD,
+};
+pub use run::{combine_files, CombineOptions, GroupResult, RunResult, SkipReason, SkippedFile};
+pub use synonyms::{name_similarity, suggest_aliases, AliasSuggestion, DEFAULT_SYNONYM_SIMILARITY};
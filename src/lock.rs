@@ -0,0 +1,62 @@
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use log::info;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Name of the `--lock` file created in the output directory
+pub const LOCK_FILE: &str = ".csv_combine.lock";
+
+/// Takes an exclusive advisory lock on the lock file in `dir`, waiting for another
+/// instance to finish when `wait` is set and failing otherwise. The lock is held
+/// until the returned file is dropped; the file itself is left in place.
+pub fn acquire(dir: &Path, wait: bool) -> Result<File> {
+    if !dir.as_os_str().is_empty() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let path = dir.join(LOCK_FILE);
+    let mut file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+
+    if file.try_lock_exclusive().is_err() {
+        if !wait {
+            return Err(anyhow::anyhow!(
+                "Another csv_combine run holds {}, use --lock-wait to wait for it",
+                path.display()
+            ));
+        }
+        info!(
+            "Waiting for another csv_combine run to release {}",
+            path.display()
+        );
+        file.lock_exclusive()?;
+    }
+
+    // The PID only helps whoever finds a stuck lock, it is not part of the locking
+    file.set_len(0)?;
+    writeln!(file, "{}", std::process::id())?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_second_lock_fails_until_released() {
+        let test_dir = TempDir::new().unwrap();
+
+        let held = acquire(test_dir.path(), false).unwrap();
+        let err = acquire(test_dir.path(), false).unwrap_err();
+        assert!(err.to_string().contains("--lock-wait"));
+
+        drop(held);
+        assert!(acquire(test_dir.path(), false).is_ok());
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::Result;
+use log::{LevelFilter, Log, Metadata, Record};
+use pretty_env_logger::env_logger;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+thread_local! {
+    /// Label put before the records of this thread, see [`set_group`]
+    static GROUP: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Prefixes the records logged by the current thread with `[group]` until it is
+/// cleared, so lines of groups processed side by side stay apart
+pub fn set_group(group: Option<String>) {
+    GROUP.with(|current| *current.borrow_mut() = group);
+}
+
+/// Sends every record to the console and copies warnings and errors to `--error-log`
+struct TeeLogger {
+    console: env_logger::Logger,
+    error_log: Option<Mutex<File>>,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let group = GROUP.with(|group| group.borrow().clone());
+        match group {
+            Some(group) => self.log_record(
+                &Record::builder()
+                    .args(format_args!("[{}] {}", group, record.args()))
+                    .level(record.level())
+                    .target(record.target())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            ),
+            None => self.log_record(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Some(file) = &self.error_log
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.flush();
+        }
+    }
+}
+
+impl TeeLogger {
+    fn log_record(&self, record: &Record) {
+        self.console.log(record);
+
+        if record.level() <= log::Level::Warn
+            && let Some(file) = &self.error_log
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = writeln!(file, "{}", format_error_line(record));
+        }
+    }
+}
+
+fn format_error_line(record: &Record) -> String {
+    format!(
+        "{} {:<5} {}: {}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        record.level(),
+        record.target(),
+        record.args()
+    )
+}
+
+/// Installs the console logger, plus an append-mode warn/error sink when `error_log` is set
+pub fn init(error_log: Option<&Path>) -> Result<()> {
+    let console = env_logger::builder()
+        .format_timestamp(None)
+        .filter_level(LevelFilter::Debug)
+        .build();
+    let max_level = console.filter();
+
+    let error_log = match error_log {
+        Some(path) => Some(Mutex::new(
+            File::options().create(true).append(true).open(path)?,
+        )),
+        None => None,
+    };
+
+    log::set_logger(Box::leak(Box::new(TeeLogger { console, error_log })))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_error_line() {
+        let line = format_error_line(
+            &Record::builder()
+                .level(log::Level::Warn)
+                .target("csv_combine")
+                .args(format_args!("Skipping {}: {}", "a.csv", "too wide"))
+                .build(),
+        );
+
+        // The timestamp varies, the rest does not
+        assert!(line.ends_with(" WARN  csv_combine: Skipping a.csv: too wide"));
+    }
+}
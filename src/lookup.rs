@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// A `--lookup COLUMN:FILE` specification
+#[derive(Debug, Clone, PartialEq)]
+pub struct LookupSpec {
+    pub column: String,
+    pub path: PathBuf,
+}
+
+impl LookupSpec {
+    /// Parses `COLUMN:FILE`. The column name ends at the first colon, so the file
+    /// may be a Windows path such as `C:\lookups\states.csv`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let Some((column, path)) = spec.split_once(':') else {
+            return Err(format!("expected COLUMN:FILE, got '{}'", spec));
+        };
+
+        let column = column.trim();
+        if column.is_empty() || path.trim().is_empty() {
+            return Err(format!("expected COLUMN:FILE, got '{}'", spec));
+        }
+
+        Ok(Self {
+            column: column.to_string(),
+            path: PathBuf::from(path.trim()),
+        })
+    }
+}
+
+/// Code to label translations for one column
+#[derive(Debug, Clone, Default)]
+pub struct Lookup {
+    pub column: String,
+    pub labels: HashMap<String, String>,
+}
+
+impl Lookup {
+    /// Loads a lookup CSV: a header row, then `code,label` rows. Extra columns are ignored.
+    pub fn load(spec: &LookupSpec) -> Result<Self> {
+        let labels = read_labels(&spec.path)
+            .with_context(|| format!("Failed to read lookup file {}", spec.path.display()))?;
+        Ok(Self {
+            column: spec.column.clone(),
+            labels,
+        })
+    }
+}
+
+fn read_labels(path: &Path) -> Result<HashMap<String, String>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+    let mut labels = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        if let (Some(code), Some(label)) = (record.get(0), record.get(1)) {
+            labels
+                .entry(code.trim().to_string())
+                .or_insert_with(|| label.to_string());
+        }
+    }
+    Ok(labels)
+}
+
+/// Replaces values of the lookup columns with their labels. Unmatched and empty
+/// values are left unchanged; the distinct unmatched values are returned per column.
+pub fn apply_lookups(
+    header: &[String],
+    rows: &mut [Vec<String>],
+    lookups: &[Lookup],
+) -> Vec<(String, BTreeSet<String>)> {
+    let mut unmatched = Vec::new();
+    for lookup in lookups {
+        let Some(col_idx) = header.iter().position(|col| *col == lookup.column) else {
+            continue;
+        };
+
+        let mut missing = BTreeSet::new();
+        for cell in rows.iter_mut().filter_map(|row| row.get_mut(col_idx)) {
+            let code = cell.trim();
+            if code.is_empty() {
+                continue;
+            }
+            match lookup.labels.get(code) {
+                Some(label) => cell.clone_from(label),
+                None => {
+                    missing.insert(code.to_string());
+                }
+            }
+        }
+        if !missing.is_empty() {
+            unmatched.push((lookup.column.clone(), missing));
+        }
+    }
+    unmatched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec = LookupSpec::parse("state:C:\\lookups\\states.csv").unwrap();
+        assert_eq!(spec.column, "state");
+        assert_eq!(spec.path, PathBuf::from("C:\\lookups\\states.csv"));
+        assert!(LookupSpec::parse("states.csv").is_err());
+    }
+
+    #[test]
+    fn test_apply_lookups() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("states.csv");
+        fs::write(
+            &path,
+            "code,label\nNY,New York\nCA,California\nNY,Duplicate\n",
+        )
+        .unwrap();
+        let lookup = Lookup::load(&LookupSpec {
+            column: "state".to_string(),
+            path,
+        })
+        .unwrap();
+
+        let header = vec!["name".to_string(), "state".to_string()];
+        let mut rows = vec![
+            vec!["Alice".to_string(), "NY".to_string()],
+            vec!["Bob".to_string(), "TX".to_string()],
+            vec!["Carol".to_string(), "".to_string()],
+        ];
+        let unmatched = apply_lookups(&header, &mut rows, &[lookup]);
+
+        assert_eq!(rows[0][1], "New York");
+        assert_eq!(rows[1][1], "TX");
+        assert_eq!(rows[2][1], "");
+        assert_eq!(
+            unmatched,
+            vec![("state".to_string(), BTreeSet::from(["TX".to_string()]))]
+        );
+    }
+}
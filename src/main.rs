@@ -1,692 +1,5755 @@
-use anyhow::Result;
-use calamine::{open_workbook_auto, Reader};
+mod aggregate;
+mod archive;
+mod cache;
+mod coerce;
+mod datetime;
+mod diff;
+mod feather;
+mod filter;
+mod join;
+mod lock;
+mod logging;
+mod lookup;
+mod profile;
+mod progress;
+mod report;
+mod schema;
+mod s3;
+mod sniff;
+mod split;
+mod template;
+mod workbook;
+
+use aggregate::AggregateRule;
+use anyhow::{Context, Result};
+use archive::ArchiveFormat;
+use cache::FileCache;
+use calamine::{open_workbook_auto, Data, ExcelDateTime, ExcelDateTimeType, Reader};
+use clap::Parser;
+use coerce::{OnTypeError, TypeSpec};
+use csv_combine::csv_io::{
+    self, column_projection, create_csv_writer, is_gzipped_csv, project_row, write_combined_csv, write_row,
+    CsvReadOptions, CsvWriteOptions, OutputFile,
+};
+use csv_combine::{
+    compatibility_dot, generate_header_hash, group_files_by, map_rows_to_header,
+    merge_headers_by, name_similarity, suggest_aliases, suggest_threshold, AliasSuggestion, ColumnOrder,
+    MergeStrategy, SimilarityMetric,
+    DEFAULT_OVERLAP_THRESHOLD, DEFAULT_SYNONYM_SIMILARITY,
+};
+use datetime::DatetimeSpec;
+use csv_combine::encoding;
+use filter::Predicate;
 use log::*;
-use std::collections::{HashMap, HashSet};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use lookup::{Lookup, LookupSpec};
+use profile::Profiler;
+use progress::ThroughputLogger;
+use report::{FileEntry, FileStatus, GroupSummary, ReportFormat};
+use schema::SchemaFormat;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use system_pause::pause;
+use template::TemplateAnchor;
+use unicode_normalization::UnicodeNormalization;
+use workbook::WorkbookWriter;
+
+/// Command line arguments
+#[derive(Parser, Debug, Default)]
+#[command(version, about)]
+struct Args {
+    /// Directory or file to process (defaults to the current directory)
+    input: Option<PathBuf>,
+
+    /// Exit with an error instead of succeeding when no input files are found
+    #[arg(long)]
+    halt_on_empty: bool,
+
+    /// Read the files to combine from this list (one path per line) instead of scanning
+    #[arg(long, value_name = "FILE", conflicts_with = "input")]
+    input_list: Option<PathBuf>,
+
+    /// Only process the first N files found, for quick trial runs
+    #[arg(long, value_name = "N")]
+    first_n_files: Option<usize>,
+
+    /// Order in which files are processed [default for scans: name; --input-list keeps its order]
+    #[arg(long, value_enum, value_name = "ORDER")]
+    sort_files_by: Option<FileOrder>,
+
+    /// Process files in descending --sort-files-by order
+    #[arg(long)]
+    sort_desc: bool,
+
+    /// Concatenate byte-split exports (data.csv.001, data.csv.002, ...) into one file
+    /// before reading, so the header in part 001 applies to every part
+    #[arg(long, conflicts_with = "input_list")]
+    reassemble_split: bool,
+
+    /// Read column names from a sidecar file and treat every input as headerless
+    #[arg(long, value_name = "FILE")]
+    header_from: Option<PathBuf>,
+
+    /// Pick each file's header from its first rows instead of always using the first row,
+    /// skipping the preamble above it
+    #[arg(long, conflicts_with = "header_from")]
+    detect_header: bool,
+
+    /// Drop this many rows (a vendor preamble) from the top of each CSV file or sheet
+    /// before its header row
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with_all = ["header_from", "split_on_blank_lines"])]
+    skip_header_rows: usize,
+
+    /// Drop every line of each CSV up to and including the first one holding only MARKER,
+    /// e.g. ---DATA---, and read the next line as the header. Files without it are skipped.
+    #[arg(long, value_name = "MARKER", allow_hyphen_values = true, conflicts_with_all = ["header_from", "split_on_blank_lines"])]
+    data_starts_after: Option<String>,
+
+    /// Drop this many rows (a "Total" line, a footnote) from the end of each CSV file or sheet
+    #[arg(long, value_name = "N", default_value_t = 0, conflicts_with_all = ["header_from", "split_on_blank_lines"])]
+    skip_footer_rows: usize,
+
+    /// CSV of header aliases (source_name,canonical_name[,confidence]), or a
+    /// --header-synonyms-learn JSON file, applied while reading
+    #[arg(long, value_name = "FILE")]
+    alias_map: Option<PathBuf>,
+
+    /// Only apply --alias-map entries with at least this confidence
+    #[arg(long, value_name = "CONFIDENCE", default_value_t = 0.0)]
+    alias_min_confidence: f64,
+
+    /// Memory-map CSV inputs instead of reading them through a buffered file handle
+    #[arg(long)]
+    mmap: bool,
+
+    /// Only process files whose header contains every listed column (comma separated)
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    must_have: Vec<String>,
+
+    /// Only keep these columns (comma separated) while reading, discarding the rest
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    read_columns: Vec<String>,
+
+    /// Rewrite a datetime column to UTC ISO-8601 (repeatable). ZONE is the timezone of
+    /// values without an offset: utc (default), local or +HH:MM
+    #[arg(long, value_name = "COLUMN[:ZONE]", value_parser = DatetimeSpec::parse)]
+    normalize_datetime: Vec<DatetimeSpec>,
+
+    /// Declared column types, coerced and validated per cell, e.g. "age:int,price:float,date:date".
+    /// Types: int, float, bool, date, text
+    #[arg(long, value_name = "COLUMN:TYPE", value_delimiter = ',', value_parser = TypeSpec::parse)]
+    schema: Vec<TypeSpec>,
+
+    /// What to do with rows whose --schema values cannot be coerced
+    #[arg(long, value_enum, value_name = "ACTION", default_value_t = OnTypeError::Reject)]
+    on_type_error: OnTypeError,
+
+    /// Replace values of COLUMN with labels from a code,label CSV (repeatable)
+    #[arg(long, value_name = "COLUMN:FILE", value_parser = LookupSpec::parse)]
+    lookup: Vec<LookupSpec>,
+
+    /// Log the values --lookup found no label for
+    #[arg(long)]
+    log_unmatched_lookups: bool,
+
+    /// chrono format used to parse --normalize-datetime values (common formats are tried otherwise)
+    #[arg(long, value_name = "FORMAT")]
+    datetime_format: Option<String>,
+
+    /// Log files/sec and rows/sec throughput every SECS seconds while reading
+    #[arg(long, value_name = "SECS")]
+    progress_interval: Option<u64>,
+
+    /// Give up on a file that takes longer than SECS seconds to read, skipping it with a warning
+    #[arg(long, value_name = "SECS")]
+    timeout_per_file: Option<u64>,
+
+    /// Output record terminator: lf (default), crlf, or any single character
+    #[arg(long, value_name = "TERMINATOR", value_parser = parse_terminator)]
+    line_terminator: Option<csv::Terminator>,
+
+    /// Write output CSVs in this encoding instead of UTF-8, e.g. windows-1252
+    #[arg(long, value_name = "LABEL", value_parser = encoding::parse_encoding)]
+    output_encoding: Option<&'static encoding_rs::Encoding>,
+
+    /// Replace characters --output-encoding cannot represent with '?' instead of failing
+    #[arg(long, requires = "output_encoding")]
+    output_encoding_lossy: bool,
+
+    /// Write a comment line with this text before the header of each output CSV
+    #[arg(long, value_name = "TEXT")]
+    output_comment: Option<String>,
+
+    /// Character that starts the --output-comment line
+    #[arg(long, value_name = "CHAR", default_value_t = '#')]
+    comment_char: char,
+
+    /// Join the files of each group on a key column instead of stacking their rows
+    #[arg(long, value_name = "COLUMN", conflicts_with_all = ["dedup", "aggregate", "max_memory"])]
+    join_on: Option<String>,
+
+    /// Which keys a --join-on output keeps
+    #[arg(long, value_enum, default_value_t)]
+    join_type: join::JoinType,
+
+    /// How --join-on handles a key repeated within one file
+    #[arg(long, value_enum, default_value_t)]
+    join_duplicates: join::DuplicateKeys,
+
+    /// Prefix each joined file's non-key columns with its source path or file name,
+    /// e.g. orders_amount, so columns the files share stay apart
+    #[arg(long, value_enum, value_name = "PREFIX", requires = "join_on", conflicts_with_all = ["match_table_order", "strict_schema", "coalesce"])]
+    prefix_columns_with: Option<join::ColumnPrefix>,
+
+    /// NFC-normalize header cells and replace non-breaking spaces before matching
+    #[arg(long)]
+    normalize_unicode: bool,
+
+    /// Per-file CSV delimiter as PATTERN=DELIM, where PATTERN is a filename substring or
+    /// glob (repeatable, first match wins)
+    #[arg(long, value_name = "PATTERN=DELIM", value_parser = DelimiterRule::parse)]
+    delimiter_map: Vec<DelimiterRule>,
+
+    /// Sniff each CSV's delimiter (comma, semicolon, tab or pipe) from its first lines,
+    /// for files --delimiter-map does not match
+    #[arg(long)]
+    auto_delimiter: bool,
+
+    /// Only report per-group and total data-row counts, without mapping or writing files
+    #[arg(long)]
+    count_only: bool,
+
+    /// Skip malformed CSV rows (wrong field count or invalid UTF-8) instead of failing the file
+    #[arg(long)]
+    skip_bad_rows: bool,
+
+    /// Skip .csv files whose content is clearly not CSV (HTML, binary, archives)
+    #[arg(long)]
+    validate_content: bool,
+
+    /// Keep a random fraction (0..1) of each output's rows
+    #[arg(long, value_name = "FRACTION", value_parser = parse_fraction, conflicts_with = "sample_n")]
+    sample_fraction: Option<f64>,
+
+    /// Keep at most COUNT random rows of each output
+    #[arg(long, value_name = "COUNT")]
+    sample_n: Option<usize>,
+
+    /// Seed for --sample-fraction/--sample-n (a random seed is logged when omitted)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Keep only the last N data rows of each file
+    #[arg(long, value_name = "N")]
+    tail_rows: Option<usize>,
+
+    /// Treat columns differing only by case as one column, named with this casing
+    #[arg(long, value_enum, value_name = "CASING")]
+    canonical_casing: Option<CanonicalCasing>,
+
+    /// Skip files whose header has more than N columns, and warn about wider merged headers
+    #[arg(long, value_name = "N")]
+    max_columns: Option<usize>,
+
+    /// Read the Excel sheet(s) whose name matches this glob instead of the first sheet
+    #[arg(long, value_name = "GLOB")]
+    sheet_pattern: Option<glob::Pattern>,
+
+    /// Which sheets matching --sheet-pattern are read
+    #[arg(long, value_enum, default_value_t)]
+    sheet_pattern_mode: SheetPatternMode,
+
+    /// Stack every sheet of a workbook that shares the first sheet's header
+    #[arg(long)]
+    combine_sheets_within_file: bool,
+
+    /// Render Excel cells the way Excel shows them (dates, whole numbers, booleans) instead of raw values
+    #[arg(long)]
+    preserve_excel_formatting: bool,
+
+    /// Date system of Excel date serials: auto (what the workbook declares), 1900 or 1904
+    #[arg(long, value_enum, default_value_t, value_name = "SYSTEM")]
+    excel_date_system: ExcelDateSystem,
+
+    /// Drop a leading unnamed index column (empty or "Unnamed: 0" header, values 0, 1, 2, ...)
+    #[arg(long)]
+    drop_index_column: bool,
+
+    /// Treat blank-line separated blocks of a CSV as separate tables, each with its own header and delimiter
+    #[arg(long)]
+    split_on_blank_lines: bool,
+
+    /// Write the file compatibility graph as Graphviz DOT and exit without combining
+    #[arg(long, value_name = "FILE")]
+    dry_run_graph: Option<PathBuf>,
+
+    /// Share of columns (0..1) two headers must have in common to be grouped
+    #[arg(long, value_name = "FRACTION", value_parser = parse_fraction, default_value_t = DEFAULT_OVERLAP_THRESHOLD)]
+    overlap_threshold: f64,
+
+    /// Columns two headers must have in common to be grouped, on top of --overlap-threshold
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    min_shared_columns: usize,
+
+    /// Abort before writing when the files would form more than N groups
+    #[arg(long, value_name = "N")]
+    max_output_files: Option<usize>,
+
+    /// Only combine the groups with these header hashes (comma separated), as logged
+    /// by --count-only, skipping the rest
+    #[arg(long, value_name = "HASHES", value_delimiter = ',')]
+    only_groups: Vec<String>,
+
+    /// Abort before writing, listing the odd files out, unless all files form a single group
+    #[arg(long)]
+    expect_single_group: bool,
+
+    /// Compare the single output to a previous one and write the row changes to added.csv
+    /// and removed.csv
+    #[arg(long, value_name = "OLD_CSV", conflicts_with = "max_memory")]
+    diff: Option<PathBuf>,
+
+    /// Also write the single output into a copy of this .xlsx, keeping its other sheets,
+    /// formatting and charts, as <template name>_combined.xlsx
+    #[arg(long, value_name = "XLSX", conflicts_with = "max_memory")]
+    xlsx_template: Option<PathBuf>,
+
+    /// Sheet and top-left cell of the output in --xlsx-template, e.g. Data!B3; the first
+    /// sheet when no sheet is named
+    #[arg(long, value_name = "[SHEET!]CELL", value_parser = TemplateAnchor::parse, requires = "xlsx_template")]
+    template_anchor: Option<TemplateAnchor>,
+
+    /// Show each planned group with its files and merged header and ask whether to combine
+    /// it, skip it or abort, before anything is written
+    #[arg(long)]
+    interactive: bool,
+
+    /// Approve every --interactive group without asking, as needed when stdin is not a terminal
+    #[arg(long, requires = "interactive")]
+    yes: bool,
+
+    /// What a file is compared against when joining a group: centroid (the group's merged
+    /// header so far), representative (the group's first file) or any-member
+    #[arg(long, value_name = "STRATEGY", default_value = "centroid")]
+    merge_strategy: MergeStrategy,
+
+    /// How two headers are scored against --overlap-threshold: jaccard (shared over all
+    /// columns), overlap (shared over the smaller header's columns) or dice
+    #[arg(long, value_name = "METRIC", default_value = "jaccard")]
+    similarity_metric: SimilarityMetric,
+
+    /// Order of a merged header's columns: first-seen, or frequency (columns in the most
+    /// files first, ties in first-seen order)
+    #[arg(long, value_name = "ORDER", default_value = "first-seen")]
+    column_order: ColumnOrder,
+
+    /// Write aliases suggested from similarly named columns as a JSON file of
+    /// clusters, usable as --alias-map once reviewed, and exit
+    #[arg(long, value_name = "FILE")]
+    header_synonyms_learn: Option<PathBuf>,
+
+    /// Report the pairwise overlap distribution and a suggested --overlap-threshold, then exit
+    #[arg(long)]
+    suggest_threshold: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// Leave empty cells out of the objects written by --format jsonl
+    #[arg(long)]
+    jsonl_omit_empty: bool,
+
+    /// Split outputs with more than N data rows into parts named <output>_part1.csv, _part2.csv, ...
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with = "max_memory"
+    )]
+    max_rows_per_file: Option<u64>,
+
+    /// Buffer output CSVs through a buffer of this many bytes, cutting write calls on large
+    /// outputs (see `cargo bench --bench csv_write`)
+    #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    write_buffer_size: Option<u64>,
+
+    /// Fail when a row written to an output is shorter than its header, instead of padding it
+    #[arg(long)]
+    strict: bool,
+
+    /// After writing, read the output CSVs back and fail unless grouping them again gives
+    /// one group per written group, with the same header
+    #[arg(long)]
+    self_check: bool,
+
+    /// Pack every output file into one combined.tar or combined.zip in the output directory,
+    /// removing the loose files
+    #[arg(long, value_enum, value_name = "FORMAT", requires = "max_rows_per_file")]
+    archive: Option<ArchiveFormat>,
+
+    /// Output directory for CSV and JSON lines files, or the workbook path for xlsx-workbook
+    /// (defaults to the current directory / combined.xlsx). An s3://bucket/prefix/ URL
+    /// uploads the combined output files there instead.
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Reuse parsed contents of unchanged files from a cache in the output directory
+    #[arg(long)]
+    cache: bool,
+
+    /// Log a per-phase timing breakdown and the slowest files to read at the end of the run
+    #[arg(long)]
+    profile: bool,
+
+    /// Emit source file paths relative to the input directory instead of as found
+    #[arg(long)]
+    relative_paths: bool,
+
+    /// Pivot key-value files (one field name and value per row) so field names become columns
+    #[arg(long)]
+    transpose: bool,
+
+    /// Column holding the field names of --transpose files
+    #[arg(long, value_name = "COLUMN", default_value = "key")]
+    transpose_key_column: String,
+
+    /// Column holding the values of --transpose files
+    #[arg(long, value_name = "COLUMN", default_value = "value")]
+    transpose_value_column: String,
+
+    /// Hold an advisory lock on .csv_combine.lock in the output directory for the whole run,
+    /// failing when another instance holds it
+    #[arg(long)]
+    lock: bool,
+
+    /// With --lock, wait for another instance to release the lock instead of failing
+    #[arg(long, requires = "lock")]
+    lock_wait: bool,
+
+    /// Also append warnings and errors to this file
+    #[arg(long, value_name = "PATH")]
+    error_log: Option<PathBuf>,
+
+    /// Write a run summary (combine_report.html) next to the outputs
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    combine_report: Option<ReportFormat>,
+
+    /// List every file read in the --combine-report with its status: included, empty,
+    /// unreadable, unsupported or rejected
+    #[arg(long, requires = "combine_report")]
+    include_empty_files_in_manifest: bool,
+
+    /// After combining, list the merged columns found in only one file of their group,
+    /// which are often header typos
+    #[arg(long)]
+    report_unmatched_columns: bool,
+
+    /// Write every merged schema produced (hash and pipe-joined header, one row per group) to this CSV
+    #[arg(long, value_name = "PATH")]
+    schemas_out: Option<PathBuf>,
+
+    /// Write every input's header to this CSV in long format, one file_path,
+    /// column_index, column_name row per column
+    #[arg(long, value_name = "PATH")]
+    emit_headers_csv: Option<PathBuf>,
+
+    /// Write a CSV describing every merged column of every output: its type, source
+    /// file count, fill rate, value lengths and sample values
+    #[arg(long, value_name = "PATH")]
+    data_dictionary: Option<PathBuf>,
+
+    /// Write each group's merged header to this CSV, for reuse with --strict-schema;
+    /// with several groups one file per group, named <stem>_<hash>.csv
+    #[arg(long, value_name = "PATH")]
+    save_schema: Option<PathBuf>,
+
+    /// Write a schema with inferred column types next to each output
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    emit_schema: Option<SchemaFormat>,
+
+    /// Suffix repeated output column names with _2, _3, ... instead of failing
+    #[arg(long)]
+    rename_duplicates: bool,
+
+    /// Case applied to output column names after grouping and merging
+    #[arg(long, value_enum, value_name = "CASE")]
+    columns_case_fold: Option<CaseFold>,
+
+    /// Rewrite output column names in one naming style: snake (customer_name), camel
+    /// (customerName) or title (Customer Name)
+    #[arg(long, value_enum, value_name = "STYLE", conflicts_with = "columns_case_fold")]
+    normalize_headers: Option<HeaderStyle>,
+
+    /// Apply --normalize-headers while reading instead, so names that differ only in
+    /// style group and merge as one column
+    #[arg(long, requires = "normalize_headers")]
+    normalize_headers_on_read: bool,
+
+    /// Add a column holding the group's header hash to every output row
+    #[arg(long)]
+    add_group_id: bool,
+
+    /// Approximate memory budget in bytes for a combined group's rows; past it, rows
+    /// are appended to the output file and the buffer is cleared
+    #[arg(
+        long,
+        value_name = "BYTES",
+        conflicts_with_all = ["join_on", "sample_fraction", "sample_n", "schema", "emit_schema", "combine_report", "data_dictionary"]
+    )]
+    max_memory: Option<usize>,
+
+    /// Process up to N groups at a time once files are grouped, 0 for one per CPU core
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    threads: usize,
+
+    /// Keep only rows matching an expression such as "Age > 18 AND Status == 'Active'",
+    /// evaluated per row against the group's columns before dedup
+    #[arg(long = "where", value_name = "EXPR", value_parser = Predicate::parse)]
+    where_filter: Option<Predicate>,
+
+    /// Drop rows that exactly repeat an earlier row of the same output
+    #[arg(long)]
+    dedup: bool,
+
+    /// Values that mean "no value", compared as empty by --dedup (comma separated, case-insensitive)
+    #[arg(long, value_name = "VALUES", value_delimiter = ',')]
+    null_values: Vec<String>,
+
+    /// Compare only these columns when deduplicating, keeping the first row of each key (comma separated)
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', requires = "dedup")]
+    dedup_key: Vec<String>,
+
+    /// Combine the rows --dedup-key collapses instead of keeping only the first, as COLUMN:FUNC
+    /// with FUNC one of sum, max, min, first, last or concat (comma separated)
+    #[arg(
+        long,
+        value_name = "RULES",
+        value_delimiter = ',',
+        value_parser = AggregateRule::parse,
+        requires = "dedup_key",
+        conflicts_with = "max_memory"
+    )]
+    aggregate: Vec<AggregateRule>,
+
+    /// Write the rows --dedup removed, with their source file, to this CSV
+    #[arg(long, value_name = "PATH", requires = "dedup")]
+    dedup_report: Option<PathBuf>,
+
+    /// Add a __source_file column naming the file each row came from
+    #[arg(long)]
+    add_source_file: bool,
+
+    /// Add a __source_row column with each row's 1-based data row number in its source file
+    #[arg(long)]
+    add_source_row: bool,
+
+    /// Name of the --add-group-id column
+    #[arg(long, value_name = "NAME", default_value = "group_id")]
+    group_id_column: String,
+
+    /// Columns placed first in every output, in this order (created empty when missing)
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    pin_columns: Vec<String>,
+
+    /// Make each group's merged header exactly its first file's header, dropping the
+    /// columns other files add with a warning
+    #[arg(long, conflicts_with_all = ["pin_columns", "match_table_order", "strict_schema", "coalesce"])]
+    keep_first_header_exact: bool,
+
+    /// Make every output header exactly the columns listed in this file, in order
+    #[arg(long, value_name = "FILE", conflicts_with = "pin_columns")]
+    match_table_order: Option<PathBuf>,
+
+    /// Let --match-table-order add columns no input file has, empty, instead of failing
+    #[arg(long)]
+    allow_missing: bool,
+
+    /// Make every output header exactly the header row of this CSV, silently dropping
+    /// other source columns and leaving reference columns no input has empty
+    #[arg(long, value_name = "HEADER_CSV", conflicts_with_all = ["pin_columns", "match_table_order"])]
+    strict_schema: Option<PathBuf>,
+
+    /// Remove trailing spaces from the values of these columns (comma separated)
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    rtrim_columns: Vec<String>,
+
+    /// Fill TARGET from the first non-empty SOURCE column, as TARGET:SOURCE1,SOURCE2,...
+    /// (repeatable). A TARGET no input has is added to the output.
+    #[arg(long, value_name = "TARGET:SOURCES", value_parser = CoalesceRule::parse)]
+    coalesce: Vec<CoalesceRule>,
+
+    /// Blank cells repeating an earlier value of the same row within these columns, as
+    /// COL1,COL2,... (repeatable, one group of columns each)
+    #[arg(long, value_name = "COLUMNS")]
+    dedup_row_columns: Vec<String>,
+
+    /// Replace non-empty values of these columns with their salted SHA-256 hash, so equal
+    /// values still match up after anonymizing
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    hash_columns: Vec<String>,
+
+    /// Salt prepended to every --hash-columns value before hashing
+    #[arg(long, value_name = "SALT", requires = "hash_columns")]
+    hash_salt: Option<String>,
+
+    /// Name outputs from a template using {prefix}, {hash}, {count}, {date}, {columns} and {first_source}
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_output_template)]
+    output_template: Option<String>,
+
+    /// Name outputs <dir>_<hash>.csv after the directory all of a group's files share,
+    /// ignoring deeper subdirectories, or after the input directory when they share none
+    #[arg(long, conflicts_with = "output_template")]
+    name_by_dir: bool,
+}
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    pretty_env_logger::env_logger::builder()
-        .format_timestamp(None)
-        .filter_level(LevelFilter::Debug)
-        .init();
-
-    let input_path = PathBuf::from(
-        std::env::args().nth(1).unwrap_or(
-            std::env::current_dir()
-                .unwrap_or(PathBuf::from("./"))
-                .to_string_lossy()
-                .to_string(),
-        ),
-    );
+/// Output formats selected by `--format`
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+enum OutputFormat {
+    /// One CSV file per group
+    #[default]
+    Csv,
+    /// A single .xlsx workbook with one sheet per group
+    XlsxWorkbook,
+    /// One newline-delimited JSON file per group, an object per row
+    Jsonl,
+}
 
-    info!("Searching for files in: {}", input_path.display());
-    let files = get_files(&input_path)?;
-    info!("Found {} files to process", files.len());
+/// File processing order selected by `--sort-files-by`
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum FileOrder {
+    /// Path, alphabetically
+    Name,
+    /// Last modification time
+    Mtime,
+    /// File size in bytes
+    Size,
+}
 
-    if files.is_empty() {
-        warn!("No CSV or Excel files found!");
-        return Ok(());
-    }
+/// How `--sheet-pattern` treats several matching sheets
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+enum SheetPatternMode {
+    /// Read the first matching sheet
+    #[default]
+    First,
+    /// Stack every matching sheet that shares the first one's header
+    Combine,
+}
 
-    // Read all file headers and contents
-    let mut file_data: Vec<(PathBuf, Vec<Vec<String>>)> = Vec::new();
+/// Epoch Excel date serials count from, selected by `--excel-date-system`
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+enum ExcelDateSystem {
+    /// The system the workbook declares
+    #[default]
+    Auto,
+    /// Serial 1 is 1900-01-01, the Windows Excel default
+    #[value(name = "1900")]
+    Excel1900,
+    /// Serial 0 is 1904-01-01, used by workbooks from older Mac Excel
+    #[value(name = "1904")]
+    Excel1904,
+}
 
-    for file_path in files {
-        info!("Reading: {}", file_path.display());
-        match get_file_contents(&file_path) {
-            Ok(data) => {
-                if data.is_empty() {
-                    warn!("File is empty: {}", file_path.display());
-                    continue;
-                }
-                file_data.push((file_path, data));
-            }
-            Err(e) => {
-                warn!("Failed to read file {}: {}", file_path.display(), e);
-                continue;
-            }
+/// Casing applied to column names by `--canonical-casing`
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum CanonicalCasing {
+    /// Capitalize the first letter of each word
+    Title,
+    Lower,
+    Upper,
+    /// Keep the casing of the first file that has the column
+    First,
+}
+
+/// Case applied to output headers by `--columns-case-fold`
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum CaseFold {
+    Lower,
+    Upper,
+    /// Capitalize the first letter of each word
+    Title,
+}
+
+/// Naming style of `--normalize-headers`
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum HeaderStyle {
+    Snake,
+    Camel,
+    Title,
+}
+
+impl HeaderStyle {
+    /// Rewrites `value` from its words, split at separators and case changes
+    fn apply(self, value: &str) -> String {
+        let words = header_words(value);
+        if words.is_empty() {
+            return value.to_string();
+        }
+        match self {
+            HeaderStyle::Snake => words.join("_"),
+            HeaderStyle::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(idx, word)| if idx == 0 { word.clone() } else { capitalize(word) })
+                .collect(),
+            HeaderStyle::Title => words.iter().map(|word| capitalize(word)).collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+impl CaseFold {
+    fn apply(self, value: &str) -> String {
+        match self {
+            CaseFold::Lower => value.to_lowercase(),
+            CaseFold::Upper => value.to_uppercase(),
+            CaseFold::Title => to_title_case(value),
         }
     }
+}
+
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    let fraction: f64 = value.parse().map_err(|_| format!("'{}' is not a number", value))?;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(format!("fraction must be between 0 and 1, got {}", fraction));
+    }
+    Ok(fraction)
+}
 
-    // Group files by header compatibility (>= 50% overlap)
-    let mut groups: Vec<Vec<usize>> = Vec::new();
+/// Placeholders understood by `--output-template`
+const TEMPLATE_PLACEHOLDERS: [&str; 6] = ["prefix", "hash", "count", "date", "columns", "first_source"];
 
-    for i in 0..file_data.len() {
-        let mut added_to_group = false;
+/// Parses `--output-template`, rejecting unknown or unclosed placeholders up front
+fn parse_output_template(value: &str) -> Result<String, String> {
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            return Err(format!("unclosed '{{' in '{}'", value));
+        };
+        let name = &rest[start + 1..start + len];
+        if !TEMPLATE_PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "unknown placeholder '{{{}}}', expected one of: {}",
+                name,
+                TEMPLATE_PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &rest[start + len + 1..];
+    }
+    Ok(value.to_string())
+}
 
-        // Try to add to an existing compatible group
-        for group in &mut groups {
-            let group_representative = group[0];
-            let header1 = &file_data[i].1[0];
-            let header2 = &file_data[group_representative].1[0];
+/// Fills in an `--output-template`, adding `.csv` when the template leaves it out
+fn render_output_template(template: &str, values: &[(&str, String)]) -> String {
+    let mut name = template.to_string();
+    for (placeholder, value) in values {
+        name = name.replace(&format!("{{{}}}", placeholder), value);
+    }
+    if !name.ends_with(".csv") {
+        name.push_str(".csv");
+    }
+    name
+}
 
-            if headers_are_compatible(header1, header2) {
-                group.push(i);
-                added_to_group = true;
-                break;
+/// Name of the deepest directory holding every file in `paths`, or of
+/// `input_path` when they have none in common, made safe for a file name
+fn group_dir_name<'a>(paths: impl IntoIterator<Item = &'a Path>, input_path: &Path) -> String {
+    let mut paths = paths.into_iter();
+    let mut shared = paths.next().and_then(Path::parent).map(Path::to_path_buf);
+    for path in paths {
+        while let Some(dir) = shared.as_mut().filter(|dir| !path.starts_with(dir.as_path())) {
+            if !dir.pop() {
+                shared = None;
             }
         }
+    }
+    let dir = shared.filter(|dir| dir.file_name().is_some()).unwrap_or_else(|| {
+        let input_dir = if input_path.is_file() { input_path.parent().unwrap_or(input_path) } else { input_path };
+        std::path::absolute(input_dir).unwrap_or_else(|_| input_dir.to_path_buf())
+    });
+
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    match name.trim_matches('_') {
+        "" => "input".to_string(),
+        name => name.to_string(),
+    }
+}
 
-        // Create a new group if not compatible with any existing group
-        if !added_to_group {
-            groups.push(vec![i]);
-        }
+/// Returns `name`, or `name` with a numeric suffix when an earlier group already used it
+fn unique_output_name(name: String, used: &mut HashSet<String>) -> String {
+    if used.insert(name.clone()) {
+        return name;
     }
+    let stem = name.strip_suffix(".csv").unwrap_or(&name);
+    let unique = (2..)
+        .map(|n| format!("{}_{}.csv", stem, n))
+        .find(|candidate| !used.contains(candidate))
+        .unwrap_or_default();
+    warn!("Output name {} is already taken, writing {} instead", name, unique);
+    used.insert(unique.clone());
+    unique
+}
 
-    info!("Found {} compatible header groups", groups.len());
+/// A `--coalesce` entry: the target column and the columns it is filled from, in order
+#[derive(Debug, Clone, PartialEq)]
+struct CoalesceRule {
+    target: String,
+    sources: Vec<String>,
+}
 
-    // Process each group
-    let mut files_created = 0;
-    for group in groups {
-        // Collect all headers from the group and merge them
-        let mut all_headers: Vec<Vec<String>> = Vec::new();
-        for &file_idx in &group {
-            all_headers.push(file_data[file_idx].1[0].clone());
+impl CoalesceRule {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let (target, sources) = spec
+            .split_once(':')
+            .ok_or_else(|| format!("expected TARGET:SOURCE1,SOURCE2,..., got '{}'", spec))?;
+        let sources: Vec<String> = sources
+            .split(',')
+            .map(|col| col.trim().to_string())
+            .filter(|col| !col.is_empty())
+            .collect();
+        if target.trim().is_empty() || sources.is_empty() {
+            return Err(format!("expected TARGET:SOURCE1,SOURCE2,..., got '{}'", spec));
         }
 
-        let merged_header = merge_headers(&all_headers);
-        let header_hash = generate_header_hash(&merged_header);
+        Ok(Self {
+            target: target.trim().to_string(),
+            sources,
+        })
+    }
+}
 
-        info!(
-            "Processing group with merged headers: {} ({} files)",
-            merged_header.join(", "),
-            group.len()
-        );
+/// A `--delimiter-map` entry selecting the delimiter for matching file names
+#[derive(Debug, Clone)]
+struct DelimiterRule {
+    pattern: String,
+    glob: Option<glob::Pattern>,
+    delimiter: u8,
+}
 
-        if group.len() == 1 {
-            // Single file - copy with merged header (should be same as original)
-            let file_idx = group[0];
-            let (file_path, data) = &file_data[file_idx];
-            let output_filename = format!("single_{}.csv", header_hash);
-            info!("Copying single file: {}", file_path.display());
+impl DelimiterRule {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let (pattern, delimiter) = spec
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected PATTERN=DELIM, got '{}'", spec))?;
+        if pattern.is_empty() {
+            return Err(format!("missing file pattern in '{}'", spec));
+        }
 
-            let mapped_rows = map_rows_to_header(&data[0], &merged_header, &data[1..]);
-            write_combined_csv(&output_filename, &merged_header, &mapped_rows)?;
+        let delimiter = match delimiter {
+            "\\t" | "tab" => b'\t',
+            d if d.len() == 1 => d.as_bytes()[0],
+            d => return Err(format!("delimiter must be a single ASCII character, got '{}'", d)),
+        };
 
-            info!("Created: {} (1 file, {} data rows)", output_filename, data.len() - 1);
-            files_created += 1;
+        let glob = if pattern.contains(['*', '?', '[']) {
+            Some(glob::Pattern::new(pattern).map_err(|e| e.to_string())?)
         } else {
-            // Multiple compatible files - combine them
-            let output_filename = format!("combined_{}.csv", header_hash);
-            info!("Combining {} compatible files into: {}", group.len(), output_filename);
+            None
+        };
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            glob,
+            delimiter,
+        })
+    }
+
+    fn matches(&self, file_name: &str) -> bool {
+        match &self.glob {
+            Some(glob) => glob.matches(file_name),
+            None => file_name.contains(&self.pattern),
+        }
+    }
+}
+
+/// Settings that control how individual input files are read
+#[derive(Debug, Default, Clone)]
+struct ReadOptions {
+    /// Header applied to headerless inputs (from `--header-from`)
+    header: Option<Vec<String>>,
+    /// Memory-map CSV inputs (from `--mmap`)
+    mmap: bool,
+    /// Find the header among the first rows (from `--detect-header`)
+    detect_header: bool,
+    /// Rows dropped above the header (from `--skip-header-rows`)
+    skip_header_rows: usize,
+    /// Line the data of a CSV follows (from `--data-starts-after`)
+    data_starts_after: Option<String>,
+    /// Rows dropped at the end (from `--skip-footer-rows`)
+    skip_footer_rows: usize,
+    /// Columns kept while reading, all when empty (from `--read-columns`)
+    read_columns: Vec<String>,
+    /// Normalize header cells to NFC (from `--normalize-unicode`)
+    normalize_unicode: bool,
+    /// Naming style applied to header cells (from `--normalize-headers-on-read`)
+    header_style: Option<HeaderStyle>,
+    /// Source to canonical column names (from `--alias-map`). Ordered so the
+    /// `--cache` fingerprint is stable.
+    aliases: BTreeMap<String, String>,
+    /// Per-file delimiters (from `--delimiter-map`)
+    delimiter_map: Vec<DelimiterRule>,
+    /// Sniff the delimiter of each other CSV (from `--auto-delimiter`)
+    auto_delimiter: bool,
+    /// Skip malformed CSV rows (from `--skip-bad-rows`)
+    skip_bad_rows: bool,
+    /// Sniff CSV content before parsing (from `--validate-content`)
+    validate_content: bool,
+    /// Excel sheet name glob (from `--sheet-pattern`)
+    sheet_pattern: Option<glob::Pattern>,
+    /// How several matching sheets are read (from `--sheet-pattern-mode`)
+    sheet_pattern_mode: SheetPatternMode,
+    /// Stack all sheets of a workbook (from `--combine-sheets-within-file`)
+    combine_sheets: bool,
+    /// Key and value columns of files to pivot (from `--transpose`)
+    transpose: Option<(String, String)>,
+    /// Render Excel cells as displayed (from `--preserve-excel-formatting`)
+    preserve_excel_formatting: bool,
+    /// Epoch of Excel date serials (from `--excel-date-system`)
+    excel_date_system: ExcelDateSystem,
+    /// Drop pandas-style index columns (from `--drop-index-column`)
+    drop_index_column: bool,
+    /// Read CSV blocks as separate tables (from `--split-on-blank-lines`)
+    split_on_blank_lines: bool,
+}
+
+impl ReadOptions {
+    /// Whether CSV records are all read as data, ragged rows included, and the
+    /// header picked once the whole file is loaded
+    fn reads_raw_rows(&self) -> bool {
+        self.detect_header
+            || self.skip_header_rows > 0
+            || self.skip_footer_rows > 0
+            || self.data_starts_after.is_some()
+    }
 
-            let mut all_data: Vec<Vec<String>> = Vec::new();
+    /// The settings [`csv_io::read_csv`] reads a CSV input with
+    fn csv(&self, delimiter: Option<u8>) -> CsvReadOptions {
+        CsvReadOptions {
+            header: self.header.clone(),
+            raw_rows: self.reads_raw_rows(),
+            read_columns: self.read_columns.clone(),
+            skip_bad_rows: self.skip_bad_rows,
+            mmap: self.mmap,
+            delimiter,
+        }
+    }
 
-            for &file_idx in &group {
-                let (file_path, data) = &file_data[file_idx];
-                info!("  - Including: {} (headers: {})", file_path.display(), data[0].join(", "));
+    fn from_args(args: &Args) -> Result<Self> {
+        let header = match &args.header_from {
+            Some(path) => {
+                let header = read_header_file(path)?;
+                info!(
+                    "Using header from {}: {}",
+                    path.display(),
+                    header.join(", ")
+                );
+                Some(header)
+            }
+            None => None,
+        };
 
-                // Map rows from this file's header to the merged header
-                let mapped_rows = map_rows_to_header(&data[0], &merged_header, &data[1..]);
-                all_data.extend(mapped_rows);
+        let aliases = match &args.alias_map {
+            Some(path) => {
+                let aliases = read_alias_map(path, args.alias_min_confidence)?;
+                info!("Loaded {} header aliases from {}", aliases.len(), path.display());
+                aliases
             }
+            None => BTreeMap::new(),
+        };
 
-            write_combined_csv(&output_filename, &merged_header, &all_data)?;
-            info!(
-                "Created: {} ({} files, {} data rows)",
-                output_filename,
-                group.len(),
-                all_data.len()
-            );
-            files_created += 1;
-        }
+        Ok(Self {
+            header,
+            aliases,
+            mmap: args.mmap,
+            detect_header: args.detect_header,
+            skip_header_rows: args.skip_header_rows,
+            data_starts_after: args.data_starts_after.clone(),
+            skip_footer_rows: args.skip_footer_rows,
+            read_columns: args.read_columns.clone(),
+            normalize_unicode: args.normalize_unicode,
+            header_style: args.normalize_headers.filter(|_| args.normalize_headers_on_read),
+            delimiter_map: args.delimiter_map.clone(),
+            auto_delimiter: args.auto_delimiter,
+            skip_bad_rows: args.skip_bad_rows,
+            validate_content: args.validate_content,
+            sheet_pattern: args.sheet_pattern.clone(),
+            sheet_pattern_mode: args.sheet_pattern_mode,
+            combine_sheets: args.combine_sheets_within_file,
+            preserve_excel_formatting: args.preserve_excel_formatting,
+            excel_date_system: args.excel_date_system,
+            drop_index_column: args.drop_index_column,
+            split_on_blank_lines: args.split_on_blank_lines,
+            transpose: args.transpose.then(|| {
+                (
+                    args.transpose_key_column.clone(),
+                    args.transpose_value_column.clone(),
+                )
+            }),
+        })
     }
+}
 
-    info!("Processing complete! Created {} output files", files_created);
-    pause!("All CSV files have been processed successfully, press enter to continue.");
+/// Settings that control how combined output files are written
+#[derive(Debug, Default, Clone)]
+struct WriteOptions {
+    /// How each CSV is written (from `--line-terminator`, `--output-comment`,
+    /// `--output-encoding`, `--strict` and `--write-buffer-size`)
+    csv: CsvWriteOptions,
+    /// Write groups as JSON lines instead of CSV (from `--format jsonl`)
+    jsonl: bool,
+    /// Leave empty cells out of JSON lines (from `--jsonl-omit-empty`)
+    omit_empty: bool,
+    /// Data rows per output part (from `--max-rows-per-file`)
+    max_rows: Option<usize>,
+}
 
-    Ok(())
+impl WriteOptions {
+    fn from_args(args: &Args) -> Self {
+        let comment = args
+            .output_comment
+            .iter()
+            .flat_map(|text| text.lines())
+            .map(|line| format!("{} {}", args.comment_char, line))
+            .collect();
+
+        Self {
+            csv: CsvWriteOptions {
+                terminator: args.line_terminator,
+                comment,
+                encoding: args.output_encoding,
+                lossy_encoding: args.output_encoding_lossy,
+                strict: args.strict,
+                buffer_size: args.write_buffer_size.map(|bytes| bytes as usize),
+            },
+            jsonl: args.format == OutputFormat::Jsonl,
+            omit_empty: args.jsonl_omit_empty,
+            max_rows: args.max_rows_per_file.map(|rows| rows as usize),
+        }
+    }
 }
 
-fn generate_header_hash(header: &[String]) -> String {
-    let mut hasher = DefaultHasher::new();
-    header.hash(&mut hasher);
-    format!("{:x}", hasher.finish())
+fn parse_terminator(value: &str) -> Result<csv::Terminator, String> {
+    match value.to_lowercase().as_str() {
+        "lf" | "\\n" => Ok(csv::Terminator::Any(b'\n')),
+        "crlf" | "\\r\\n" => Ok(csv::Terminator::CRLF),
+        "cr" | "\\r" => Ok(csv::Terminator::Any(b'\r')),
+        _ if value.len() == 1 => Ok(csv::Terminator::Any(value.as_bytes()[0])),
+        _ => Err(format!(
+            "expected lf, crlf, cr or a single ASCII character, got '{}'",
+            value
+        )),
+    }
 }
 
-fn headers_are_compatible(header1: &[String], header2: &[String]) -> bool {
-    let set1: HashSet<&String> = header1.iter().collect();
-    let set2: HashSet<&String> = header2.iter().collect();
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(args.error_log.as_deref())?;
+    let s3_target = args.output.as_deref().and_then(s3::S3Target::parse).transpose()?;
+    if s3_target.is_some() && args.format == OutputFormat::XlsxWorkbook {
+        return Err(anyhow::anyhow!("--format xlsx-workbook cannot be written to an S3 --output"));
+    }
+    if s3_target.is_some() && args.lock {
+        warn!("--lock guards a local output directory, it has no effect with an S3 --output");
+    }
+    // Combined outputs for an S3 --output are written here, removed when main returns
+    let staging = s3_target.as_ref().map(|_| s3::staging_dir()).transpose()?;
+    // Held until main returns, dropping it releases the lock
+    let _lock = if args.lock && s3_target.is_none() {
+        Some(lock::acquire(&output_base_dir(&args), args.lock_wait)?)
+    } else {
+        None
+    };
 
-    let intersection: HashSet<_> = set1.intersection(&set2).collect();
-    let union: HashSet<_> = set1.union(&set2).collect();
+    let read_options = ReadOptions::from_args(&args)?;
+    let write_options = WriteOptions::from_args(&args);
+    let lookups = args.lookup.iter().map(Lookup::load).collect::<Result<Vec<_>>>()?;
 
-    if union.is_empty() {
-        return false;
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if args.sample_fraction.is_some() || args.sample_n.is_some() {
+        info!("Sampling rows with seed {}", seed);
     }
 
-    let overlap_percentage = (intersection.len() as f64) / (union.len() as f64);
+    let input_path = args.input.clone().unwrap_or(
+        std::env::current_dir().unwrap_or(PathBuf::from("./")),
+    );
 
-    // Files are compatible if they have >= 50% overlap
-    overlap_percentage >= 0.5
-}
+    let mut profiler = Profiler::new();
+    let phase_started = Instant::now();
+    let mut files = match &args.input_list {
+        Some(list) => {
+            info!("Reading file list: {}", list.display());
+            read_input_list(list)?
+        }
+        None => {
+            info!("Searching for files in: {}", input_path.display());
+            get_files(&input_path)?
+        }
+    };
+    // Reassembled copies are removed with their temporary directory when this goes out of scope
+    let reassembled = if args.reassemble_split {
+        let sets = split::find_split_sets(&input_path)?;
+        let reassembled = split::reassemble(&sets)?;
+        files.extend(reassembled.logical_files().map(Path::to_path_buf));
+        Some(reassembled)
+    } else {
+        None
+    };
+    // An explicit list keeps its order unless a sort is asked for
+    let order = match (&args.input_list, args.sort_files_by) {
+        (Some(_), None) => None,
+        (_, order) => Some(order.unwrap_or(FileOrder::Name)),
+    };
+    if let Some(order) = order {
+        sort_files(&mut files, order, args.sort_desc);
+    }
+    if let Some(n) = args.first_n_files
+        && files.len() > n
+    {
+        info!("Limiting to the first {} of {} files (--first-n-files)", n, files.len());
+        files.truncate(n);
+    }
+    profiler.add("discovery", phase_started.elapsed());
+    info!("Found {} files to process", files.len());
+
+    if files.is_empty() {
+        let searched = args.input_list.as_deref().unwrap_or(&input_path);
+        return no_files_found(searched, &args);
+    }
 
-fn merge_headers(headers: &[Vec<String>]) -> Vec<String> {
-    let mut merged = Vec::new();
-    let mut seen = HashSet::new();
+    // Read all file headers and contents
+    let phase_started = Instant::now();
+    let mut file_data: Vec<(PathBuf, Vec<Vec<String>>)> = Vec::new();
+    let mut file_entries: Vec<FileEntry> = Vec::new();
+    let mut throughput = args
+        .progress_interval
+        .map(|secs| ThroughputLogger::new(Duration::from_secs(secs)));
+    let mut cache = args
+        .cache
+        .then(|| FileCache::load(output_base_dir(&args), &format!("{:?}", read_options)));
+    let timeout = args.timeout_per_file.map(Duration::from_secs);
 
-    // Add all unique headers while preserving order
-    // Start with the first header to maintain column order preference
-    for header_set in headers {
-        for col in header_set {
-            if !seen.contains(col) {
-                seen.insert(col.clone());
-                merged.push(col.clone());
+    for file_path in files {
+        info!("Reading: {}", file_path.display());
+        let read_started = Instant::now();
+        // A reassembled split set is named after its parts but read from a temporary copy
+        let read_path = reassembled.as_ref().map_or(file_path.as_path(), |r| r.read_path(&file_path));
+        // Temporary copies would only fill the cache with entries never hit again
+        let cacheable = read_path == file_path;
+        // Split files hold several tables, which the cache cannot store
+        let contents = if read_options.split_on_blank_lines && is_csv_path(&file_path) {
+            read_within(timeout, read_path, &read_options, read_csv_blocks)
+        } else {
+            match cache.as_ref().filter(|_| cacheable).and_then(|cache| cache.get(&file_path)) {
+                Some(data) => {
+                    debug!("Using cached contents for {}", file_path.display());
+                    Ok(data)
+                }
+                None => {
+                    let contents = read_within(timeout, read_path, &read_options, |path, options| {
+                        get_file_contents(path, options)
+                    });
+                    if let (Some(cache), Ok(data), true) = (&mut cache, &contents, cacheable)
+                        && let Err(e) = cache.put(&file_path, data)
+                    {
+                        warn!("Failed to cache {}: {}", file_path.display(), e);
+                    }
+                    contents
+                }
+            }
+            .map(|data| vec![data])
+        };
+        profiler.add_file(
+            source_name(&file_path, &input_path, args.relative_paths),
+            read_started.elapsed(),
+        );
+        if let Some(throughput) = &mut throughput {
+            let rows = contents.as_ref().map_or(0, |tables| {
+                tables.iter().map(|data| data.len().saturating_sub(1)).sum()
+            });
+            debug!(
+                "Read {} rows from {} in {:.2?}",
+                rows,
+                file_path.display(),
+                read_started.elapsed()
+            );
+            throughput.record(rows);
+        }
+        // Of several tables in one file, an included one decides its status
+        let (status, detail) = match contents {
+            Ok(tables) => {
+                let mut outcome = (FileStatus::Empty, "no header row".to_string());
+                for mut data in tables {
+                    // Header-only files are kept so their schema still produces output
+                    if data.first().is_none_or(|header| header.is_empty()) {
+                        warn!("File is empty: {}", file_path.display());
+                        continue;
+                    }
+                    if let Some(reason) = header_rejection(&data[0], &args) {
+                        warn!("Skipping {}: {}", file_path.display(), reason);
+                        if outcome.0 != FileStatus::Included {
+                            outcome = (FileStatus::Rejected, reason);
+                        }
+                        continue;
+                    }
+                    if let Some(n) = args.tail_rows {
+                        keep_tail_rows(&mut data, n);
+                    }
+                    file_data.push((file_path.clone(), data));
+                    outcome = (FileStatus::Included, String::new());
+                }
+                outcome
+            }
+            Err(e) => {
+                warn!("Failed to read file {}: {}", file_path.display(), e);
+                match e.downcast_ref::<UnsupportedFile>() {
+                    Some(_) => (FileStatus::Unsupported, e.to_string()),
+                    None => (FileStatus::Unreadable, format!("{:#}", e)),
+                }
             }
+        };
+        if args.include_empty_files_in_manifest {
+            file_entries.push(FileEntry {
+                source: source_name(&file_path, &input_path, args.relative_paths),
+                status,
+                detail,
+            });
         }
     }
 
-    merged
-}
+    if let Some(throughput) = &throughput {
+        throughput.finish();
+    }
 
-fn map_rows_to_header(
-    old_header: &[String],
-    new_header: &[String],
-    rows: &[Vec<String>],
-) -> Vec<Vec<String>> {
-    // Create a mapping from old column names to their indices
-    let old_col_map: HashMap<&String, usize> = old_header
-        .iter()
-        .enumerate()
-        .map(|(idx, col)| (col, idx))
-        .collect();
+    if let Some(cache) = &cache
+        && let Err(e) = cache.save()
+    {
+        warn!("Failed to save the file cache: {}", e);
+    }
+    profiler.add("reading", phase_started.elapsed());
 
-    let mut mapped_rows = Vec::new();
+    let phase_started = Instant::now();
+    if let Some(casing) = args.canonical_casing {
+        canonicalize_casing(file_data.iter_mut().map(|(_, data)| &mut data[0]), casing);
+    }
 
-    for row in rows {
-        let mut new_row = vec![String::new(); new_header.len()];
+    // Group files by header compatibility (>= --overlap-threshold, 50% by default)
+    let headers: Vec<Vec<String>> = file_data.iter().map(|(_, data)| data[0].clone()).collect();
+
+    if let Some(path) = &args.emit_headers_csv {
+        let names: Vec<String> = file_data
+            .iter()
+            .map(|(path, _)| source_name(path, &input_path, args.relative_paths))
+            .collect();
+        let header = ["file_path", "column_index", "column_name"].map(String::from);
+        write_combined_csv(path, &header, &long_headers(&names, &headers), &CsvWriteOptions::default())?;
+        info!("Wrote the headers of {} files to {}", headers.len(), path.display());
+    }
 
-        for (new_idx, col_name) in new_header.iter().enumerate() {
-            if let Some(&old_idx) = old_col_map.get(col_name) {
-                if old_idx < row.len() {
-                    new_row[new_idx] = row[old_idx].clone();
-                }
-            }
-            // If column doesn't exist in old header, leave it as empty string
+    if let Some(graph_path) = &args.dry_run_graph {
+        let names: Vec<String> = file_data
+            .iter()
+            .map(|(path, _)| source_name(path, &input_path, args.relative_paths))
+            .collect();
+        std::fs::write(
+            graph_path,
+            compatibility_dot(
+                &names,
+                &headers,
+                args.overlap_threshold,
+                args.min_shared_columns,
+                args.merge_strategy,
+                args.similarity_metric,
+            ),
+        )?;
+        info!("Wrote compatibility graph to {}", graph_path.display());
+        return Ok(());
+    }
+
+    if let Some(path) = &args.header_synonyms_learn {
+        let suggestions = suggest_aliases(&headers, DEFAULT_SYNONYM_SIMILARITY);
+        write_alias_suggestions(path, &suggestions)?;
+        info!(
+            "Wrote {} suggested aliases to {}, review them before using it as --alias-map",
+            suggestions.len(),
+            path.display()
+        );
+        return Ok(());
+    }
+
+    if args.suggest_threshold {
+        for line in threshold_report(&headers, args.min_shared_columns, args.merge_strategy, args.similarity_metric) {
+            info!("{}", line);
+        }
+        return Ok(());
+    }
+
+    let groups = group_files_by(
+        &headers,
+        args.overlap_threshold,
+        args.min_shared_columns,
+        args.merge_strategy,
+        args.similarity_metric,
+    );
+    profiler.add("grouping", phase_started.elapsed());
+
+    info!("Found {} compatible header groups", groups.len());
+    let groups = if args.only_groups.is_empty() {
+        groups
+    } else {
+        let selected = select_groups(groups, &headers, &args.only_groups, args.column_order);
+        info!("Combining {} groups selected by --only-groups", selected.len());
+        selected
+    };
+
+    if args.count_only {
+        let mut total_rows = 0;
+        for group in &groups {
+            let header_hash = group_hash(&headers, group, args.column_order);
+            let rows = count_data_rows(group.iter().map(|&file_idx| &file_data[file_idx].1));
+            info!("Group {}: {} files, {} data rows", header_hash, group.len(), rows);
+            total_rows += rows;
+        }
+        info!(
+            "Total: {} groups, {} files, {} data rows",
+            groups.len(),
+            file_data.len(),
+            total_rows
+        );
+        if args.profile {
+            profiler.report();
         }
+        return Ok(());
+    }
 
-        mapped_rows.push(new_row);
+    if let Some(max_output_files) = args.max_output_files
+        && groups.len() > max_output_files
+    {
+        return Err(anyhow::anyhow!(
+            "{} files form {} groups, more than --max-output-files {}. \
+             Try a lower --overlap-threshold than {} (see --suggest-threshold)",
+            file_data.len(),
+            groups.len(),
+            max_output_files,
+            args.overlap_threshold
+        ));
     }
 
-    mapped_rows
-}
+    if args.expect_single_group && groups.len() > 1 {
+        let names: Vec<String> = file_data
+            .iter()
+            .map(|(path, _)| source_name(path, &input_path, args.relative_paths))
+            .collect();
+        return Err(anyhow::anyhow!(
+            "{}",
+            single_group_error(&groups, &names, &headers).join("\n")
+        ));
+    }
 
-fn get_file_contents(path: impl AsRef<Path>) -> Result<Vec<Vec<String>>> {
-    let path = path.as_ref();
-    if let Some(extension) = path.extension() {
-        return match extension.to_string_lossy().to_lowercase().as_ref() {
-            "csv" => read_csv_file(path),
-            "xlsx" | "xls" | "xlsm" | "xlsb" | "ods" => read_excel_file(path),
-            _ => Err(anyhow::anyhow!(
-                "Unsupported file extension: {:?}",
-                extension
-            )),
+    if args.diff.is_some() && groups.len() > 1 {
+        return Err(anyhow::anyhow!(
+            "--diff compares a single output, but the files form {} groups",
+            groups.len()
+        ));
+    }
+    if args.xlsx_template.is_some() && groups.len() > 1 {
+        return Err(anyhow::anyhow!(
+            "--xlsx-template takes a single output, but the files form {} groups",
+            groups.len()
+        ));
+    }
+
+    let groups = if args.interactive {
+        let plans: Vec<String> = groups
+            .iter()
+            .map(|group| {
+                let group_headers: Vec<Vec<String>> = group.iter().map(|&idx| headers[idx].clone()).collect();
+                let merged = merge_headers_by(&group_headers, args.column_order);
+                let names: Vec<String> = group
+                    .iter()
+                    .map(|&idx| source_name(&file_data[idx].0, &input_path, args.relative_paths))
+                    .collect();
+                format!("files: {}\n  merged header: {}", names.join(", "), merged.join(", "))
+            })
+            .collect();
+        if !args.yes && !std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+            return Err(anyhow::anyhow!(
+                "--interactive needs a terminal to ask on, pass --yes to approve every group"
+            ));
+        }
+        let answers = if args.yes {
+            for (idx, plan) in plans.iter().enumerate() {
+                info!("Group {} of {}: {}", idx + 1, plans.len(), plan);
+            }
+            Some(vec![true; plans.len()])
+        } else {
+            approve_groups(&plans, &mut std::io::stdin().lock(), &mut std::io::stdout())?
         };
+        let Some(answers) = answers else {
+            return Err(anyhow::anyhow!("Aborted at the --interactive prompt, nothing was written"));
+        };
+        let approved: Vec<Vec<usize>> = groups
+            .into_iter()
+            .zip(answers)
+            .filter_map(|(group, approved)| approved.then_some(group))
+            .collect();
+        info!("Combining {} approved groups", approved.len());
+        approved
+    } else {
+        groups
+    };
+
+    let output_dir = match (args.format, &args.output, &staging) {
+        (_, _, Some(staging)) => staging.path().to_path_buf(),
+        (OutputFormat::Csv | OutputFormat::Jsonl, Some(dir), None) => {
+            std::fs::create_dir_all(dir)?;
+            dir.clone()
+        }
+        _ => PathBuf::new(),
+    };
+    match args.format {
+        _ if args.max_memory.is_none() => {}
+        OutputFormat::XlsxWorkbook => {
+            warn!("--max-memory has no effect with --format xlsx-workbook, sheets are built in memory")
+        }
+        OutputFormat::Jsonl => warn!("--max-memory has no effect with --format jsonl"),
+        OutputFormat::Csv => {}
     }
-    Err(anyhow::Error::msg("File has no extension"))
-}
+    if !args.hash_columns.is_empty() && args.hash_salt.is_none() {
+        warn!("--hash-columns without --hash-salt: short values such as SSNs can be recovered by hashing guesses");
+    }
+    if args.max_rows_per_file.is_some() && args.format == OutputFormat::XlsxWorkbook {
+        warn!("--max-rows-per-file has no effect with --format xlsx-workbook");
+    }
+    let mut workbook = match args.format {
+        OutputFormat::XlsxWorkbook => Some(WorkbookWriter::new()),
+        OutputFormat::Csv | OutputFormat::Jsonl => None,
+    };
+
+    let table_order = match (&args.match_table_order, &args.strict_schema) {
+        (Some(path), _) => {
+            let headers = file_data.iter().map(|(_, data)| data[0].as_slice());
+            Some(load_table_order(path, headers, args.allow_missing)?)
+        }
+        (None, Some(path)) => Some(read_reference_header(path)?),
+        (None, None) => None,
+    };
 
-fn read_csv_file(path: impl AsRef<Path>) -> Result<Vec<Vec<String>>> {
-    let mut reader = csv::Reader::from_path(path)?;
-    let mut data = Vec::new();
+    // Process each group
+    let context = GroupContext {
+        args: &args,
+        file_data: &file_data,
+        input_path: &input_path,
+        lookups: &lookups,
+        write_options: &write_options,
+        output_dir: &output_dir,
+        table_order: table_order.as_deref(),
+        run_date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+        used_names: Mutex::new(HashSet::new()),
+    };
+    let mut outputs = RunOutputs::default();
+    if args.threads != 1 && groups.len() > 1 && workbook.is_none() {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(args.threads).build()?;
+        info!("Processing {} groups on {} threads", groups.len(), pool.current_num_threads());
+        let processed = pool.install(|| {
+            groups
+                .par_iter()
+                .enumerate()
+                .map(|(idx, group)| {
+                    let mut group_outputs = RunOutputs::default();
+                    let mut group_profiler = Profiler::new();
+                    logging::set_group(Some(group_hash(&headers, group, args.column_order)));
+                    let result = process_group(
+                        &context,
+                        group,
+                        None,
+                        &mut group_rng(seed, idx),
+                        &mut group_profiler,
+                        &mut group_outputs,
+                    );
+                    logging::set_group(None);
+                    result.map(|()| (group_outputs, group_profiler))
+                })
+                .collect::<Result<Vec<_>>>()
+        })?;
+        // Collected in group order, so outputs are listed as in a sequential run
+        for (group_outputs, group_profiler) in processed {
+            outputs.extend(group_outputs);
+            profiler.merge(group_profiler);
+        }
+    } else {
+        if args.threads != 1 && workbook.is_some() {
+            warn!("--threads has no effect with --format xlsx-workbook, sheets are added in order");
+        }
+        for (idx, group) in groups.iter().enumerate() {
+            process_group(
+                &context,
+                group,
+                workbook.as_mut(),
+                &mut group_rng(seed, idx),
+                &mut profiler,
+                &mut outputs,
+            )?;
+        }
+    }
+    let RunOutputs {
+        files_created,
+        summaries,
+        schemas,
+        dedup_report,
+        mut output_files,
+        single_output,
+        unmatched_report,
+        written_groups,
+        merged_headers,
+        dictionary,
+        ..
+    } = outputs;
+
+    if let (Some(old_path), Some((header, rows))) = (&args.diff, &single_output) {
+        let changes = diff::diff_rows(&diff::read_previous(old_path, header)?, rows);
+        let base_dir = output_base_dir(&args);
+        write_combined_csv(base_dir.join("added.csv"), header, &changes.added, &write_options.csv)?;
+        write_combined_csv(base_dir.join("removed.csv"), header, &changes.removed, &write_options.csv)?;
+        info!(
+            "{} rows added and {} rows removed since {}, written to added.csv and removed.csv",
+            changes.added.len(),
+            changes.removed.len(),
+            old_path.display()
+        );
+    }
 
-    // Read and include the header
-    if let Ok(headers) = reader.headers() {
-        let header_row: Vec<String> = headers.iter().map(|s| s.to_string()).collect();
-        data.push(header_row);
+    if let (Some(template_path), Some((header, rows))) = (&args.xlsx_template, &single_output) {
+        let name = format!("{}_combined.xlsx", template_path.file_stem().unwrap_or_default().to_string_lossy());
+        let output_path = output_base_dir(&args).join(name);
+        let anchor = args.template_anchor.clone().unwrap_or_default();
+        template::write_into_template(template_path, &output_path, &anchor, header, rows)?;
+        info!("Wrote {} data rows into a copy of {}: {}", rows.len(), template_path.display(), output_path.display());
     }
 
-    // Read all data rows
-    for result in reader.records() {
-        let record = result?;
-        let row: Vec<String> = record.iter().map(|s| s.to_string()).collect();
-        data.push(row);
+    if args.self_check {
+        if args.format == OutputFormat::Csv {
+            self_check(&args, &written_groups)?;
+        } else {
+            warn!("--self-check only reads back CSV outputs, skipping it");
+        }
     }
 
-    Ok(data)
-}
+    if let Some(format) = args.archive
+        && !output_files.is_empty()
+    {
+        let archive_path = output_dir.join(format!("combined.{}", format.extension()));
+        archive::pack(&archive_path, format, &output_files)?;
+        info!("Packed {} output files into {}", output_files.len(), archive_path.display());
+        // The packed files are removed, the archive replaces them
+        output_files = vec![archive_path];
+    }
 
-fn read_excel_file(path: impl AsRef<Path>) -> Result<Vec<Vec<String>>> {
-    let mut workbook = open_workbook_auto(path.as_ref())?;
+    if let Some(workbook) = workbook {
+        let phase_started = Instant::now();
+        let workbook_path = args
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("combined.xlsx"));
+        workbook.save(&workbook_path)?;
+        profiler.add("writing", phase_started.elapsed());
+        info!(
+            "Created workbook: {} ({} sheets)",
+            workbook_path.display(),
+            files_created
+        );
+    }
 
-    // Get the first sheet
-    let sheet_names = workbook.sheet_names().to_vec();
-    if sheet_names.is_empty() {
-        return Err(anyhow::anyhow!("Excel file has no sheets"));
+    if args.dedup {
+        info!("--dedup removed {} duplicate rows", dedup_report.len());
+    }
+    if args.report_unmatched_columns {
+        info!("{} merged columns are found in only one file of their group", unmatched_report.len());
+        for line in &unmatched_report {
+            info!("  {}", line);
+        }
+    }
+    if let Some(path) = &args.dedup_report {
+        let header = ["output", "source_file", "header", "row"].map(String::from);
+        write_combined_csv(path, &header, &dedup_report, &CsvWriteOptions::default())?;
+        info!("Wrote removed duplicate rows to {}", path.display());
     }
 
-    let sheet_name = &sheet_names[0];
-    info!("Reading sheet: {}", sheet_name);
+    if let Some(path) = &args.schemas_out {
+        let header = ["hash".to_string(), "header".to_string()];
+        write_combined_csv(path, &header, &schemas, &CsvWriteOptions::default())?;
+        info!("Wrote {} merged schemas to {}", schemas.len(), path.display());
+    }
+    if let Some(path) = &args.data_dictionary {
+        let header = schema::DICTIONARY_HEADER.map(String::from);
+        write_combined_csv(path, &header, &dictionary, &CsvWriteOptions::default())?;
+        info!("Wrote a data dictionary of {} columns to {}", dictionary.len(), path.display());
+    }
+    if let Some(path) = &args.save_schema {
+        for path in save_schemas(path, &merged_headers)? {
+            info!("Saved merged header to {}", path.display());
+        }
+    }
 
-    let range = workbook.worksheet_range(sheet_name)?;
+    if let Some(ReportFormat::Html) = args.combine_report {
+        let report_path = output_base_dir(&args).join("combine_report.html");
+        std::fs::write(&report_path, report::render_html(&summaries, &file_entries))?;
+        info!("Wrote run report to {}", report_path.display());
+    }
 
-    let mut data = Vec::new();
-    for row in range.rows() {
-        let row_data: Vec<String> = row.iter().map(|cell| cell.to_string()).collect();
-        data.push(row_data);
+    if let Some(target) = &s3_target {
+        let phase_started = Instant::now();
+        s3::upload_files(&output_dir, &output_files, target).await?;
+        profiler.add("writing", phase_started.elapsed());
     }
 
-    Ok(data)
+    info!("Processing complete! Created {} output files", files_created);
+    if args.profile {
+        profiler.report();
+    }
+    pause!("All CSV files have been processed successfully, press enter to continue.");
+
+    Ok(())
 }
 
-fn write_combined_csv(output_path: &str, header: &[String], data: &[Vec<String>]) -> Result<()> {
-    let mut writer = csv::Writer::from_path(output_path)?;
+/// Inputs shared by every group of a run
+struct GroupContext<'a> {
+    args: &'a Args,
+    file_data: &'a [(PathBuf, Vec<Vec<String>>)],
+    input_path: &'a Path,
+    lookups: &'a [Lookup],
+    write_options: &'a WriteOptions,
+    output_dir: &'a Path,
+    /// The `--match-table-order` or `--strict-schema` header every group is written with
+    table_order: Option<&'a [String]>,
+    run_date: String,
+    /// Output names taken so far, shared by groups processed in parallel
+    used_names: Mutex<HashSet<String>>,
+}
 
-    // Write header
-    writer.write_record(header)?;
+/// What processing the groups collects for the end of the run
+#[derive(Default)]
+struct RunOutputs {
+    files_created: usize,
+    summaries: Vec<GroupSummary>,
+    schemas: Vec<Vec<String>>,
+    dedup_report: Vec<Vec<String>>,
+    output_files: Vec<PathBuf>,
+    /// The only group's output, for --diff and --xlsx-template
+    single_output: Option<(Vec<String>, Vec<Vec<String>>)>,
+    unmatched_report: Vec<String>,
+    /// Output header and files of each group, for --self-check
+    written_groups: Vec<(Vec<String>, Vec<PathBuf>)>,
+    /// Hash and merged header of each group, for --save-schema
+    merged_headers: Vec<(String, Vec<String>)>,
+    /// Rows of the --data-dictionary
+    dictionary: Vec<Vec<String>>,
+}
 
-    // Write all data rows
-    for row in data {
-        writer.write_record(row)?;
+impl RunOutputs {
+    /// Appends what a later group collected
+    fn extend(&mut self, other: RunOutputs) {
+        self.files_created += other.files_created;
+        self.summaries.extend(other.summaries);
+        self.schemas.extend(other.schemas);
+        self.dedup_report.extend(other.dedup_report);
+        self.output_files.extend(other.output_files);
+        self.single_output = other.single_output.or(self.single_output.take());
+        self.unmatched_report.extend(other.unmatched_report);
+        self.written_groups.extend(other.written_groups);
+        self.merged_headers.extend(other.merged_headers);
+        self.dictionary.extend(other.dictionary);
     }
+}
 
-    writer.flush()?;
-    Ok(())
+/// The sampling RNG of the `idx`th group: its own stream of the run's seed, so a
+/// group samples the same rows however many groups run beside it
+fn group_rng(seed: u64, idx: usize) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(idx as u64);
+    rng
 }
 
-fn get_files(search_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
-    let search_path = search_path.as_ref();
-    if search_path.is_dir() {
-        let mut files = Vec::new();
-        for entry in walkdir::WalkDir::new(search_path) {
-            let entry = entry?;
-            let path = entry.path();
-            if is_path_valid(path) {
-                files.push(PathBuf::from(path));
+/// Merges, maps and writes one group. A group of one file is copied with its
+/// header run through the same steps; only groups of several files are joined.
+fn process_group(
+    context: &GroupContext,
+    group: &[usize],
+    workbook: Option<&mut WorkbookWriter>,
+    rng: &mut ChaCha8Rng,
+    profiler: &mut Profiler,
+    outputs: &mut RunOutputs,
+) -> Result<()> {
+    let GroupContext {
+        args,
+        file_data,
+        input_path,
+        lookups,
+        write_options,
+        output_dir,
+        table_order,
+        run_date,
+        used_names,
+    } = context;
+    let phase_started = Instant::now();
+    // Collect all headers from the group and merge them
+    let mut all_headers: Vec<Vec<String>> = Vec::new();
+    for &file_idx in group {
+        all_headers.push(file_data[file_idx].1[0].clone());
+    }
+
+    let merged_header = merge_headers_by(&all_headers, args.column_order);
+    if args.report_unmatched_columns && group.len() > 1 {
+        for (col, member, similar) in unmatched_columns(&all_headers) {
+            let source = source_name(&file_data[group[member]].0, input_path, args.relative_paths);
+            outputs.unmatched_report.push(match similar {
+                Some(other) => format!("'{}' only in {} (similar to '{}')", col, source, other),
+                None => format!("'{}' only in {}", col, source),
+            });
+        }
+    }
+    let header_hash = generate_header_hash(&merged_header);
+    let merged_header = match table_order {
+        Some(table_order) => {
+            let dropped: Vec<&String> = merged_header
+                .iter()
+                .filter(|col| !table_order.contains(col))
+                .collect();
+            if !dropped.is_empty() {
+                let dropped = dropped.iter().map(|col| col.as_str()).collect::<Vec<_>>().join(", ");
+                if args.strict_schema.is_some() {
+                    debug!("Dropping columns not in --strict-schema for group {}: {}", header_hash, dropped);
+                } else {
+                    warn!("Dropping columns not in the target table for group {}: {}", header_hash, dropped);
+                }
             }
+            table_order.to_vec()
         }
-        Ok(files)
-    } else if search_path.is_file() {
-        Ok(vec![search_path.to_path_buf()])
-    } else {
-        Err(anyhow::anyhow!(
-            "{:?} is not a file nor is a directory, I don't know how you got here?!?!?!?",
-            search_path
-        ))
+        None if args.keep_first_header_exact => {
+            let (first, dropped) = first_header_exact(&all_headers);
+            for (member, columns) in dropped {
+                let source = source_name(&file_data[group[member]].0, input_path, args.relative_paths);
+                warn!("Dropping columns of {} not in the first file's header: {}", source, columns.join(", "));
+            }
+            first
+        }
+        None => add_coalesce_targets(pin_columns(merged_header, &args.pin_columns), &args.coalesce),
+    };
+
+    // Joining one file would only apply --join-duplicates to it
+    let join_column = args
+        .join_on
+        .as_ref()
+        .filter(|key| group.len() > 1 && merged_header.contains(key));
+    if let (Some(key), None, true) = (&args.join_on, join_column, group.len() > 1) {
+        warn!("Join column '{}' is not in this group, stacking rows instead", key);
     }
-}
+    let join_prefixes = match (join_column, args.prefix_columns_with) {
+        (Some(key), Some(prefix_by)) => Some(join_prefixes(prefix_by, group, file_data, input_path, key)?),
+        _ => None,
+    };
+    let merged_header = match (join_column, &join_prefixes) {
+        (Some(key), Some(prefixes)) => {
+            let headers: Vec<(&str, Vec<String>)> = group
+                .iter()
+                .zip(prefixes)
+                .filter_map(|(&file_idx, prefix)| {
+                    let prefix = prefix.as_deref()?;
+                    Some((prefix, join::prefix_columns(&file_data[file_idx].1[0], key, prefix)))
+                })
+                .collect();
+            join::prefixed_header(&merged_header, key, &headers)
+        }
+        _ => merged_header,
+    };
 
-fn is_path_valid(file_path: impl AsRef<Path>) -> bool {
-    let file_path = file_path.as_ref();
-    if file_path.is_file()
-        && let Some(extension) = file_path.extension()
+    if let Some(max_columns) = args.max_columns
+        && merged_header.len() > max_columns
     {
-        let ext = extension.to_string_lossy().to_lowercase();
-        return matches!(
-            ext.as_ref(),
-            "csv" | "xlsx" | "xls" | "xlsm" | "xlsb" | "ods"
+        warn!(
+            "Merged header for group {} has {} columns, more than --max-columns {}",
+            header_hash,
+            merged_header.len(),
+            max_columns
         );
     }
-    false
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // Output-only renames never affect mapping, which uses the merged header
+    let output_header = finalize_header(args, &merged_header)?;
+    outputs.schemas.push(vec![header_hash.clone(), output_header.join("|")]);
+    outputs.merged_headers.push((header_hash.clone(), merged_header.clone()));
+    let mut seen_rows: HashSet<Vec<String>> = HashSet::new();
+    let dedup_columns = dedup_columns(&args.dedup_key, &merged_header);
+    // Rows carry the provenance columns from mapping onwards
+    let mut row_header = merged_header.clone();
+    row_header.extend(provenance_columns(args));
+    if let Some(predicate) = &args.where_filter {
+        for col in predicate.columns().into_iter().filter(|col| !row_header.iter().any(|h| h == col)) {
+            warn!("--where column '{}' is not in group {}, reading it as empty", col, header_hash);
+        }
+    }
+
+    let prefix = if group.len() == 1 { "single" } else { "combined" };
+    let output_filename = match &args.output_template {
+        Some(template) => {
+            let first_source = file_data[group[0]].0.file_stem().unwrap_or_default().to_string_lossy();
+            render_output_template(
+                template,
+                &[
+                    ("prefix", prefix.to_string()),
+                    ("hash", header_hash.clone()),
+                    ("count", group.len().to_string()),
+                    ("date", run_date.clone()),
+                    ("columns", output_header.len().to_string()),
+                    ("first_source", first_source.trim_end_matches(".csv").to_string()),
+                ],
+            )
+        }
+        None if args.name_by_dir => {
+            let dir = group_dir_name(group.iter().map(|&idx| file_data[idx].0.as_path()), input_path);
+            format!("{}_{}.csv", dir, header_hash)
+        }
+        None => format!("{}_{}.csv", prefix, header_hash),
+    };
+    let output_filename = {
+        // Only poisoned if another group panicked while naming its output
+        let mut used_names = used_names.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        unique_output_name(output_filename, &mut used_names)
+    };
+
+    info!(
+        "Processing group with merged headers: {} ({} files)",
+        merged_header.join(", "),
+        group.len()
+    );
+    if group.len() == 1 {
+        info!("Copying single file: {}", file_data[group[0]].0.display());
+    } else {
+        info!("Combining {} compatible files into: {}", group.len(), output_filename);
+    }
+
+    let join_key = join_column.and_then(|key| merged_header.iter().position(|col| col == key));
+
+    let mut all_data: Vec<Vec<String>> = Vec::new();
+    let mut collapsed_rows: Vec<Vec<String>> = Vec::new();
+    let mut join_inputs: Vec<(String, Vec<Vec<String>>)> = Vec::new();
+
+    // Spilling needs an output file and rows that can be finished independently
+    let mut spill = match (args.max_memory, args.format, join_key) {
+        (Some(budget), OutputFormat::Csv, None) => Some((
+            create_csv_writer(output_dir.join(&output_filename), &output_header, &write_options.csv)?,
+            budget,
+        )),
+        _ => None,
+    };
+    let mut buffered_bytes = 0;
+    let mut spilled_rows = 0;
+    let files = if group.len() == 1 { "file" } else { "files" };
+    // Files of the group with each merged column, for --data-dictionary
+    let mut source_counts = vec![0; merged_header.len()];
+
+    for (member, &file_idx) in group.iter().enumerate() {
+        let (file_path, data) = &file_data[file_idx];
+        if group.len() > 1 {
+            info!("  - Including: {} (headers: {})", file_path.display(), data[0].join(", "));
+        }
+
+        // Map rows from this file's header to the merged header
+        let source = source_name(file_path, input_path, args.relative_paths);
+        let file_header = match (join_column, join_prefixes.as_ref().and_then(|prefixes| prefixes[member].as_deref())) {
+            (Some(key), Some(prefix)) => join::prefix_columns(&data[0], key, prefix),
+            _ => data[0].clone(),
+        };
+        for (count, col) in source_counts.iter_mut().zip(&merged_header) {
+            *count += usize::from(file_header.contains(col));
+        }
+        let mut mapped_rows = map_rows_to_header(&file_header, &merged_header, &data[1..]);
+        add_provenance(args, &mut mapped_rows, &source);
+        filter_rows(args, &row_header, &mut mapped_rows);
+        if let Some(key_idx) = join_key {
+            if !data[0].contains(&merged_header[key_idx]) {
+                warn!(
+                    "Leaving {} out of the join: it has no '{}' column",
+                    file_path.display(),
+                    merged_header[key_idx]
+                );
+                continue;
+            }
+            join_inputs.push((source, mapped_rows));
+            continue;
+        }
+
+        if args.dedup {
+            let (kept, removed) = dedup_rows(mapped_rows, &dedup_columns, &args.null_values, &mut seen_rows);
+            report_duplicates(&mut outputs.dedup_report, &output_filename, &merged_header, &source, &removed);
+            if !args.aggregate.is_empty() {
+                collapsed_rows.extend(removed);
+            }
+            mapped_rows = kept;
+        }
+        buffered_bytes += estimate_row_bytes(&mapped_rows);
+        all_data.extend(mapped_rows);
+        if let Some((writer, budget)) = &mut spill
+            && buffered_bytes >= *budget
+        {
+            debug!("Spilling {} rows (~{} bytes) to {}", all_data.len(), buffered_bytes, output_filename);
+            spilled_rows += spill_rows(writer, args, lookups, &row_header, &header_hash, &mut all_data)?;
+            buffered_bytes = 0;
+        }
+    }
+
+    if let Some((mut writer, _)) = spill {
+        spilled_rows += spill_rows(&mut writer, args, lookups, &row_header, &header_hash, &mut all_data)?;
+        writer.flush()?;
+        let output_path = output_dir.join(&output_filename);
+        profiler.add("mapping", phase_started.elapsed());
+        info!(
+            "Created: {} ({} {}, {} data rows)",
+            output_path.display(),
+            group.len(),
+            files,
+            spilled_rows
+        );
+        outputs.written_groups.push((output_header, vec![output_path.clone()]));
+        outputs.output_files.push(output_path);
+        outputs.files_created += 1;
+        return Ok(());
+    }
+
+    if let Some(key_idx) = join_key {
+        let inputs: Vec<join::JoinInput> = join_inputs
+            .iter()
+            .map(|(name, rows)| join::JoinInput {
+                name: name.clone(),
+                rows,
+            })
+            .collect();
+        all_data = join::join_rows(
+            key_idx,
+            row_header.len(),
+            &inputs,
+            args.join_type,
+            args.join_duplicates,
+        )?;
+        info!(
+            "Joined {} files on '{}' into {} rows",
+            inputs.len(),
+            merged_header[key_idx],
+            all_data.len()
+        );
+    }
+
+    // Collapsed rows may belong to a key first seen in an earlier file
+    aggregate_duplicates(args, &merged_header, &dedup_columns, &mut all_data, &collapsed_rows);
+    apply_row_transforms(args, lookups, &row_header, &mut all_data);
+    let all_data = enforce_schema_and_hash(args, &output_filename, &row_header, all_data, write_options)?;
+    let mut all_data = sample_rows(all_data, args, rng);
+    if args.add_group_id {
+        add_group_id(&mut all_data, &header_hash);
+    }
+    profiler.add("mapping", phase_started.elapsed());
+    let phase_started = Instant::now();
+    let (output_name, paths) = write_output(
+        output_dir,
+        &output_filename,
+        &output_header,
+        &all_data,
+        write_options,
+        workbook,
+    )?;
+    outputs.written_groups.push((output_header.clone(), paths.clone()));
+    outputs.output_files.extend(paths);
+    write_schema(args, &output_filename, &output_header, &all_data)?;
+    if args.data_dictionary.is_some() {
+        let columns = &output_header[..merged_header.len()];
+        outputs
+            .dictionary
+            .extend(schema::data_dictionary(&output_name, columns, &source_counts, &all_data));
+    }
+    profiler.add("writing", phase_started.elapsed());
+    info!(
+        "Created: {} ({} {}, {} data rows)",
+        output_name,
+        group.len(),
+        files,
+        all_data.len()
+    );
+    if args.combine_report.is_some() {
+        let sources = group
+            .iter()
+            .map(|&file_idx| source_name(&file_data[file_idx].0, input_path, args.relative_paths))
+            .collect();
+        outputs.summaries.push(GroupSummary::new(output_name, &output_header, sources, &all_data));
+    }
+    if args.diff.is_some() || args.xlsx_template.is_some() {
+        outputs.single_output = Some((output_header, all_data));
+    }
+    outputs.files_created += 1;
+    Ok(())
+}
+
+/// Describes the pairwise overlap scores for `--suggest-threshold`: a histogram
+/// in tenths, and the threshold at the largest gap with the groups a run with it
+/// and the given grouping options produces
+fn threshold_report(
+    headers: &[Vec<String>],
+    min_shared: usize,
+    strategy: MergeStrategy,
+    metric: SimilarityMetric,
+) -> Vec<String> {
+    let Some(suggestion) = suggest_threshold(headers, metric) else {
+        return vec![format!(
+            "Not enough distinct overlap scores among {} files to suggest a threshold",
+            headers.len()
+        )];
+    };
+
+    let mut buckets = [0usize; 10];
+    for score in &suggestion.scores {
+        buckets[((score * 10.0) as usize).min(9)] += 1;
+    }
+
+    let mut lines = vec![format!(
+        "Overlap scores of {} file pairs:",
+        suggestion.scores.len()
+    )];
+    for (bucket, count) in buckets.iter().enumerate() {
+        lines.push(format!(
+            "  {:.1}-{:.1}: {:>5} {}",
+            bucket as f64 / 10.0,
+            (bucket + 1) as f64 / 10.0,
+            count,
+            "#".repeat((*count).min(60))
+        ));
+    }
+    lines.push(format!(
+        "Suggested --overlap-threshold {:.2} (largest gap: {:.2} to {:.2}), giving {} groups",
+        suggestion.threshold,
+        suggestion.gap.0,
+        suggestion.gap.1,
+        group_files_by(headers, suggestion.threshold, min_shared, strategy, metric).len()
+    ));
+    lines
+}
+
+/// Rows of `--emit-headers-csv`: each file's name, 1-based column index and
+/// column name, one row per column
+fn long_headers(names: &[String], headers: &[Vec<String>]) -> Vec<Vec<String>> {
+    names
+        .iter()
+        .zip(headers)
+        .flat_map(|(name, header)| {
+            header
+                .iter()
+                .enumerate()
+                .map(move |(idx, col)| vec![name.clone(), (idx + 1).to_string(), col.clone()])
+        })
+        .collect()
+}
+
+/// Hash of a group's merged header, which names its output and identifies it in logs
+fn group_hash(headers: &[Vec<String>], group: &[usize], column_order: ColumnOrder) -> String {
+    let group_headers: Vec<Vec<String>> = group.iter().map(|&idx| headers[idx].clone()).collect();
+    generate_header_hash(&merge_headers_by(&group_headers, column_order))
+}
+
+/// Keeps the groups whose header hash is in `only`, warning about hashes no group has
+fn select_groups(
+    groups: Vec<Vec<usize>>,
+    headers: &[Vec<String>],
+    only: &[String],
+    column_order: ColumnOrder,
+) -> Vec<Vec<usize>> {
+    let mut unused: Vec<&str> = only.iter().map(|hash| hash.trim()).collect();
+    let selected = groups
+        .into_iter()
+        .filter(|group| {
+            let hash = group_hash(headers, group, column_order);
+            let keep = only.iter().any(|only| only.trim() == hash);
+            unused.retain(|&only| only != hash);
+            keep
+        })
+        .collect();
+    for hash in unused {
+        warn!("--only-groups hash {} matches no group", hash);
+    }
+    selected
+}
+
+/// Describes why `--expect-single-group` failed: every file outside the largest
+/// group, with its header
+fn single_group_error(
+    groups: &[Vec<usize>],
+    names: &[String],
+    headers: &[Vec<String>],
+) -> Vec<String> {
+    // The first of the largest groups, so ties keep the group of the first file
+    let main_group = groups
+        .iter()
+        .rev()
+        .max_by_key(|group| group.len())
+        .map(|group| group.as_slice())
+        .unwrap_or_default();
+
+    let mut lines = vec![format!(
+        "{} files form {} groups, but --expect-single-group was given. \
+         Files incompatible with the {} file group of {}:",
+        names.len(),
+        groups.len(),
+        main_group.len(),
+        main_group.first().map_or("", |&idx| names[idx].as_str())
+    )];
+    for group in groups.iter().filter(|group| group.as_slice() != main_group) {
+        for &idx in group {
+            lines.push(format!("  {}: {}", names[idx], headers[idx].join(",")));
+        }
+    }
+    lines
+}
+
+/// Reads back the output CSVs of each written group and fails, listing every
+/// mismatch, unless grouping them again reproduces the written groups
+fn self_check(args: &Args, written: &[(Vec<String>, Vec<PathBuf>)]) -> Result<()> {
+    let mut names = Vec::new();
+    let mut headers = Vec::new();
+    let mut owners = Vec::new();
+    for (idx, (_, files)) in written.iter().enumerate() {
+        for file in files {
+            let mut reader = csv::ReaderBuilder::new()
+                .comment(args.output_comment.is_some().then_some(args.comment_char as u8))
+                .from_path(file)
+                .with_context(|| format!("--self-check could not read {}", file.display()))?;
+            headers.push(reader.byte_headers()?.iter().map(|col| String::from_utf8_lossy(col).into_owned()).collect());
+            names.push(file.file_name().unwrap_or_default().to_string_lossy().into_owned());
+            owners.push(idx);
+        }
+    }
+
+    let expected: Vec<Vec<String>> = written.iter().map(|(header, _)| header.clone()).collect();
+    let problems = regrouping_problems(&names, &headers, &owners, &expected, args);
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!("--self-check failed:\n  {}", problems.join("\n  ")));
+    }
+    info!("--self-check: {} output files re-group into the same {} groups", names.len(), written.len());
+    Ok(())
+}
+
+/// Groups output headers as the run grouped its inputs. `owners` is the written
+/// group of each output and `expected` the header of each written group.
+fn regrouping_problems(
+    names: &[String],
+    headers: &[Vec<String>],
+    owners: &[usize],
+    expected: &[Vec<String>],
+    args: &Args,
+) -> Vec<String> {
+    let regrouped = group_files_by(
+        headers,
+        args.overlap_threshold,
+        args.min_shared_columns,
+        args.merge_strategy,
+        args.similarity_metric,
+    );
+    let mut problems = Vec::new();
+    if regrouped.len() != expected.len() {
+        problems.push(format!(
+            "{} output files form {} groups, not the {} written",
+            names.len(),
+            regrouped.len(),
+            expected.len()
+        ));
+    }
+
+    for group in &regrouped {
+        let files: Vec<&str> = group.iter().map(|&idx| names[idx].as_str()).collect();
+        let owner = owners[group[0]];
+        if group.iter().any(|&idx| owners[idx] != owner) {
+            problems.push(format!("outputs of different groups group together: {}", files.join(", ")));
+            continue;
+        }
+        let group_headers: Vec<Vec<String>> = group.iter().map(|&idx| headers[idx].clone()).collect();
+        let merged = merge_headers_by(&group_headers, args.column_order);
+        if merged != expected[owner] {
+            problems.push(format!(
+                "{} merge into {} instead of the written {}",
+                files.join(", "),
+                merged.join(","),
+                expected[owner].join(",")
+            ));
+        }
+    }
+    problems
+}
+
+/// Asks about each `--interactive` plan in turn, returning whether each group is
+/// combined (proceed) or skipped, or `None` when the run is aborted or input ends
+fn approve_groups(
+    plans: &[String],
+    input: &mut impl std::io::BufRead,
+    output: &mut impl Write,
+) -> Result<Option<Vec<bool>>> {
+    let mut answers = Vec::new();
+    for (idx, plan) in plans.iter().enumerate() {
+        writeln!(output, "Group {} of {}: {}", idx + 1, plans.len(), plan)?;
+        loop {
+            write!(output, "[p]roceed, [s]kip this group or [a]bort? ")?;
+            output.flush()?;
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                return Ok(None);
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "p" | "proceed" | "y" | "yes" => answers.push(true),
+                "s" | "skip" | "n" | "no" => answers.push(false),
+                "a" | "abort" | "q" | "quit" => return Ok(None),
+                _ => continue,
+            }
+            break;
+        }
+    }
+    Ok(Some(answers))
+}
+
+/// The `--prefix-columns-with` prefix of each group member, `None` for files
+/// without the join key, which are left out of the join
+fn join_prefixes(
+    prefix_by: join::ColumnPrefix,
+    group: &[usize],
+    file_data: &[(PathBuf, Vec<Vec<String>>)],
+    input_path: &Path,
+    key: &str,
+) -> Result<Vec<Option<String>>> {
+    let mut prefixes: Vec<Option<String>> = Vec::new();
+    for &file_idx in group {
+        let (path, data) = &file_data[file_idx];
+        if !data[0].iter().any(|col| col == key) {
+            prefixes.push(None);
+            continue;
+        }
+        let prefix = match prefix_by {
+            join::ColumnPrefix::Filename => path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            join::ColumnPrefix::Source => source_name(&path.with_extension(""), input_path, true).replace(['/', '\\'], "_"),
+        };
+        if let Some(other) = prefixes.iter().position(|other| other.as_ref() == Some(&prefix)) {
+            let hint = match prefix_by {
+                join::ColumnPrefix::Filename => " (try --prefix-columns-with source)",
+                join::ColumnPrefix::Source => "",
+            };
+            return Err(anyhow::anyhow!(
+                "{} and {} both get the column prefix '{}'{}",
+                file_data[group[other]].0.display(),
+                path.display(),
+                prefix,
+                hint
+            ));
+        }
+        prefixes.push(Some(prefix));
+    }
+    Ok(prefixes)
+}
+
+/// Names a source file wherever its path is emitted: relative to the input
+/// directory with `--relative-paths`, otherwise as it was found
+fn source_name(path: &Path, input_path: &Path, relative: bool) -> String {
+    if !relative {
+        return path.display().to_string();
+    }
+
+    let root = if input_path.is_file() {
+        input_path.parent().unwrap_or(input_path)
+    } else {
+        input_path
+    };
+    match path.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.display().to_string(),
+        _ => path.display().to_string(),
+    }
+}
+
+/// Writes the `--emit-schema` file for an output, named after its data file
+fn write_schema(
+    args: &Args,
+    output_filename: &str,
+    header: &[String],
+    rows: &[Vec<String>],
+) -> Result<()> {
+    let Some(format) = args.emit_schema else {
+        return Ok(());
+    };
+
+    let table = output_filename.trim_end_matches(".csv");
+    let schema_path = output_base_dir(args).join(format!("{}.{}", table, format.extension()));
+    let types = schema::infer_types(header, rows);
+    std::fs::write(
+        &schema_path,
+        schema::render_schema(format, table, header, &types),
+    )?;
+    info!("Wrote schema: {}", schema_path.display());
+    Ok(())
+}
+
+/// Applies `--schema` to an output's rows, reporting bad values per column and
+/// handling their rows as `--on-type-error` says. Returns the rows to write.
+fn enforce_schema(
+    args: &Args,
+    output_filename: &str,
+    header: &[String],
+    rows: Vec<Vec<String>>,
+    options: &WriteOptions,
+) -> Result<Vec<Vec<String>>> {
+    if args.schema.is_empty() {
+        return Ok(rows);
+    }
+
+    let mut enforced = coerce::enforce_types(header, rows, &args.schema);
+    if enforced.rejected.is_empty() {
+        return Ok(enforced.accepted);
+    }
+    // Rejected values are no less sensitive than the written ones
+    hash_rejected(args, header, &mut enforced.rejected);
+    if args.on_type_error == OnTypeError::Fail {
+        let error = enforced.rejected[0].last().cloned().unwrap_or_default();
+        return Err(anyhow::anyhow!(
+            "{} rows of {} have values that do not match --schema, first: {}",
+            enforced.rejected.len(),
+            output_filename,
+            error
+        ));
+    }
+
+    for (col, failures) in enforced.failures.iter().filter(|(_, failures)| *failures > 0) {
+        warn!("{}: {} values in column '{}' could not be coerced", output_filename, failures, col);
+    }
+    match args.on_type_error {
+        OnTypeError::Reject => {
+            let rejects_path = output_base_dir(args).join(format!(
+                "{}_rejects.csv",
+                output_filename.trim_end_matches(".csv")
+            ));
+            let mut rejects_header = header.to_vec();
+            rejects_header.push("type_error".to_string());
+            write_combined_csv(&rejects_path, &rejects_header, &enforced.rejected, &options.csv)?;
+            warn!("Moved {} rows to {}", enforced.rejected.len(), rejects_path.display());
+        }
+        _ => warn!("Skipped {} rows of {}", enforced.rejected.len(), output_filename),
+    }
+    Ok(enforced.accepted)
+}
+
+/// Outcome of a run that found no inputs: a warning, or an error with `--halt-on-empty`
+fn no_files_found(input_path: &Path, args: &Args) -> Result<()> {
+    if args.halt_on_empty {
+        return Err(anyhow::anyhow!(
+            "No CSV or Excel files found in {}",
+            input_path.display()
+        ));
+    }
+    warn!("No CSV or Excel files found!");
+    Ok(())
+}
+
+/// Directory for run-level files such as the `--cache` index and `--combine-report`:
+/// the output directory, or the workbook's directory. With an S3 `--output` these
+/// stay in the current directory, only the combined outputs are uploaded.
+fn output_base_dir(args: &Args) -> PathBuf {
+    match (args.format, &args.output) {
+        (_, Some(url)) if s3::S3Target::parse(url).is_some() => PathBuf::new(),
+        (OutputFormat::Csv | OutputFormat::Jsonl, Some(dir)) => dir.clone(),
+        (OutputFormat::XlsxWorkbook, Some(path)) => {
+            path.parent().map(Path::to_path_buf).unwrap_or_default()
+        }
+        (_, None) => PathBuf::new(),
+    }
+}
+
+/// Writes one group's output as CSV or JSON lines files in `output_dir`, or as a
+/// sheet of the combined workbook. Returns the name it was written under and the
+/// files written: one, or one per `--max-rows-per-file` part.
+fn write_output(
+    output_dir: &Path,
+    output_filename: &str,
+    header: &[String],
+    rows: &[Vec<String>],
+    options: &WriteOptions,
+    workbook: Option<&mut WorkbookWriter>,
+) -> Result<(String, Vec<PathBuf>)> {
+    if let Some(workbook) = workbook {
+        let sheet_name = output_filename.trim_end_matches(".csv");
+        let sheet_name = workbook.add_sheet(sheet_name, header, rows)?;
+        return Ok((format!("sheet '{}'", sheet_name), Vec::new()));
+    }
+
+    let parts: Vec<(String, &[Vec<String>])> = match options.max_rows {
+        Some(max_rows) if rows.len() > max_rows => rows
+            .chunks(max_rows)
+            .enumerate()
+            .map(|(idx, chunk)| (part_filename(output_filename, idx + 1), chunk))
+            .collect(),
+        _ => vec![(output_filename.to_string(), rows)],
+    };
+
+    let mut paths = Vec::new();
+    for (filename, rows) in parts {
+        let output_path = output_dir.join(filename);
+        if options.jsonl {
+            let output_path = output_path.with_extension("jsonl");
+            write_jsonl(&output_path, header, rows, options.omit_empty)?;
+            paths.push(output_path);
+        } else {
+            write_combined_csv(&output_path, header, rows, &options.csv)?;
+            paths.push(output_path);
+        }
+    }
+
+    let name = match paths.as_slice() {
+        [first, .., last] => format!("{} to {}", first.display(), last.display()),
+        _ => paths[0].display().to_string(),
+    };
+    Ok((name, paths))
+}
+
+/// Names part `part` (from 1) of a `--max-rows-per-file` output
+fn part_filename(output_filename: &str, part: usize) -> String {
+    format!("{}_part{}.csv", output_filename.trim_end_matches(".csv"), part)
+}
+
+/// Writes rows as newline-delimited JSON objects keyed by `header`, in column order.
+/// Every value is a string; empty cells are left out when `omit_empty` is set.
+fn write_jsonl(
+    output_path: &Path,
+    header: &[String],
+    rows: &[Vec<String>],
+    omit_empty: bool,
+) -> Result<()> {
+    let context = || format!("Failed to write {}", output_path.display());
+    let mut file = std::io::BufWriter::new(std::fs::File::create(output_path).with_context(context)?);
+    for row in rows {
+        let object: serde_json::Map<String, serde_json::Value> = header
+            .iter()
+            .zip(row)
+            .filter(|(_, value)| !(omit_empty && value.is_empty()))
+            .map(|(col, value)| (col.clone(), serde_json::Value::String(value.clone())))
+            .collect();
+        serde_json::to_writer(&mut file, &object).with_context(context)?;
+        file.write_all(b"\n").with_context(context)?;
+    }
+    file.flush().with_context(context)?;
+    Ok(())
+}
+
+/// Columns found in only one of a group's headers, with the index of that header
+/// and the most similar column of the other headers, when one is close enough to
+/// suggest a typo
+fn unmatched_columns(headers: &[Vec<String>]) -> Vec<(String, usize, Option<String>)> {
+    let mut unmatched = Vec::new();
+    for (idx, header) in headers.iter().enumerate() {
+        // Reversed, as max_by keeps the last of equals, so ties go to the first-seen column
+        let others: Vec<&String> = headers
+            .iter()
+            .enumerate()
+            .filter(|(other_idx, _)| *other_idx != idx)
+            .flat_map(|(_, other)| other)
+            .rev()
+            .collect();
+        for col in header.iter().filter(|col| !others.contains(col)) {
+            let similar = others
+                .iter()
+                .filter(|other| !header.contains(other))
+                .map(|other| (*other, name_similarity(col, other)))
+                .filter(|(_, similarity)| *similarity >= DEFAULT_SYNONYM_SIMILARITY)
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(other, _)| other.clone());
+            unmatched.push((col.clone(), idx, similar));
+        }
+    }
+    unmatched
+}
+
+/// Drops rows failing `--where`, reading its columns by name from `header`
+fn filter_rows(args: &Args, header: &[String], rows: &mut Vec<Vec<String>>) {
+    let Some(predicate) = &args.where_filter else {
+        return;
+    };
+    let positions: HashMap<&str, usize> = header.iter().enumerate().map(|(idx, col)| (col.as_str(), idx)).collect();
+    let before = rows.len();
+    rows.retain(|row| {
+        predicate.matches(&|name| positions.get(name).and_then(|&idx| row.get(idx)).map_or("", String::as_str))
+    });
+    debug!("--where kept {} of {} rows", rows.len(), before);
+}
+
+/// Applies the value rewriting options to rows already mapped to the merged header
+fn apply_row_transforms(args: &Args, lookups: &[Lookup], header: &[String], rows: &mut [Vec<String>]) {
+    if !args.rtrim_columns.is_empty() {
+        rtrim_columns(header, rows, &args.rtrim_columns);
+    }
+    coalesce_columns(header, rows, &args.coalesce);
+    for group in &args.dedup_row_columns {
+        let columns: Vec<String> = group.split(',').map(|col| col.trim().to_string()).collect();
+        dedup_row_columns(header, rows, &columns);
+    }
+    for col in padded_columns(header, rows) {
+        info!(
+            "Column '{}' looks space-padded to a fixed width, consider --rtrim-columns \"{}\"",
+            col, col
+        );
+    }
+
+    if !args.normalize_datetime.is_empty() {
+        let failures = datetime::normalize_datetimes(
+            header,
+            rows,
+            &args.normalize_datetime,
+            args.datetime_format.as_deref(),
+        );
+        if failures > 0 {
+            warn!("{} datetime values could not be parsed and were left unchanged", failures);
+        }
+    }
+
+    for (col, unmatched) in lookup::apply_lookups(header, rows, lookups) {
+        if args.log_unmatched_lookups {
+            let values: Vec<&str> = unmatched.iter().map(|value| value.as_str()).collect();
+            warn!("No --lookup label for {} values of '{}': {}", values.len(), col, values.join(", "));
+        }
+    }
+}
+
+/// Applies `--hash-columns`, after every other transform and `--schema`, so the
+/// hash covers the value as it would have been written and types are checked
+/// on the source values rather than their digests
+fn apply_hash_columns(args: &Args, header: &[String], rows: &mut [Vec<String>]) {
+    if !args.hash_columns.is_empty() {
+        hash_columns(header, rows, &args.hash_columns, args.hash_salt.as_deref().unwrap_or_default());
+    }
+}
+
+/// Hashes the `--hash-columns` of rows `--schema` rejected, including the value
+/// their trailing type error quotes
+fn hash_rejected(args: &Args, header: &[String], rows: &mut [Vec<String>]) {
+    for row in rows.iter_mut() {
+        let Some(mut error) = row.pop() else {
+            continue;
+        };
+        let original = row.clone();
+        apply_hash_columns(args, header, std::slice::from_mut(row));
+        for (col, (before, after)) in header.iter().zip(original.iter().zip(row.iter())) {
+            if before != after {
+                error = error.replacen(&format!("{}: '{}'", col, before), &format!("{}: '{}'", col, after), 1);
+            }
+        }
+        row.push(error);
+    }
+}
+
+/// [`enforce_schema`] followed by [`apply_hash_columns`]
+fn enforce_schema_and_hash(
+    args: &Args,
+    output_filename: &str,
+    header: &[String],
+    rows: Vec<Vec<String>>,
+    options: &WriteOptions,
+) -> Result<Vec<Vec<String>>> {
+    let mut rows = enforce_schema(args, output_filename, header, rows, options)?;
+    apply_hash_columns(args, header, &mut rows);
+    Ok(rows)
+}
+
+/// Replaces the non-empty values of the named columns with the lowercase hex
+/// SHA-256 of `salt` followed by the value
+fn hash_columns(header: &[String], rows: &mut [Vec<String>], columns: &[String], salt: &str) {
+    let indices: Vec<usize> = header
+        .iter()
+        .enumerate()
+        .filter(|(_, col)| columns.contains(col))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    for row in rows.iter_mut() {
+        for &idx in &indices {
+            if let Some(value) = row.get_mut(idx).filter(|value| !value.is_empty()) {
+                let digest = Sha256::new().chain_update(salt).chain_update(value.as_bytes()).finalize();
+                *value = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+            }
+        }
+    }
+}
+
+/// Appends the `--coalesce` targets a merged header lacks, when it has one of their sources
+fn add_coalesce_targets(mut header: Vec<String>, rules: &[CoalesceRule]) -> Vec<String> {
+    for rule in rules {
+        if !header.contains(&rule.target) && rule.sources.iter().any(|col| header.contains(col)) {
+            header.push(rule.target.clone());
+        }
+    }
+    header
+}
+
+/// Fills each empty `--coalesce` target cell with the first non-empty value of its sources
+fn coalesce_columns(header: &[String], rows: &mut [Vec<String>], rules: &[CoalesceRule]) {
+    let position = |name: &String| header.iter().position(|col| col == name);
+    for rule in rules {
+        let Some(target) = position(&rule.target) else {
+            continue;
+        };
+        let sources: Vec<usize> = rule.sources.iter().filter_map(position).collect();
+
+        for row in rows.iter_mut() {
+            if row.get(target).is_none_or(|value| !value.trim().is_empty()) {
+                continue;
+            }
+            if let Some(value) = sources
+                .iter()
+                .filter_map(|&idx| row.get(idx))
+                .find(|value| !value.trim().is_empty())
+            {
+                row[target] = value.clone();
+            }
+        }
+    }
+}
+
+/// Blanks cells of `columns` that repeat a value an earlier one of them holds in
+/// the same row, so only the first copy is kept. Values are compared trimmed.
+fn dedup_row_columns(header: &[String], rows: &mut [Vec<String>], columns: &[String]) {
+    let indices: Vec<usize> = columns
+        .iter()
+        .filter_map(|name| header.iter().position(|col| col == name))
+        .collect();
+
+    for row in rows.iter_mut() {
+        let mut seen: Vec<String> = Vec::new();
+        for &idx in &indices {
+            let Some(cell) = row.get_mut(idx) else {
+                continue;
+            };
+            let value = cell.trim();
+            if value.is_empty() {
+                continue;
+            }
+            if seen.iter().any(|earlier| earlier == value) {
+                cell.clear();
+            } else {
+                seen.push(value.to_string());
+            }
+        }
+    }
+}
+
+/// Removes trailing spaces from the values of the named columns
+fn rtrim_columns(header: &[String], rows: &mut [Vec<String>], columns: &[String]) {
+    let indices: Vec<usize> = header
+        .iter()
+        .enumerate()
+        .filter(|(_, col)| columns.contains(col))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    for row in rows.iter_mut() {
+        for &idx in &indices {
+            if let Some(value) = row.get_mut(idx) {
+                value.truncate(value.trim_end_matches(' ').len());
+            }
+        }
+    }
+}
+
+/// Columns that look like fixed-width padding: every non-empty value has the
+/// same length and at least one ends in a space
+fn padded_columns<'a>(header: &'a [String], rows: &[Vec<String>]) -> Vec<&'a String> {
+    header
+        .iter()
+        .enumerate()
+        .filter(|&(idx, _)| {
+            let mut values = rows
+                .iter()
+                .filter_map(|row| row.get(idx))
+                .filter(|value| !value.is_empty());
+            let Some(first) = values.next() else {
+                return false;
+            };
+            let width = first.chars().count();
+            let mut padded = first.ends_with(' ');
+            let mut count = 1;
+            for value in values {
+                if value.chars().count() != width {
+                    return false;
+                }
+                padded |= value.ends_with(' ');
+                count += 1;
+            }
+            padded && count > 1
+        })
+        .map(|(_, col)| col)
+        .collect()
+}
+
+/// Reads the `--match-table-order` column list and checks that every column
+/// appears in at least one input header, unless `allow_missing` is set
+fn load_table_order<'a>(
+    path: &Path,
+    headers: impl IntoIterator<Item = &'a [String]>,
+    allow_missing: bool,
+) -> Result<Vec<String>> {
+    let table_order = read_header_file(path)?;
+    let present: std::collections::HashSet<&String> = headers.into_iter().flatten().collect();
+    let missing: Vec<&str> = table_order
+        .iter()
+        .filter(|col| !present.contains(col))
+        .map(String::as_str)
+        .collect();
+
+    if !missing.is_empty() {
+        if !allow_missing {
+            return Err(anyhow::anyhow!(
+                "Target table columns missing from every input: {} (use --allow-missing to add them empty)",
+                missing.join(", ")
+            ));
+        }
+        warn!("Adding empty target table columns: {}", missing.join(", "));
+    }
+    Ok(table_order)
+}
+
+/// Reads the `--strict-schema` header: the first row of a CSV, whose other rows are ignored
+fn read_reference_header(path: &Path) -> Result<Vec<String>> {
+    let mut reader = csv::ReaderBuilder::new().from_path(path)?;
+    let header: Vec<String> = reader.headers()?.iter().map(|col| col.trim().to_string()).collect();
+    if header.is_empty() || header.iter().any(|col| col.is_empty()) {
+        return Err(anyhow::anyhow!(
+            "Reference header {} contains no usable column names",
+            path.display()
+        ));
+    }
+    Ok(header)
+}
+
+/// Writes each merged header as a header-only CSV for `--save-schema`: to `path`
+/// when there is one group, otherwise to `<stem>_<hash>.csv` next to it
+fn save_schemas(path: &Path, headers: &[(String, Vec<String>)]) -> Result<Vec<PathBuf>> {
+    let mut saved = Vec::new();
+    for (hash, header) in headers {
+        let target = if headers.len() == 1 {
+            path.to_path_buf()
+        } else {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            path.with_file_name(format!("{}_{}.csv", stem, hash))
+        };
+        write_combined_csv(&target, header, &[], &CsvWriteOptions::default())
+            .with_context(|| format!("Failed to save the merged header to {}", target.display()))?;
+        saved.push(target);
+    }
+    Ok(saved)
+}
+
+/// The `--keep-first-header-exact` header, the first of `headers` verbatim, and
+/// the columns each later member has that it lacks, by member index
+fn first_header_exact(headers: &[Vec<String>]) -> (Vec<String>, Vec<(usize, Vec<String>)>) {
+    let first = headers.first().cloned().unwrap_or_default();
+    let dropped = headers
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(member, header)| {
+            let extra: Vec<String> = header.iter().filter(|col| !first.contains(col)).cloned().collect();
+            (member, extra)
+        })
+        .filter(|(_, extra)| !extra.is_empty())
+        .collect();
+    (first, dropped)
+}
+
+/// Moves the pinned columns to the front in the given order, adding any that
+/// are missing, and keeps the rest in merged order
+fn pin_columns(merged_header: Vec<String>, pinned: &[String]) -> Vec<String> {
+    if pinned.is_empty() {
+        return merged_header;
+    }
+
+    let mut header: Vec<String> = Vec::with_capacity(merged_header.len() + pinned.len());
+    for col in pinned {
+        if !header.contains(col) {
+            header.push(col.clone());
+        }
+    }
+    header.extend(merged_header.into_iter().filter(|col| !pinned.contains(col)));
+    header
+}
+
+/// Appends the `--add-group-id` value to each row, matching the column
+/// `finalize_header` adds
+fn add_group_id(rows: &mut [Vec<String>], header_hash: &str) {
+    for row in rows.iter_mut() {
+        row.push(header_hash.to_string());
+    }
+}
+
+/// Approximate heap size of rows for `--max-memory`: the capacity of every cell
+/// plus the `String` and `Vec` headers, ignoring allocator overhead
+fn estimate_row_bytes(rows: &[Vec<String>]) -> usize {
+    rows.iter()
+        .map(|row| {
+            std::mem::size_of::<Vec<String>>()
+                + row
+                    .iter()
+                    .map(|cell| std::mem::size_of::<String>() + cell.capacity())
+                    .sum::<usize>()
+        })
+        .sum()
+}
+
+/// Finishes buffered rows of a `--max-memory` group, appends them to its output
+/// and empties the buffer, returning how many rows were written
+fn spill_rows(
+    writer: &mut csv::Writer<OutputFile>,
+    args: &Args,
+    lookups: &[Lookup],
+    row_header: &[String],
+    header_hash: &str,
+    rows: &mut Vec<Vec<String>>,
+) -> Result<usize> {
+    apply_row_transforms(args, lookups, row_header, rows);
+    apply_hash_columns(args, row_header, rows);
+    if args.add_group_id {
+        add_group_id(rows, header_hash);
+    }
+    let width = row_header.len() + usize::from(args.add_group_id);
+    let mut padded = 0;
+    for row in rows.iter() {
+        padded += usize::from(write_row(writer, row, width, args.strict)?);
+    }
+    if padded > 0 {
+        warn!("Padded {} rows of group {} shorter than its header", padded, header_hash);
+    }
+    let written = rows.len();
+    rows.clear();
+    Ok(written)
+}
+
+/// Positions of the `--dedup-key` columns in a merged header, or of every column
+/// when no key is given or the group lacks one of the key columns
+fn dedup_columns(key: &[String], header: &[String]) -> Vec<usize> {
+    let positions: Option<Vec<usize>> = key
+        .iter()
+        .map(|column| header.iter().position(|col| col == column))
+        .collect();
+    match positions {
+        Some(positions) if !positions.is_empty() => positions,
+        Some(_) => (0..header.len()).collect(),
+        None => {
+            warn!(
+                "--dedup-key {} is not fully in this group, comparing whole rows instead",
+                key.join(",")
+            );
+            (0..header.len()).collect()
+        }
+    }
+}
+
+/// The cells `--dedup` compares, with `null_values` and blank cells as empty
+fn dedup_key(row: &[String], columns: &[usize], null_values: &[String]) -> Vec<String> {
+    columns
+        .iter()
+        .map(|&idx| {
+            let cell = row.get(idx).map_or("", String::as_str);
+            let value = cell.trim();
+            if value.is_empty() || null_values.iter().any(|null| null.trim().eq_ignore_ascii_case(value)) {
+                String::new()
+            } else {
+                cell.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Splits rows into those not seen before in this output and the duplicates
+/// `--dedup` removes. Only `columns` are compared, so the provenance columns
+/// never make rows distinct, and `null_values` compare as empty.
+fn dedup_rows(
+    rows: Vec<Vec<String>>,
+    columns: &[usize],
+    null_values: &[String],
+    seen: &mut HashSet<Vec<String>>,
+) -> (Vec<Vec<String>>, Vec<Vec<String>>) {
+    rows.into_iter()
+        .partition(|row| seen.insert(dedup_key(row, columns, null_values)))
+}
+
+/// Folds each row `--dedup-key` removed into the kept row with the same key,
+/// combining the `--aggregate` columns in input order
+fn aggregate_duplicates(
+    args: &Args,
+    header: &[String],
+    key_columns: &[usize],
+    kept: &mut [Vec<String>],
+    removed: &[Vec<String>],
+) {
+    let rules: Vec<(usize, &AggregateRule)> = args
+        .aggregate
+        .iter()
+        .filter_map(|rule| header.iter().position(|col| *col == rule.column).map(|idx| (idx, rule)))
+        .collect();
+    if rules.is_empty() || removed.is_empty() {
+        return;
+    }
+
+    let index: HashMap<Vec<String>, usize> = kept
+        .iter()
+        .enumerate()
+        .map(|(row_idx, row)| (dedup_key(row, key_columns, &args.null_values), row_idx))
+        .collect();
+    let mut not_numbers = vec![0; rules.len()];
+    for row in removed {
+        let Some(&row_idx) = index.get(&dedup_key(row, key_columns, &args.null_values)) else {
+            continue;
+        };
+        for (position, &(col_idx, rule)) in rules.iter().enumerate() {
+            let value = row.get(col_idx).map_or("", String::as_str);
+            if let Some(current) = kept[row_idx].get_mut(col_idx)
+                && !aggregate::fold(current, value, rule.function)
+            {
+                not_numbers[position] += 1;
+            }
+        }
+    }
+
+    for ((_, rule), count) in rules.iter().zip(not_numbers) {
+        if count > 0 {
+            warn!("{} values of '{}' are not numbers and were left out of its sum", count, rule.column);
+        }
+    }
+}
+
+/// Adds removed duplicates to the `--dedup-report` rows, header and values `|`-joined
+fn report_duplicates(
+    report: &mut Vec<Vec<String>>,
+    output: &str,
+    header: &[String],
+    source: &str,
+    removed: &[Vec<String>],
+) {
+    for row in removed {
+        report.push(vec![
+            output.to_string(),
+            source.to_string(),
+            header.join("|"),
+            row[..header.len().min(row.len())].join("|"),
+        ]);
+    }
+}
+
+/// Columns added by `--add-source-file` and `--add-source-row`, in row order
+fn provenance_columns(args: &Args) -> Vec<String> {
+    let mut columns = Vec::new();
+    if args.add_source_file {
+        columns.push("__source_file".to_string());
+    }
+    if args.add_source_row {
+        columns.push("__source_row".to_string());
+    }
+    columns
+}
+
+/// Appends the provenance columns to the mapped rows of one source file
+fn add_provenance(args: &Args, rows: &mut [Vec<String>], source: &str) {
+    for (idx, row) in rows.iter_mut().enumerate() {
+        if args.add_source_file {
+            row.push(source.to_string());
+        }
+        if args.add_source_row {
+            row.push((idx + 1).to_string());
+        }
+    }
+}
+
+/// Builds the header written to the output from the merged header
+fn finalize_header(args: &Args, merged_header: &[String]) -> Result<Vec<String>> {
+    let mut header = merged_header.to_vec();
+    if let Some(fold) = args.columns_case_fold {
+        for col in header.iter_mut() {
+            *col = fold.apply(col);
+        }
+    }
+    if let Some(style) = args.normalize_headers.filter(|_| !args.normalize_headers_on_read) {
+        for col in header.iter_mut() {
+            *col = style.apply(col);
+        }
+    }
+    header.extend(provenance_columns(args));
+    if args.add_group_id {
+        header.push(args.group_id_column.clone());
+    }
+
+    // Folding can make distinct columns collide, e.g. `Name` and `NAME`, as can
+    // normalizing `Name` and `name`
+    resolve_duplicate_columns(header, args.rename_duplicates)
+}
+
+/// Makes column names unique by suffixing repeats with `_2`, `_3`, ... when
+/// `rename` is set, otherwise fails listing the duplicates
+fn resolve_duplicate_columns(header: Vec<String>, rename: bool) -> Result<Vec<String>> {
+    let mut counts: HashMap<&String, usize> = HashMap::new();
+    for col in &header {
+        *counts.entry(col).or_default() += 1;
+    }
+
+    let mut duplicates: Vec<&String> = header
+        .iter()
+        .filter(|col| counts[col] > 1)
+        .collect();
+    duplicates.dedup();
+    if duplicates.is_empty() {
+        return Ok(header);
+    }
+
+    if !rename {
+        return Err(anyhow::anyhow!(
+            "Output header has duplicate columns: {} (use --rename-duplicates to suffix them)",
+            duplicates.iter().map(|col| col.as_str()).collect::<Vec<_>>().join(", ")
+        ));
+    }
+
+    let mut taken: std::collections::HashSet<String> = header.iter().cloned().collect();
+    let mut seen: std::collections::HashSet<&String> = std::collections::HashSet::new();
+    let mut renamed = Vec::with_capacity(header.len());
+    for col in &header {
+        if seen.insert(col) {
+            renamed.push(col.clone());
+            continue;
+        }
+
+        let mut suffix = 2;
+        let mut candidate = format!("{}_{}", col, suffix);
+        while taken.contains(&candidate) {
+            suffix += 1;
+            candidate = format!("{}_{}", col, suffix);
+        }
+        taken.insert(candidate.clone());
+        renamed.push(candidate);
+    }
+
+    Ok(renamed)
+}
+
+/// Drops all but the last `n` data rows, keeping the header
+fn keep_tail_rows(data: &mut Vec<Vec<String>>, n: usize) {
+    let data_rows = data.len().saturating_sub(1);
+    if data_rows > n {
+        data.drain(1..1 + data_rows - n);
+    }
+}
+
+/// Counts the data rows (excluding headers) across the given file contents
+fn count_data_rows<'a>(files: impl IntoIterator<Item = &'a Vec<Vec<String>>>) -> usize {
+    files
+        .into_iter()
+        .map(|data| data.len().saturating_sub(1))
+        .sum()
+}
+
+/// Keeps a random subset of rows (per `--sample-fraction`/`--sample-n`) in their original order
+fn sample_rows(rows: Vec<Vec<String>>, args: &Args, rng: &mut ChaCha8Rng) -> Vec<Vec<String>> {
+    let target = match (args.sample_fraction, args.sample_n) {
+        (Some(fraction), _) => (rows.len() as f64 * fraction).round() as usize,
+        (None, Some(count)) => count,
+        (None, None) => return rows,
+    };
+    if target >= rows.len() {
+        return rows;
+    }
+
+    let mut keep = rand::seq::index::sample(rng, rows.len(), target).into_vec();
+    keep.sort_unstable();
+
+    let mut keep = keep.into_iter().peekable();
+    rows.into_iter()
+        .enumerate()
+        .filter(|(idx, _)| keep.next_if_eq(idx).is_some())
+        .map(|(_, row)| row)
+        .collect()
+}
+
+/// Returns why a file with this header is kept out of grouping, if it is
+fn header_rejection(header: &[String], args: &Args) -> Option<String> {
+    if let Some(max_columns) = args.max_columns
+        && header.len() > max_columns
+    {
+        return Some(format!(
+            "header has {} columns, more than --max-columns {}",
+            header.len(),
+            max_columns
+        ));
+    }
+
+    let missing = missing_columns(header, &args.must_have);
+    if !missing.is_empty() {
+        return Some(format!("missing required columns: {}", missing.join(", ")));
+    }
+
+    None
+}
+
+/// Returns the required columns that are not present in the header
+fn missing_columns<'a>(header: &[String], required: &'a [String]) -> Vec<&'a str> {
+    required
+        .iter()
+        .map(|col| col.trim())
+        .filter(|col| !col.is_empty() && !header.iter().any(|h| h == col))
+        .collect()
+}
+
+/// Capitalizes the first letter of every alphanumeric word and lowercases the rest
+fn to_title_case(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut at_word_start = true;
+    for c in value.chars() {
+        if c.is_alphanumeric() {
+            if at_word_start {
+                result.extend(c.to_uppercase());
+            } else {
+                result.extend(c.to_lowercase());
+            }
+            at_word_start = false;
+        } else {
+            result.push(c);
+            at_word_start = true;
+        }
+    }
+    result
+}
+
+/// Lowercase words of a column name. Words end at anything but letters and
+/// digits, and where a lowercase letter or digit is followed by an uppercase
+/// one, so `CustomerName`, `customer_name` and `Customer Name` agree. A run of
+/// capitals stays one word up to its last letter, as in `HTTPCode`.
+fn header_words(value: &str) -> Vec<String> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (idx, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let prev = idx.checked_sub(1).map(|idx| chars[idx]);
+        let next = chars.get(idx + 1);
+        let boundary = c.is_uppercase()
+            && prev.is_some_and(|prev| {
+                prev.is_lowercase()
+                    || prev.is_ascii_digit()
+                    || (prev.is_uppercase() && next.is_some_and(|next| next.is_lowercase()))
+            });
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Renames columns that match case-insensitively across all headers to one
+/// consistent spelling, so they group and merge as a single column
+fn canonicalize_casing<'a>(
+    headers: impl IntoIterator<Item = &'a mut Vec<String>>,
+    casing: CanonicalCasing,
+) {
+    let mut headers: Vec<&mut Vec<String>> = headers.into_iter().collect();
+
+    let mut canonical: HashMap<String, String> = HashMap::new();
+    for header in headers.iter() {
+        for col in header.iter() {
+            canonical.entry(col.to_lowercase()).or_insert_with(|| match casing {
+                CanonicalCasing::Title => to_title_case(col),
+                CanonicalCasing::Lower => col.to_lowercase(),
+                CanonicalCasing::Upper => col.to_uppercase(),
+                CanonicalCasing::First => col.clone(),
+            });
+        }
+    }
+
+    for header in headers.iter_mut() {
+        for col in header.iter_mut() {
+            if let Some(name) = canonical.get(&col.to_lowercase()) {
+                col.clone_from(name);
+            }
+        }
+    }
+}
+
+/// A file left out because of its format rather than a read failure
+#[derive(Debug)]
+struct UnsupportedFile(String);
+
+impl std::fmt::Display for UnsupportedFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedFile {}
+
+fn get_file_contents(path: impl AsRef<Path>, options: &ReadOptions) -> Result<Vec<Vec<String>>> {
+    let path = path.as_ref();
+    let Some(extension) = path.extension() else {
+        return Err(UnsupportedFile("File has no extension".to_string()).into());
+    };
+
+    let is_csv = matches!(extension.to_string_lossy().to_lowercase().as_ref(), "csv" | "gz");
+    let mut data = match extension.to_string_lossy().to_lowercase().as_ref() {
+        "csv" => read_csv_file(path, options)?,
+        "gz" if is_gzipped_csv(path) => read_csv_file(path, options)?,
+        "arrow" | "feather" => feather::read_arrow_file(path)?,
+        "xlsx" | "xls" | "xlsm" | "xlsb" | "ods" => read_excel_file(path, options)?,
+        _ => {
+            return Err(UnsupportedFile(format!("Unsupported file extension: {:?}", extension)).into());
+        }
+    };
+    if is_csv {
+        // Sheets are trimmed one at a time while reading
+        skip_report_rows(&mut data, options);
+    }
+
+    let data = if options.detect_header {
+        let header_idx = detect_header_row(&data);
+        info!("Using row {} of {} as its header", header_idx + 1, path.display());
+        project_table(data.into_iter().skip(header_idx).collect(), options)
+    } else if is_csv && !options.reads_raw_rows() {
+        data
+    } else {
+        // Sheets and Arrow files are loaded whole, so they are projected afterwards
+        project_table(data, options)
+    };
+
+    finish_table(path, data, options)
+}
+
+/// Drops the `--skip-header-rows` rows above a file's or sheet's header and the
+/// `--skip-footer-rows` rows at its end. The first row left is never dropped as
+/// a footer, so a short file keeps its header.
+fn skip_report_rows(rows: &mut Vec<Vec<String>>, options: &ReadOptions) {
+    rows.drain(..options.skip_header_rows.min(rows.len()));
+    let footer = options.skip_footer_rows.min(rows.len().saturating_sub(1));
+    rows.truncate(rows.len() - footer);
+}
+
+/// Rows scanned by `--detect-header`
+const DETECT_HEADER_ROWS: usize = 10;
+
+/// Index of the row among the first [`DETECT_HEADER_ROWS`] that looks most like a
+/// header: as wide as the widest scanned row, cells filled, non-numeric and distinct.
+/// Ties go to the earliest row, so a file without a preamble keeps its first row.
+fn detect_header_row(data: &[Vec<String>]) -> usize {
+    let candidates = &data[..data.len().min(DETECT_HEADER_ROWS)];
+    let width = candidates.iter().map(Vec::len).max().unwrap_or_default();
+    if width == 0 {
+        return 0;
+    }
+
+    let score = |row: &Vec<String>| {
+        let filled: Vec<&str> = row.iter().map(|cell| cell.trim()).filter(|cell| !cell.is_empty()).collect();
+        if filled.is_empty() {
+            return 0.0;
+        }
+        let textual = filled.iter().filter(|cell| cell.chars().any(char::is_alphabetic)).count();
+        let distinct = filled.iter().collect::<HashSet<_>>().len();
+        (filled.len() as f64 / width as f64)
+            * (textual as f64 / filled.len() as f64)
+            * (distinct as f64 / filled.len() as f64)
+    };
+
+    let mut best = (0, 0.0);
+    for (idx, row) in candidates.iter().enumerate() {
+        let row_score = score(row);
+        if row_score > best.1 {
+            best = (idx, row_score);
+        }
+    }
+    best.0
+}
+
+/// Applies `--read-columns` to a table that was read in full
+fn project_table(mut data: Vec<Vec<String>>, options: &ReadOptions) -> Vec<Vec<String>> {
+    if let Some(projection) = data
+        .first()
+        .and_then(|header| column_projection(header, &options.read_columns))
+    {
+        for row in data.iter_mut() {
+            *row = project_row(row.iter().map(String::as_str), &projection);
+        }
+    }
+    data
+}
+
+/// Applies the cleanups shared by every table read from a file
+fn finish_table(
+    path: &Path,
+    mut data: Vec<Vec<String>>,
+    options: &ReadOptions,
+) -> Result<Vec<Vec<String>>> {
+    if !options.read_columns.is_empty() && data.first().is_some_and(|header| header.is_empty()) {
+        return Err(anyhow::anyhow!("None of the --read-columns are present"));
+    }
+
+    if options.drop_index_column && has_index_column(&data) {
+        debug!("Dropping unnamed index column from {}", path.display());
+        for row in data.iter_mut() {
+            if !row.is_empty() {
+                row.remove(0);
+            }
+        }
+    }
+
+    if let Some(header) = data.first_mut() {
+        clean_header(header, options);
+    }
+
+    if let Some((key_column, value_column)) = &options.transpose
+        && let Some(pivoted) = transpose_key_values(&data, key_column, value_column)
+    {
+        debug!("Transposed key-value file: {}", path.display());
+        data = pivoted;
+        clean_header(&mut data[0], options);
+    }
+
+    Ok(data)
+}
+
+fn is_csv_path(path: &Path) -> bool {
+    is_gzipped_csv(path)
+        || path
+            .extension()
+            .is_some_and(|ext| ext.to_string_lossy().eq_ignore_ascii_case("csv"))
+}
+
+/// Reads a CSV made of several blank-line separated tables (`--split-on-blank-lines`).
+/// Each block gets its own header and sniffed delimiter, unless `--delimiter-map` names one.
+fn read_csv_blocks(path: &Path, options: &ReadOptions) -> Result<Vec<Vec<Vec<String>>>> {
+    let mut bytes = Vec::new();
+    if is_gzipped_csv(path) {
+        std::io::Read::read_to_end(
+            &mut flate2::read::GzDecoder::new(std::fs::File::open(path)?),
+            &mut bytes,
+        )?;
+    } else {
+        bytes = std::fs::read(path)?;
+    }
+    let text = String::from_utf8_lossy(&bytes);
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mapped_delimiter = options
+        .delimiter_map
+        .iter()
+        .find(|rule| rule.matches(&file_name))
+        .map(|rule| rule.delimiter);
+
+    let mut tables = Vec::new();
+    for block in split_blocks(&text) {
+        let delimiter = mapped_delimiter.unwrap_or_else(|| sniff_delimiter(&block));
+        let reader = csv::ReaderBuilder::new()
+            .has_headers(options.header.is_none())
+            .flexible(options.skip_bad_rows)
+            .delimiter(delimiter)
+            .from_reader(block.as_bytes());
+        let data = csv_io::read_csv_records(path, reader, &options.csv(Some(delimiter)))?;
+        tables.push(finish_table(path, data, options)?);
+    }
+    debug!("Read {} tables from {}", tables.len(), path.display());
+    Ok(tables)
+}
+
+/// Splits text into blocks of consecutive non-blank lines
+fn split_blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                blocks.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push_str(line);
+            current.push('\n');
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
+
+/// Picks the delimiter that splits the most lines of a block into the same
+/// (more than one) number of fields as its first line. Comma wins ties.
+fn sniff_delimiter(block: &str) -> u8 {
+    const CANDIDATES: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+    let score = |delimiter: u8| -> usize {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(delimiter)
+            .from_reader(block.as_bytes());
+        let widths: Vec<usize> = reader
+            .records()
+            .map_while(|record| record.ok())
+            .map(|record| record.len())
+            .collect();
+        match widths.first() {
+            Some(&width) if width > 1 => widths.iter().filter(|&&w| w == width).count() * width,
+            _ => 0,
+        }
+    };
+
+    let mut best = (b',', score(b','));
+    for delimiter in CANDIDATES.into_iter().skip(1) {
+        let candidate = score(delimiter);
+        if candidate > best.1 {
+            best = (delimiter, candidate);
+        }
+    }
+    best.0
+}
+
+/// Sniffs the delimiter of one CSV from its first lines, so every file of a
+/// run gets its own
+fn sniff_file_delimiter(path: &Path) -> Result<u8> {
+    let sample = read_sample(path)?;
+    // A full sample usually ends mid-line, which would skew the widths
+    let sample = match sample.iter().rposition(|&b| b == b'\n') {
+        Some(end) if sample.len() == sniff::SAMPLE_LEN => &sample[..end],
+        _ => &sample[..],
+    };
+    Ok(sniff_delimiter(&String::from_utf8_lossy(sample)))
+}
+
+/// Pivots a key-value table so each distinct key becomes a column. A new row
+/// starts whenever a key repeats, so several records can be stacked in one file.
+/// Returns `None` when the header lacks either column.
+fn transpose_key_values(
+    data: &[Vec<String>],
+    key_column: &str,
+    value_column: &str,
+) -> Option<Vec<Vec<String>>> {
+    let (header, rows) = data.split_first()?;
+    let key_idx = header.iter().position(|col| col == key_column)?;
+    let value_idx = header.iter().position(|col| col == value_column)?;
+
+    let mut columns: Vec<String> = Vec::new();
+    let mut records: Vec<HashMap<String, String>> = Vec::new();
+    for row in rows {
+        let Some(key) = row.get(key_idx).filter(|key| !key.is_empty()) else {
+            continue;
+        };
+        let value = row.get(value_idx).cloned().unwrap_or_default();
+
+        if !columns.contains(key) {
+            columns.push(key.clone());
+        }
+        match records.last_mut() {
+            Some(record) if !record.contains_key(key) => {
+                record.insert(key.clone(), value);
+            }
+            _ => records.push(HashMap::from([(key.clone(), value)])),
+        }
+    }
+
+    let mut pivoted = vec![columns.clone()];
+    pivoted.extend(records.into_iter().map(|mut record| {
+        columns
+            .iter()
+            .map(|col| record.remove(col).unwrap_or_default())
+            .collect()
+    }));
+    Some(pivoted)
+}
+
+/// True when the first column is an unnamed index, as written by pandas: an
+/// empty or `Unnamed: 0` header over a contiguous integer sequence
+fn has_index_column(data: &[Vec<String>]) -> bool {
+    let Some((header, rows)) = data.split_first() else {
+        return false;
+    };
+    match header.first().map(|col| col.trim()) {
+        Some("") | Some("Unnamed: 0") if header.len() > 1 => {}
+        _ => return false,
+    }
+
+    let mut expected: Option<i64> = None;
+    for row in rows {
+        let Some(value) = row.first().and_then(|value| value.trim().parse::<i64>().ok()) else {
+            return false;
+        };
+        if expected.is_some_and(|expected| expected != value) {
+            return false;
+        }
+        expected = Some(value + 1);
+    }
+    true
+}
+
+/// Applies the read-time header cleanups before the header is used for grouping
+fn clean_header(header: &mut [String], options: &ReadOptions) {
+    if options.normalize_unicode {
+        for col in header.iter_mut() {
+            *col = normalize_unicode(col);
+        }
+    }
+    for col in header.iter_mut() {
+        if let Some(canonical) = options.aliases.get(col.as_str()) {
+            col.clone_from(canonical);
+        }
+    }
+    if let Some(style) = options.header_style {
+        for col in header.iter_mut() {
+            *col = style.apply(col);
+        }
+    }
+}
+
+/// One cluster of the `--header-synonyms-learn` JSON file: the name its
+/// columns are renamed to, and the names suggested as its synonyms
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct AliasCluster {
+    canonical: String,
+    aliases: Vec<ClusterAlias>,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct ClusterAlias {
+    name: String,
+    /// Name similarity to the canonical name, the alias's confidence (1.0 when absent)
+    #[serde(default = "full_confidence")]
+    similarity: f64,
+}
+
+fn full_confidence() -> f64 {
+    1.0
+}
+
+/// Groups suggestions by canonical name, in the order the names are first suggested
+fn alias_clusters(suggestions: &[AliasSuggestion]) -> Vec<AliasCluster> {
+    let mut clusters: Vec<AliasCluster> = Vec::new();
+    for suggestion in suggestions {
+        let alias = ClusterAlias {
+            name: suggestion.source.clone(),
+            // Two decimals are plenty to review and keep the file readable
+            similarity: (suggestion.similarity * 100.0).round() / 100.0,
+        };
+        match clusters.iter_mut().find(|cluster| cluster.canonical == suggestion.canonical) {
+            Some(cluster) => cluster.aliases.push(alias),
+            None => clusters.push(AliasCluster {
+                canonical: suggestion.canonical.clone(),
+                aliases: vec![alias],
+            }),
+        }
+    }
+    clusters
+}
+
+/// Reads an alias map: a `.json` file of [`AliasCluster`]s, or an alias table
+/// with `source_name`, `canonical_name` and an optional `confidence` column
+/// (1.0 when absent). Entries at or above `min_confidence` are kept.
+fn read_alias_map(path: &Path, min_confidence: f64) -> Result<BTreeMap<String, String>> {
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+        let clusters: Vec<AliasCluster> = serde_json::from_str(&std::fs::read_to_string(path)?)
+            .with_context(|| format!("Alias map {} is not a list of alias clusters", path.display()))?;
+        let mut aliases = BTreeMap::new();
+        for cluster in &clusters {
+            for alias in cluster.aliases.iter().filter(|alias| alias.similarity >= min_confidence) {
+                aliases.insert(alias.name.trim().to_string(), cluster.canonical.trim().to_string());
+            }
+        }
+        return Ok(aliases);
+    }
+
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|col| col.trim() == name);
+    let (Some(source_idx), Some(canonical_idx)) = (column("source_name"), column("canonical_name"))
+    else {
+        return Err(anyhow::anyhow!(
+            "Alias map {} needs source_name and canonical_name columns",
+            path.display()
+        ));
+    };
+    let confidence_idx = column("confidence");
+
+    let mut aliases = BTreeMap::new();
+    let mut below_threshold = 0;
+    for result in reader.records() {
+        let record = result?;
+        let source = record.get(source_idx).unwrap_or_default().trim();
+        let canonical = record.get(canonical_idx).unwrap_or_default().trim();
+        if source.is_empty() || canonical.is_empty() {
+            continue;
+        }
+
+        let confidence = match confidence_idx.and_then(|idx| record.get(idx)) {
+            Some(value) if !value.trim().is_empty() => value.trim().parse::<f64>().map_err(|_| {
+                anyhow::anyhow!("Invalid confidence '{}' for alias '{}'", value, source)
+            })?,
+            _ => 1.0,
+        };
+        if confidence < min_confidence {
+            below_threshold += 1;
+            continue;
+        }
+        aliases.insert(source.to_string(), canonical.to_string());
+    }
+
+    if below_threshold > 0 {
+        debug!(
+            "Ignored {} aliases below confidence {}",
+            below_threshold, min_confidence
+        );
+    }
+    Ok(aliases)
+}
+
+/// Writes `--header-synonyms-learn` suggestions as a JSON list of clusters, which
+/// `--alias-map` reads back with the similarity as confidence
+fn write_alias_suggestions(path: &Path, suggestions: &[AliasSuggestion]) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(&alias_clusters(suggestions))? + "\n")?;
+    Ok(())
+}
+
+/// NFC-normalizes a header cell and replaces non-breaking spaces with regular spaces
+fn normalize_unicode(value: &str) -> String {
+    value
+        .nfc()
+        .map(|c| match c {
+            '\u{00A0}' | '\u{202F}' | '\u{2007}' => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+/// Reads column names from a sidecar `.header`/`.schema` file.
+///
+/// A single line is parsed as a comma separated header, otherwise each
+/// non-empty line holds one column name.
+fn read_header_file(path: impl AsRef<Path>) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+
+    let mut records = Vec::new();
+    for result in reader.records() {
+        records.push(result?);
+    }
+
+    let header: Vec<String> = if records.len() == 1 {
+        records[0].iter().map(|s| s.trim().to_string()).collect()
+    } else {
+        records
+            .iter()
+            .filter_map(|record| record.get(0))
+            .map(|s| s.trim().to_string())
+            .collect()
+    };
+
+    if header.is_empty() || header.iter().any(|col| col.is_empty()) {
+        return Err(anyhow::anyhow!(
+            "Header file {} contains no usable column names",
+            path.display()
+        ));
+    }
+
+    Ok(header)
+}
+
+/// Runs `read` on `path`, on a worker thread when there is a `--timeout-per-file`
+/// so a file that takes longer can be abandoned. The worker cannot be stopped
+/// and finishes in the background, its result discarded.
+fn read_within<T: Send + 'static>(
+    timeout: Option<Duration>,
+    path: &Path,
+    options: &ReadOptions,
+    read: fn(&Path, &ReadOptions) -> Result<T>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return read(path, options);
+    };
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let (path, options) = (path.to_path_buf(), options.clone());
+    std::thread::spawn(move || {
+        // The receiver is gone once the read timed out
+        let _ = sender.send(read(&path, &options));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Err(anyhow::anyhow!(
+            "reading took longer than --timeout-per-file {}s, abandoned it",
+            timeout.as_secs()
+        )),
+        Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+            Err(anyhow::anyhow!("the read worker stopped without a result"))
+        }
+    }
+}
+
+fn read_csv_file(path: impl AsRef<Path>, options: &ReadOptions) -> Result<Vec<Vec<String>>> {
+    let path = path.as_ref();
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut delimiter = b',';
+    if let Some(rule) = options.delimiter_map.iter().find(|rule| rule.matches(&file_name)) {
+        debug!(
+            "Using delimiter '{}' for {} (matched '{}')",
+            rule.delimiter as char,
+            path.display(),
+            rule.pattern
+        );
+        delimiter = rule.delimiter;
+    } else if options.auto_delimiter {
+        delimiter = sniff_file_delimiter(path)?;
+        debug!("Sniffed delimiter '{}' for {}", delimiter as char, path.display());
+    }
+
+    if options.validate_content
+        && let Some(kind) = sniff::non_csv_content(&read_sample(path)?, delimiter)
+    {
+        return Err(UnsupportedFile(format!("looks like {}, not CSV", kind)).into());
+    }
+
+    let mut data = csv_io::read_csv(path, &options.csv(Some(delimiter)))?;
+    if let Some(marker) = &options.data_starts_after {
+        // Exports often pad the marker line with delimiters, as in `---DATA---,,`
+        let is_marker = |row: &Vec<String>| {
+            let mut cells = row.iter().map(|cell| cell.trim()).filter(|cell| !cell.is_empty());
+            cells.next() == Some(marker.as_str()) && cells.next().is_none()
+        };
+        let Some(marker_idx) = data.iter().position(is_marker) else {
+            return Err(anyhow::anyhow!("no '{}' line to start the data after", marker));
+        };
+        data.drain(..=marker_idx);
+    }
+    Ok(data)
+}
+
+fn read_excel_file(path: impl AsRef<Path>, options: &ReadOptions) -> Result<Vec<Vec<String>>> {
+    let path = path.as_ref();
+    let mut workbook = open_workbook_auto(path)?;
+
+    let sheet_names = workbook.sheet_names().to_vec();
+    if sheet_names.is_empty() {
+        return Err(anyhow::anyhow!("Excel file has no sheets"));
+    }
+
+    let selected = select_sheets(&sheet_names, options);
+    if selected.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No sheet matches --sheet-pattern (sheets: {})",
+            sheet_names.join(", ")
+        ));
+    }
+
+    let mut sheets = Vec::new();
+    for sheet_name in selected {
+        info!("Reading sheet: {}", sheet_name);
+        let range = workbook.worksheet_range(sheet_name)?;
+
+        let mut rows: Vec<Vec<String>> = range
+            .rows()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| {
+                        render_cell(cell, options.preserve_excel_formatting, options.excel_date_system)
+                    })
+                    .collect()
+            })
+            .collect();
+        skip_report_rows(&mut rows, options);
+        sheets.push((sheet_name.clone(), rows));
+    }
+
+    let mut data = Vec::new();
+    if let Some(header) = &options.header {
+        // Headerless sheets are all data
+        data.push(header.clone());
+        data.extend(sheets.into_iter().flat_map(|(_, rows)| rows));
+    } else {
+        data = stack_sheets(path, sheets);
+    }
+
+    Ok(data)
+}
+
+/// Converts an Excel cell to text. calamine does not expose number format
+/// strings, so `preserve` renders what the cell type tells us: dates and
+/// durations as text, whole numbers without a decimal point, floats at Excel's
+/// 15 significant digits and booleans as TRUE/FALSE. Anything else (currency,
+/// percentages) falls back to the plain value.
+///
+/// Dates are rendered in `date_system`. Without `preserve` their serials are
+/// written as stored, whichever system the workbook uses.
+fn render_cell(cell: &Data, preserve: bool, date_system: ExcelDateSystem) -> String {
+    if !preserve {
+        return cell.to_string();
+    }
+    let rebased;
+    let cell = match cell {
+        Data::DateTime(value) if value.is_datetime() => {
+            rebased = Data::DateTime(resolve_date_system(*value, date_system));
+            &rebased
+        }
+        _ => cell,
+    };
+
+    match cell {
+        Data::DateTime(value) if value.is_duration() => match value.as_duration() {
+            Some(duration) => {
+                let secs = duration.num_seconds();
+                format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+            }
+            None => cell.to_string(),
+        },
+        Data::DateTime(value) => match value.as_datetime() {
+            Some(datetime) if datetime.time() == chrono::NaiveTime::MIN => {
+                datetime.format("%Y-%m-%d").to_string()
+            }
+            Some(datetime) => datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => cell.to_string(),
+        },
+        Data::Float(value) if value.fract() == 0.0 && value.abs() < 1e15 => {
+            format!("{}", *value as i64)
+        }
+        Data::Float(value) => {
+            // Excel displays at most 15 significant digits, hiding binary noise
+            let digits = 14 - value.abs().log10().floor() as i32;
+            let rounded: f64 = format!("{:.*}", digits.clamp(0, 20) as usize, value)
+                .parse()
+                .unwrap_or(*value);
+            rounded.to_string()
+        }
+        Data::Bool(value) => if *value { "TRUE" } else { "FALSE" }.to_string(),
+        _ => cell.to_string(),
+    }
+}
+
+/// Applies `--excel-date-system` to a date cell. `Auto` keeps the system calamine
+/// read from the workbook.
+fn resolve_date_system(value: ExcelDateTime, date_system: ExcelDateSystem) -> ExcelDateTime {
+    let as_1900 = ExcelDateTime::new(value.as_f64(), ExcelDateTimeType::DateTime, false);
+    let is_1904 = match date_system {
+        // The flag is private, but a 1904 cell differs from its 1900 twin
+        ExcelDateSystem::Auto => value != as_1900,
+        ExcelDateSystem::Excel1900 => false,
+        ExcelDateSystem::Excel1904 => true,
+    };
+    ExcelDateTime::new(value.as_f64(), ExcelDateTimeType::DateTime, is_1904)
+}
+
+/// Picks the sheets to read: the first sheet, or those matching `--sheet-pattern`.
+/// `--combine-sheets-within-file` reads every (matching) sheet.
+fn select_sheets<'a>(sheet_names: &'a [String], options: &ReadOptions) -> Vec<&'a String> {
+    let Some(pattern) = &options.sheet_pattern else {
+        let count = if options.combine_sheets { sheet_names.len() } else { 1 };
+        return sheet_names.iter().take(count).collect();
+    };
+
+    let matching = sheet_names.iter().filter(|name| pattern.matches(name));
+    match options.sheet_pattern_mode {
+        SheetPatternMode::First if !options.combine_sheets => matching.take(1).collect(),
+        _ => matching.collect(),
+    }
+}
+
+/// Stacks the data rows of several sheets under the first sheet's header.
+/// Sheets whose header differs from the first are skipped with a warning.
+fn stack_sheets(path: &Path, sheets: Vec<(String, Vec<Vec<String>>)>) -> Vec<Vec<String>> {
+    let mut sheets = sheets.into_iter();
+    let Some((_, mut data)) = sheets.next() else {
+        return Vec::new();
+    };
+
+    for (sheet_name, rows) in sheets {
+        let Some((header, rows)) = rows.split_first() else {
+            continue;
+        };
+        if data.first() != Some(header) {
+            warn!(
+                "Skipping sheet '{}' in {}: header does not match the first sheet",
+                sheet_name,
+                path.display()
+            );
+            continue;
+        }
+        data.extend(rows.iter().cloned());
+    }
+
+    data
+}
+
+/// The supported files under `search_path`, in directory listing order. Grouping
+/// depends on the order files are seen in, so callers sort them with [`sort_files`].
+fn get_files(search_path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let search_path = search_path.as_ref();
+    if search_path.is_dir() {
+        let mut files = Vec::new();
+        for entry in walkdir::WalkDir::new(search_path) {
+            let entry = entry?;
+            let path = entry.path();
+            if is_path_valid(path) {
+                files.push(PathBuf::from(path));
+            }
+        }
+        Ok(files)
+    } else if search_path.is_file() {
+        Ok(vec![search_path.to_path_buf()])
+    } else {
+        Err(anyhow::anyhow!(
+            "{:?} is not a file nor is a directory, I don't know how you got here?!?!?!?",
+            search_path
+        ))
+    }
+}
+
+/// Sorts input files for `--sort-files-by`. Ties, and files whose metadata cannot be
+/// read, fall back to name order.
+fn sort_files(files: &mut [PathBuf], order: FileOrder, descending: bool) {
+    files.sort();
+    match order {
+        FileOrder::Name => {}
+        FileOrder::Mtime => {
+            files.sort_by_cached_key(|path| std::fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        }
+        FileOrder::Size => files.sort_by_cached_key(|path| std::fs::metadata(path).map(|meta| meta.len()).ok()),
+    }
+    if descending {
+        files.reverse();
+    }
+}
+
+/// Reads `--input-list`: one path per line, ignoring blank lines and `#` comments.
+/// Missing and unsupported files are warned about and left out.
+fn read_input_list(list_path: &Path) -> Result<Vec<PathBuf>> {
+    let contents = std::fs::read_to_string(list_path)?;
+    let mut files = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let path = PathBuf::from(line);
+        if !path.exists() {
+            warn!("Listed file does not exist: {}", path.display());
+        } else if !is_path_valid(&path) {
+            warn!("Listed file is not a supported CSV, Excel or Arrow file: {}", path.display());
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn is_path_valid(file_path: impl AsRef<Path>) -> bool {
+    let file_path = file_path.as_ref();
+    if file_path.is_file()
+        && let Some(extension) = file_path.extension()
+    {
+        let ext = extension.to_string_lossy().to_lowercase();
+        return matches!(
+            ext.as_ref(),
+            "csv" | "xlsx" | "xls" | "xlsm" | "xlsb" | "ods" | "arrow" | "feather"
+        ) || is_gzipped_csv(file_path);
+    }
+    false
+}
+
+/// Reads the first bytes of a CSV for `--validate-content`, decompressing `.csv.gz`
+fn read_sample(path: &Path) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let file = std::fs::File::open(path)?;
+    let mut sample = Vec::new();
+    if is_gzipped_csv(path) {
+        flate2::read::GzDecoder::new(file)
+            .take(sniff::SAMPLE_LEN as u64)
+            .read_to_end(&mut sample)?;
+    } else {
+        file.take(sniff::SAMPLE_LEN as u64).read_to_end(&mut sample)?;
+    }
+    Ok(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv_combine::{group_files, headers_are_compatible, merge_headers};
     use std::fs;
     use std::io::Write;
     use tempfile::TempDir;
 
     #[test]
-    fn test_is_path_valid_csv() {
+    fn test_is_path_valid_csv() {
+        let test_dir = TempDir::new().unwrap();
+        let csv_path = test_dir.path().join("test.csv");
+        fs::File::create(&csv_path).unwrap();
+
+        assert!(is_path_valid(&csv_path));
+    }
+
+    #[test]
+    fn test_is_path_valid_xlsx() {
+        let test_dir = TempDir::new().unwrap();
+        let xlsx_path = test_dir.path().join("test.xlsx");
+        fs::File::create(&xlsx_path).unwrap();
+
+        assert!(is_path_valid(&xlsx_path));
+    }
+
+    #[test]
+    fn test_is_path_valid_invalid_extension() {
+        let test_dir = TempDir::new().unwrap();
+        let txt_path = test_dir.path().join("test.txt");
+        fs::File::create(&txt_path).unwrap();
+
+        assert!(!is_path_valid(&txt_path));
+    }
+
+    #[test]
+    fn test_read_gzipped_csv() {
+        let test_dir = TempDir::new().unwrap();
+        let gz_path = test_dir.path().join("archive.CSV.gz");
+        let other_gz = test_dir.path().join("notes.txt.gz");
+
+        let mut encoder = flate2::write::GzEncoder::new(
+            fs::File::create(&gz_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b"Name,Age\nAlice,30\n").unwrap();
+        encoder.finish().unwrap();
+        fs::File::create(&other_gz).unwrap();
+
+        assert!(is_path_valid(&gz_path));
+        assert!(!is_path_valid(&other_gz));
+        assert_eq!(
+            get_file_contents(&gz_path, &ReadOptions::default()).unwrap(),
+            vec![vec!["Name", "Age"], vec!["Alice", "30"]]
+        );
+    }
+
+    #[test]
+    fn test_read_input_list() {
+        let test_dir = TempDir::new().unwrap();
+        let csv_path = test_dir.path().join("a.csv");
+        let txt_path = test_dir.path().join("notes.txt");
+        fs::write(&csv_path, "x").unwrap();
+        fs::write(&txt_path, "x").unwrap();
+
+        let list_path = test_dir.path().join("files.txt");
+        fs::write(
+            &list_path,
+            format!(
+                "# nightly inputs\n\n{}\n  {}  \n{}\n",
+                csv_path.display(),
+                txt_path.display(),
+                test_dir.path().join("missing.csv").display()
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(read_input_list(&list_path).unwrap(), vec![csv_path]);
+    }
+
+    #[test]
+    fn test_alias_map_applies_above_confidence() {
+        let test_dir = TempDir::new().unwrap();
+        let map_path = test_dir.path().join("aliases.csv");
+        fs::write(
+            &map_path,
+            "source_name,canonical_name,confidence\nCust No,customer_id,0.9\nCustomer,customer_id,0.4\nQty,quantity,\n",
+        )
+        .unwrap();
+
+        let aliases = read_alias_map(&map_path, 0.5).unwrap();
+        assert_eq!(aliases.len(), 2);
+
+        let options = ReadOptions {
+            aliases,
+            ..Default::default()
+        };
+        let mut header = vec!["Cust No".to_string(), "Customer".to_string(), "Qty".to_string()];
+        clean_header(&mut header, &options);
+        assert_eq!(header, vec!["customer_id", "Customer", "quantity"]);
+
+        fs::write(&map_path, "from,to\na,b\n").unwrap();
+        assert!(read_alias_map(&map_path, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_alias_suggestions_are_an_alias_map() {
+        let test_dir = TempDir::new().unwrap();
+        let map_path = test_dir.path().join("aliases.json");
+        let suggestion = |source: &str, canonical: &str, similarity| AliasSuggestion {
+            source: source.to_string(),
+            canonical: canonical.to_string(),
+            similarity,
+        };
+        let suggestions = vec![
+            suggestion("Qty", "qtys", 0.75),
+            suggestion("Cust Name", "Customer Name", 0.8125),
+            suggestion("QTY ", "qtys", 0.75),
+        ];
+
+        write_alias_suggestions(&map_path, &suggestions).unwrap();
+        let clusters: Vec<AliasCluster> = serde_json::from_str(&fs::read_to_string(&map_path).unwrap()).unwrap();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].canonical, "qtys");
+        assert_eq!(clusters[0].aliases.len(), 2);
+        assert_eq!(clusters[1].aliases[0].similarity, 0.81);
+
+        assert_eq!(read_alias_map(&map_path, 0.8).unwrap().len(), 1);
+        let aliases = read_alias_map(&map_path, 0.7).unwrap();
+        assert_eq!(aliases["Qty"], "qtys");
+        assert_eq!(aliases["QTY"], "qtys");
+
+        // A hand-written cluster may leave the similarity out
+        fs::write(&map_path, r#"[{"canonical": "id", "aliases": [{"name": "ID#"}]}]"#).unwrap();
+        assert_eq!(read_alias_map(&map_path, 0.9).unwrap()["ID#"], "id");
+    }
+
+    #[test]
+    fn test_read_csv_blocks() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("dump.csv");
+        fs::write(
+            &path,
+            "id,name\n1,Alice\n2,Bob\n\n\nsku;price;qty\nA1;9,99;3\n  \nx|y\n1|2\n",
+        )
+        .unwrap();
+
+        let tables = read_csv_blocks(&path, &ReadOptions::default()).unwrap();
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[0], vec![vec!["id", "name"], vec!["1", "Alice"], vec!["2", "Bob"]]);
+        assert_eq!(tables[1], vec![vec!["sku", "price", "qty"], vec!["A1", "9,99", "3"]]);
+        assert_eq!(tables[2], vec![vec!["x", "y"], vec!["1", "2"]]);
+    }
+
+    #[test]
+    fn test_validate_content_rejects_html() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("report.csv");
+        fs::write(&path, "<html><body>Session expired</body></html>\n").unwrap();
+
+        assert!(get_file_contents(&path, &ReadOptions::default()).is_ok());
+
+        let options = ReadOptions {
+            validate_content: true,
+            ..Default::default()
+        };
+        let err = get_file_contents(&path, &options).unwrap_err();
+        assert_eq!(err.to_string(), "looks like HTML, not CSV");
+    }
+
+    #[test]
+    fn test_output_template() {
+        assert!(parse_output_template("{prefix}_{date}_{count}files").is_ok());
+        assert!(parse_output_template("{sheet}.csv").unwrap_err().contains("unknown placeholder '{sheet}'"));
+        assert!(parse_output_template("out_{hash").is_err());
+
+        let values = [("prefix", "combined".to_string()), ("count", "3".to_string())];
+        assert_eq!(render_output_template("{prefix}_{count}files", &values), "combined_3files.csv");
+
+        let mut used = HashSet::new();
+        assert_eq!(unique_output_name("out.csv".to_string(), &mut used), "out.csv");
+        assert_eq!(unique_output_name("out.csv".to_string(), &mut used), "out_2.csv");
+    }
+
+    #[test]
+    fn test_group_dir_name() {
+        let input = Path::new("/data/exports");
+        let paths = [Path::new("/data/exports/2024/q1/a.csv"), Path::new("/data/exports/2024/q2/b.csv")];
+        assert_eq!(group_dir_name(paths, input), "2024");
+        assert_eq!(group_dir_name([Path::new("/data/exports/q1 (final)/a.csv")], input), "q1__final");
+
+        // Files with no directory in common fall back to the input directory
+        let paths = [Path::new("/data/a.csv"), Path::new("/mnt/b.csv")];
+        assert_eq!(group_dir_name(paths, input), "exports");
+        assert_eq!(group_dir_name(paths, Path::new("/")), "input");
+    }
+
+    #[test]
+    fn test_sort_files() {
+        let test_dir = TempDir::new().unwrap();
+        let small = test_dir.path().join("b.csv");
+        let large = test_dir.path().join("a.csv");
+        fs::write(&small, "x\n1\n").unwrap();
+        fs::write(&large, "x\n1\n2\n3\n").unwrap();
+
+        let mut files = vec![small.clone(), large.clone()];
+        sort_files(&mut files, FileOrder::Name, false);
+        assert_eq!(files, vec![large.clone(), small.clone()]);
+
+        sort_files(&mut files, FileOrder::Size, false);
+        assert_eq!(files, vec![small.clone(), large.clone()]);
+
+        sort_files(&mut files, FileOrder::Size, true);
+        assert_eq!(files, vec![large, small]);
+    }
+
+    #[test]
+    fn test_enforce_schema_writes_rejects() {
+        let test_dir = TempDir::new().unwrap();
+        let output = test_dir.path().to_str().unwrap();
+        let header = vec!["name".to_string(), "age".to_string()];
+        let rows = vec![
+            vec!["Alice".to_string(), "30".to_string()],
+            vec!["Bob".to_string(), "n/a".to_string()],
+        ];
+
+        let args = Args::parse_from(["csv_combine", "--schema", "age:int", "--output", output]);
+        let kept = enforce_schema(&args, "combined_1.csv", &header, rows.clone(), &WriteOptions::default()).unwrap();
+        assert_eq!(kept, vec![vec!["Alice", "30"]]);
+        assert_eq!(
+            fs::read_to_string(test_dir.path().join("combined_1_rejects.csv")).unwrap(),
+            "name,age,type_error\nBob,n/a,age: 'n/a' is not a valid int\n"
+        );
+
+        let args = Args::parse_from(["csv_combine", "--schema", "age:int", "--on-type-error", "fail"]);
+        assert!(enforce_schema(&args, "combined_1.csv", &header, rows, &WriteOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_first_header_exact() {
+        let header = |cols: &[&str]| cols.iter().map(|col| col.to_string()).collect::<Vec<_>>();
+        let headers = vec![
+            header(&["Name", "City"]),
+            header(&["City", "Name"]),
+            header(&["Name", "Phone", "City", "Email"]),
+        ];
+
+        let (first, dropped) = first_header_exact(&headers);
+        assert_eq!(first, vec!["Name", "City"]);
+        assert_eq!(dropped, vec![(2, header(&["Phone", "Email"]))]);
+
+        // Rows of the wider file lose the dropped columns
+        let rows = vec![header(&["Alice", "555", "Paris", "a@example.com"])];
+        assert_eq!(map_rows_to_header(&headers[2], &first, &rows), vec![header(&["Alice", "Paris"])]);
+    }
+
+    #[test]
+    fn test_save_schemas() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("schema.csv");
+        let header = |cols: &[&str]| cols.iter().map(|col| col.to_string()).collect::<Vec<_>>();
+
+        let saved = save_schemas(&path, &[("a1b2".to_string(), header(&["Name", "City"]))]).unwrap();
+        assert_eq!(saved, vec![path.clone()]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Name,City\n");
+        assert_eq!(read_reference_header(&path).unwrap(), vec!["Name", "City"]);
+
+        let saved = save_schemas(
+            &path,
+            &[
+                ("a1b2".to_string(), header(&["Name"])),
+                ("c3d4".to_string(), header(&["Sku", "Price"])),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            saved,
+            vec![test_dir.path().join("schema_a1b2.csv"), test_dir.path().join("schema_c3d4.csv")]
+        );
+        assert_eq!(fs::read_to_string(&saved[1]).unwrap(), "Sku,Price\n");
+    }
+
+    #[test]
+    fn test_add_provenance() {
+        let args = Args::parse_from(["csv_combine", "--add-source-file", "--add-source-row", "--add-group-id"]);
+        let merged = vec!["Name".to_string()];
+        assert_eq!(
+            finalize_header(&args, &merged).unwrap(),
+            vec!["Name", "__source_file", "__source_row", "group_id"]
+        );
+
+        let mut rows = vec![vec!["Alice".to_string()], vec!["Bob".to_string()]];
+        add_provenance(&args, &mut rows, "a.csv");
+        assert_eq!(rows, vec![vec!["Alice", "a.csv", "1"], vec!["Bob", "a.csv", "2"]]);
+    }
+
+    #[test]
+    fn test_spill_rows_appends_and_clears() {
+        let test_dir = TempDir::new().unwrap();
+        let output_path = test_dir.path().join("combined.csv");
+        let header = vec!["Name".to_string()];
+        let args = Args::parse_from(["csv_combine", "--max-memory", "1"]);
+
+        let mut writer = create_csv_writer(&output_path, &header, &CsvWriteOptions::default()).unwrap();
+        let mut rows = vec![vec!["Alice".to_string()]];
+        assert!(estimate_row_bytes(&rows) > 5);
+        assert_eq!(spill_rows(&mut writer, &args, &[], &header, "abc", &mut rows).unwrap(), 1);
+        assert!(rows.is_empty());
+
+        rows.push(vec!["Bob".to_string()]);
+        spill_rows(&mut writer, &args, &[], &header, "abc", &mut rows).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "Name\nAlice\nBob\n");
+    }
+
+    #[test]
+    fn test_dedup_rows() {
+        let rows = vec![
+            vec!["Alice".to_string(), "1".to_string()],
+            vec!["Bob".to_string(), "2".to_string()],
+            vec!["Alice".to_string(), "3".to_string()],
+        ];
+        let mut seen = HashSet::new();
+
+        // The second column is a provenance row number, outside the compared width
+        let (kept, removed) = dedup_rows(rows, &[0], &[], &mut seen);
+        assert_eq!(kept, vec![vec!["Alice", "1"], vec!["Bob", "2"]]);
+        assert_eq!(removed, vec![vec!["Alice", "3"]]);
+
+        let mut report = Vec::new();
+        report_duplicates(&mut report, "combined_1.csv", &["Name".to_string()], "b.csv", &removed);
+        assert_eq!(report, vec![vec!["combined_1.csv", "b.csv", "Name", "Alice"]]);
+    }
+
+    #[test]
+    fn test_dedup_rows_treats_null_values_as_empty() {
+        let rows = vec![
+            vec!["Alice".to_string(), "".to_string()],
+            vec!["Alice".to_string(), "null".to_string()],
+            vec!["Alice".to_string(), "N/A ".to_string()],
+        ];
+        let null_values = ["NULL".to_string(), "N/A".to_string()];
+
+        let (kept, removed) = dedup_rows(rows, &[0, 1], &null_values, &mut HashSet::new());
+        assert_eq!(kept, vec![vec!["Alice", ""]]);
+        assert_eq!(removed.len(), 2);
+    }
+
+    #[test]
+    fn test_aggregate_duplicates_by_key() {
+        let args = Args::parse_from([
+            "csv_combine",
+            "--dedup",
+            "--dedup-key",
+            "sku",
+            "--aggregate",
+            "qty:sum,notes:concat",
+        ]);
+        let header: Vec<String> = ["sku", "qty", "notes"].iter().map(|s| s.to_string()).collect();
+        let rows: Vec<Vec<String>> = [["A1", "2", "late"], ["B2", "1", ""], ["A1", "3", "fragile"]]
+            .iter()
+            .map(|row| row.iter().map(|s| s.to_string()).collect())
+            .collect();
+
+        let columns = dedup_columns(&args.dedup_key, &header);
+        assert_eq!(columns, vec![0]);
+        let (mut kept, removed) = dedup_rows(rows, &columns, &[], &mut HashSet::new());
+        aggregate_duplicates(&args, &header, &columns, &mut kept, &removed);
+        assert_eq!(kept, vec![vec!["A1", "5", "late; fragile"], vec!["B2", "1", ""]]);
+
+        assert_eq!(dedup_columns(&["missing".to_string()], &header), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_detect_header_skips_preamble() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("export.csv");
+        fs::write(&path, "Sales export\nGenerated 2024-03-01,,\n\nName,Region,Total\nAlice,North,10\n").unwrap();
+
+        let options = ReadOptions {
+            detect_header: true,
+            ..Default::default()
+        };
+        let data = get_file_contents(&path, &options).unwrap();
+        assert_eq!(data, vec![vec!["Name", "Region", "Total"], vec!["Alice", "North", "10"]]);
+
+        // Text-only data rows tie with the header, which comes first
+        let rows = vec![
+            vec!["Name".to_string(), "City".to_string()],
+            vec!["Alice".to_string(), "Paris".to_string()],
+        ];
+        assert_eq!(detect_header_row(&rows), 0);
+    }
+
+    #[test]
+    fn test_skip_header_and_footer_rows() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("vendor.csv");
+        fs::write(&path, "Vendor report\nRun 2024-03-01,x,y,z\nName,Total\nAlice,10\nBob,5\nTotal,15,\n").unwrap();
+
+        let options = ReadOptions {
+            skip_header_rows: 2,
+            skip_footer_rows: 1,
+            ..Default::default()
+        };
+        let data = get_file_contents(&path, &options).unwrap();
+        assert_eq!(data, vec![vec!["Name", "Total"], vec!["Alice", "10"], vec!["Bob", "5"]]);
+
+        // A footer never takes the header with it
+        let mut rows = vec![vec!["Name".to_string()], vec!["Alice".to_string()]];
+        let options = ReadOptions {
+            skip_footer_rows: 5,
+            ..Default::default()
+        };
+        skip_report_rows(&mut rows, &options);
+        assert_eq!(rows, vec![vec!["Name".to_string()]]);
+    }
+
+    #[test]
+    fn test_read_reference_header() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("header.csv");
+        fs::write(&path, "id, name\n1,Alice\n").unwrap();
+        assert_eq!(read_reference_header(&path).unwrap(), vec!["id", "name"]);
+
+        fs::write(&path, "id,,name\n").unwrap();
+        assert!(read_reference_header(&path).is_err());
+    }
+
+    #[test]
+    fn test_write_output_splits_parts() {
+        let test_dir = TempDir::new().unwrap();
+        let header = vec!["n".to_string()];
+        let rows: Vec<Vec<String>> = (1..=5).map(|n| vec![n.to_string()]).collect();
+        let options = WriteOptions {
+            max_rows: Some(2),
+            ..Default::default()
+        };
+
+        let (name, paths) =
+            write_output(test_dir.path(), "combined_1.csv", &header, &rows, &options, None).unwrap();
+        let last = test_dir.path().join("combined_1_part3.csv");
+        assert!(name.ends_with(&format!("combined_1_part1.csv to {}", last.display())));
+        assert_eq!(paths.len(), 3);
+        assert_eq!(fs::read_to_string(&paths[2]).unwrap(), "n\n5\n");
+        assert!(paths[1].ends_with("combined_1_part2.csv"));
+
+        // Outputs that fit in one part keep their name
+        let (_, paths) =
+            write_output(test_dir.path(), "single_1.csv", &header, &rows[..2], &options, None).unwrap();
+        assert_eq!(paths, vec![test_dir.path().join("single_1.csv")]);
+    }
+
+    #[test]
+    fn test_write_jsonl() {
+        let test_dir = TempDir::new().unwrap();
+        let header = vec!["name".to_string(), "note".to_string()];
+        let rows = vec![
+            vec!["Alice".to_string(), "says \"hi\"".to_string()],
+            vec!["Bob".to_string(), "".to_string()],
+        ];
+        let mut options = WriteOptions {
+            jsonl: true,
+            ..Default::default()
+        };
+
+        let (name, _) =
+            write_output(test_dir.path(), "combined_1.csv", &header, &rows, &options, None).unwrap();
+        assert!(name.ends_with("combined_1.jsonl"));
+        assert_eq!(
+            fs::read_to_string(test_dir.path().join("combined_1.jsonl")).unwrap(),
+            "{\"name\":\"Alice\",\"note\":\"says \\\"hi\\\"\"}\n{\"name\":\"Bob\",\"note\":\"\"}\n"
+        );
+
+        options.omit_empty = true;
+        write_output(test_dir.path(), "combined_1.csv", &header, &rows[1..], &options, None).unwrap();
+        assert_eq!(
+            fs::read_to_string(test_dir.path().join("combined_1.jsonl")).unwrap(),
+            "{\"name\":\"Bob\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_is_path_valid_directory() {
+        let test_dir = TempDir::new().unwrap();
+        assert!(!is_path_valid(test_dir.path()));
+    }
+
+    #[test]
+    fn test_read_csv_file() {
+        let test_dir = TempDir::new().unwrap();
+        let csv_path = test_dir.path().join("test.csv");
+
+        let csv_content = "Name,Age,City\nAlice,30,New York\nBob,25,Los Angeles\nCharlie,35,Chicago";
+        let mut file = fs::File::create(&csv_path).unwrap();
+        file.write_all(csv_content.as_bytes()).unwrap();
+
+        let result = read_csv_file(&csv_path, &ReadOptions::default()).unwrap();
+
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0], vec!["Name", "Age", "City"]);
+        assert_eq!(result[1], vec!["Alice", "30", "New York"]);
+        assert_eq!(result[2], vec!["Bob", "25", "Los Angeles"]);
+        assert_eq!(result[3], vec!["Charlie", "35", "Chicago"]);
+    }
+
+    #[test]
+    fn test_auto_delimiter_per_file() {
+        let test_dir = TempDir::new().unwrap();
+        let comma = test_dir.path().join("comma.csv");
+        let semicolon = test_dir.path().join("semicolon.csv");
+        fs::write(&comma, "Name,City\nAlice,Paris\n").unwrap();
+        fs::write(&semicolon, "Name;City\nBob;Rome\n").unwrap();
+
+        let args = Args::parse_from(["csv_combine", "--auto-delimiter"]);
+        let options = ReadOptions::from_args(&args).unwrap();
+        let first = read_csv_file(&comma, &options).unwrap();
+        let second = read_csv_file(&semicolon, &options).unwrap();
+        assert_eq!(second, vec![vec!["Name", "City"], vec!["Bob", "Rome"]]);
+        // Both land in the same group and merge into one header
+        assert_eq!(generate_header_hash(&first[0]), generate_header_hash(&second[0]));
+        assert_eq!(merge_headers(&[first[0].clone(), second[0].clone()]), vec!["Name", "City"]);
+
+        // Without it the semicolon file is one column
+        let second = read_csv_file(&semicolon, &ReadOptions::default()).unwrap();
+        assert_eq!(second[0], vec!["Name;City"]);
+    }
+
+    #[test]
+    fn test_data_starts_after_marker() {
+        let test_dir = TempDir::new().unwrap();
+        let report = test_dir.path().join("report.csv");
+        let plain = test_dir.path().join("plain.csv");
+        fs::write(&report, "Sales report\nRun: 2024-05-01,by ops\n---DATA---,,\nName,City\nAlice,Paris\n").unwrap();
+        fs::write(&plain, "Name,City\nBob,Rome\n").unwrap();
+
+        let args = Args::parse_from(["csv_combine", "--data-starts-after", "---DATA---"]);
+        let options = ReadOptions::from_args(&args).unwrap();
+        assert_eq!(
+            get_file_contents(&report, &options).unwrap(),
+            vec![vec!["Name", "City"], vec!["Alice", "Paris"]]
+        );
+        let err = get_file_contents(&plain, &options).unwrap_err();
+        assert!(err.to_string().contains("no '---DATA---' line"));
+    }
+
+    #[test]
+    fn test_read_within_timeout() {
+        let path = Path::new("slow.xlsx");
+        let slow = |_: &Path, _: &ReadOptions| -> Result<usize> {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(1)
+        };
+        let err = read_within(Some(Duration::from_millis(10)), path, &ReadOptions::default(), slow).unwrap_err();
+        assert!(err.to_string().contains("--timeout-per-file"));
+
+        let fast = |path: &Path, _: &ReadOptions| -> Result<usize> { Ok(path.as_os_str().len()) };
+        assert_eq!(read_within(Some(Duration::from_secs(5)), path, &ReadOptions::default(), fast).unwrap(), 9);
+        assert_eq!(read_within(None, path, &ReadOptions::default(), fast).unwrap(), 9);
+    }
+
+    #[test]
+    fn test_read_csv_file_empty() {
+        let test_dir = TempDir::new().unwrap();
+        let csv_path = test_dir.path().join("empty.csv");
+        fs::File::create(&csv_path).unwrap();
+
+        let result = read_csv_file(&csv_path, &ReadOptions::default()).unwrap();
+        // An empty CSV file still has an empty header row
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].len(), 0);
+    }
+
+    #[test]
+    fn test_write_combined_csv_pads_short_rows() {
+        let test_dir = TempDir::new().unwrap();
+        let output_path = test_dir.path().join("output.csv");
+        let header = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+        let data = vec![
+            vec!["Alice".to_string(), "30".to_string(), "Paris".to_string()],
+            vec!["Bob".to_string()],
+        ];
+
+        write_combined_csv(&output_path, &header, &data, &CsvWriteOptions::default()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&output_path).unwrap(),
+            "Name,Age,City\nAlice,30,Paris\nBob,,\n"
+        );
+
+        let strict = CsvWriteOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let err = write_combined_csv(&output_path, &header, &data, &strict).unwrap_err();
+        assert!(err.to_string().starts_with("Failed to write data row 2 of"));
+        assert!(format!("{:#}", err).contains("has 1 of the header's 3 columns"));
+    }
+
+    #[test]
+    fn test_write_combined_csv_with_write_buffer() {
+        let test_dir = TempDir::new().unwrap();
+        let output_path = test_dir.path().join("output.csv");
+        let header = vec!["Name".to_string(), "City".to_string()];
+        let data = vec![vec!["Alice".to_string(), "Paris".to_string()]];
+
+        // A buffer smaller than a row still writes everything, in order
+        let options = CsvWriteOptions {
+            comment: vec!["# export".to_string()],
+            buffer_size: Some(3),
+            ..Default::default()
+        };
+        write_combined_csv(&output_path, &header, &data, &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(&output_path).unwrap(),
+            "# export\nName,City\nAlice,Paris\n"
+        );
+    }
+
+    #[test]
+    fn test_write_combined_csv() {
+        let test_dir = TempDir::new().unwrap();
+        let output_path = test_dir.path().join("output.csv");
+
+        let header = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+        let data = vec![
+            vec!["Alice".to_string(), "30".to_string(), "New York".to_string()],
+            vec!["Bob".to_string(), "25".to_string(), "Los Angeles".to_string()],
+        ];
+
+        write_combined_csv(
+            output_path.to_str().unwrap(),
+            &header,
+            &data,
+            &CsvWriteOptions::default(),
+        ).unwrap();
+
+        assert!(output_path.exists());
+
+        let result = read_csv_file(&output_path, &ReadOptions::default()).unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0], header);
+        assert_eq!(result[1], data[0]);
+        assert_eq!(result[2], data[1]);
+    }
+
+    #[test]
+    fn test_get_files_from_directory() {
+        let test_dir = TempDir::new().unwrap();
+
+        // Create test files
+        fs::File::create(test_dir.path().join("file1.csv")).unwrap();
+        fs::File::create(test_dir.path().join("file2.xlsx")).unwrap();
+        fs::File::create(test_dir.path().join("file3.txt")).unwrap(); // Should be ignored
+
+        let files = get_files(test_dir.path()).unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().any(|f| f.file_name().unwrap() == "file1.csv"));
+        assert!(files.iter().any(|f| f.file_name().unwrap() == "file2.xlsx"));
+        assert!(!files.iter().any(|f| f.file_name().unwrap() == "file3.txt"));
+    }
+
+    #[test]
+    fn test_scan_grouping_is_deterministic() {
+        let test_dir = TempDir::new().unwrap();
+        let dir = test_dir.path();
+        fs::create_dir(dir.join("nested")).unwrap();
+        // Created out of name order. Read c, b, a, all three form one group;
+        // read a, b, c, b overlaps a by too little and starts its own
+        fs::write(dir.join("nested/c.csv"), "id,name,phone,city\n").unwrap();
+        fs::write(dir.join("b.csv"), "name,phone,city\n").unwrap();
+        fs::write(dir.join("a.csv"), "id,name\n").unwrap();
+
+        let group_names = |files: &[PathBuf]| {
+            let headers: Vec<Vec<String>> = files
+                .iter()
+                .map(|file| get_file_contents(file, &ReadOptions::default()).unwrap().remove(0))
+                .collect();
+            group_files_by(&headers, 0.5, 0, MergeStrategy::default(), SimilarityMetric::Jaccard)
+                .into_iter()
+                .map(|group| group.iter().map(|&idx| files[idx].strip_prefix(dir).unwrap().to_path_buf()).collect())
+                .collect::<Vec<Vec<PathBuf>>>()
+        };
+        // Discovery as a run does it
+        let scan = || {
+            let mut files = get_files(dir).unwrap();
+            sort_files(&mut files, FileOrder::Name, false);
+            files
+        };
+
+        let first = group_names(&scan());
+        assert_eq!(first, group_names(&scan()));
+        assert_eq!(
+            first,
+            vec![vec![PathBuf::from("a.csv"), PathBuf::from("nested/c.csv")], vec![PathBuf::from("b.csv")]]
+        );
+
+        let mut reversed = scan();
+        reversed.reverse();
+        assert_eq!(group_names(&reversed).len(), 1);
+    }
+
+    #[test]
+    fn test_get_files_single_file() {
+        let test_dir = TempDir::new().unwrap();
+        let csv_path = test_dir.path().join("single.csv");
+        fs::File::create(&csv_path).unwrap();
+
+        let files = get_files(&csv_path).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0], csv_path);
+    }
+
+    #[test]
+    fn test_get_file_contents_csv() {
+        let test_dir = TempDir::new().unwrap();
+        let csv_path = test_dir.path().join("test.csv");
+
+        let csv_content = "A,B,C\n1,2,3\n4,5,6";
+        let mut file = fs::File::create(&csv_path).unwrap();
+        file.write_all(csv_content.as_bytes()).unwrap();
+
+        let result = get_file_contents(&csv_path, &ReadOptions::default()).unwrap();
+
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0], vec!["A", "B", "C"]);
+        assert_eq!(result[1], vec!["1", "2", "3"]);
+        assert_eq!(result[2], vec!["4", "5", "6"]);
+    }
+
+    #[test]
+    fn test_csv_with_quotes_and_commas() {
+        let test_dir = TempDir::new().unwrap();
+        let csv_path = test_dir.path().join("complex.csv");
+
+        let csv_content = "Name,Description,Price\nProduct1,\"A product, with comma\",10.99\nProduct2,\"Another \"\"quoted\"\" item\",20.50";
+        let mut file = fs::File::create(&csv_path).unwrap();
+        file.write_all(csv_content.as_bytes()).unwrap();
+
+        let result = read_csv_file(&csv_path, &ReadOptions::default()).unwrap();
+
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0], vec!["Name", "Description", "Price"]);
+        assert_eq!(result[1], vec!["Product1", "A product, with comma", "10.99"]);
+        assert_eq!(result[2], vec!["Product2", "Another \"quoted\" item", "20.50"]);
+    }
+
+    #[test]
+    fn test_grouping_files_by_headers() {
+        let test_dir = TempDir::new().unwrap();
+
+        // Create files with same headers
+        let file1_content = "Name,Age\nAlice,30\nBob,25";
+        let file2_content = "Name,Age\nCharlie,35\nDave,40";
+
+        let mut file1 = fs::File::create(test_dir.path().join("file1.csv")).unwrap();
+        file1.write_all(file1_content.as_bytes()).unwrap();
+
+        let mut file2 = fs::File::create(test_dir.path().join("file2.csv")).unwrap();
+        file2.write_all(file2_content.as_bytes()).unwrap();
+
+        let data1 = get_file_contents(test_dir.path().join("file1.csv"), &ReadOptions::default()).unwrap();
+        let data2 = get_file_contents(test_dir.path().join("file2.csv"), &ReadOptions::default()).unwrap();
+
+        // Both files should have the same header
+        assert_eq!(data1[0], data2[0]);
+        assert_eq!(data1[0], vec!["Name", "Age"]);
+    }
+
+    #[test]
+    fn test_grouping_files_with_different_headers() {
+        let test_dir = TempDir::new().unwrap();
+
+        // Create files with different headers
+        let file1_content = "Name,Age\nAlice,30";
+        let file2_content = "Product,Price\nWidget,10.99";
+
+        let mut file1 = fs::File::create(test_dir.path().join("file1.csv")).unwrap();
+        file1.write_all(file1_content.as_bytes()).unwrap();
+
+        let mut file2 = fs::File::create(test_dir.path().join("file2.csv")).unwrap();
+        file2.write_all(file2_content.as_bytes()).unwrap();
+
+        let data1 = get_file_contents(test_dir.path().join("file1.csv"), &ReadOptions::default()).unwrap();
+        let data2 = get_file_contents(test_dir.path().join("file2.csv"), &ReadOptions::default()).unwrap();
+
+        // Files should have different headers
+        assert_ne!(data1[0], data2[0]);
+        assert_eq!(data1[0], vec!["Name", "Age"]);
+        assert_eq!(data2[0], vec!["Product", "Price"]);
+
+        // Should generate different hashes
+        let hash1 = generate_header_hash(&data1[0]);
+        let hash2 = generate_header_hash(&data2[0]);
+        assert_ne!(hash1, hash2);
+    }
+
+    #[test]
+    fn test_read_header_file_single_line() {
+        let test_dir = TempDir::new().unwrap();
+        let header_path = test_dir.path().join("dump.header");
+        fs::write(&header_path, "id, name ,amount\n").unwrap();
+
+        let header = read_header_file(&header_path).unwrap();
+        assert_eq!(header, vec!["id", "name", "amount"]);
+    }
+
+    #[test]
+    fn test_read_header_file_one_per_line() {
+        let test_dir = TempDir::new().unwrap();
+        let header_path = test_dir.path().join("dump.schema");
+        fs::write(&header_path, "id\nname\n\namount\n").unwrap();
+
+        let header = read_header_file(&header_path).unwrap();
+        assert_eq!(header, vec!["id", "name", "amount"]);
+    }
+
+    #[test]
+    fn test_read_csv_file_with_header_from() {
+        let test_dir = TempDir::new().unwrap();
+        let csv_path = test_dir.path().join("dump.csv");
+        fs::write(&csv_path, "1,Alice,10.00\n2,Bob,20.00").unwrap();
+
+        let options = ReadOptions {
+            header: Some(vec!["id".to_string(), "name".to_string(), "amount".to_string()]),
+            ..Default::default()
+        };
+        let result = read_csv_file(&csv_path, &options).unwrap();
+
+        // The first line is data, not a header
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0], vec!["id", "name", "amount"]);
+        assert_eq!(result[1], vec!["1", "Alice", "10.00"]);
+        assert_eq!(result[2], vec!["2", "Bob", "20.00"]);
+    }
+
+    #[test]
+    fn test_read_csv_file_mmap_matches_buffered() {
+        let test_dir = TempDir::new().unwrap();
+        let csv_path = test_dir.path().join("complex.csv");
+
+        let csv_content = "Name,Description,Price\nProduct1,\"A product, with comma\",10.99\nProduct2,\"Another \"\"quoted\"\" item\",20.50";
+        fs::write(&csv_path, csv_content).unwrap();
+
+        let options = ReadOptions {
+            mmap: true,
+            ..Default::default()
+        };
+        let mapped = read_csv_file(&csv_path, &options).unwrap();
+        let buffered = read_csv_file(&csv_path, &ReadOptions::default()).unwrap();
+
+        assert_eq!(mapped, buffered);
+        assert_eq!(mapped[2], vec!["Product2", "Another \"quoted\" item", "20.50"]);
+    }
+
+    #[test]
+    fn test_read_csv_file_mmap_empty() {
         let test_dir = TempDir::new().unwrap();
-        let csv_path = test_dir.path().join("test.csv");
+        let csv_path = test_dir.path().join("empty.csv");
         fs::File::create(&csv_path).unwrap();
 
-        assert!(is_path_valid(&csv_path));
+        let options = ReadOptions {
+            mmap: true,
+            ..Default::default()
+        };
+        let result = read_csv_file(&csv_path, &options).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].len(), 0);
     }
 
     #[test]
-    fn test_is_path_valid_xlsx() {
-        let test_dir = TempDir::new().unwrap();
-        let xlsx_path = test_dir.path().join("test.xlsx");
-        fs::File::create(&xlsx_path).unwrap();
+    fn test_missing_columns_none_missing() {
+        let header = vec!["id".to_string(), "date".to_string(), "amount".to_string(), "note".to_string()];
+        let required = vec!["id".to_string(), " date".to_string(), "amount".to_string()];
 
-        assert!(is_path_valid(&xlsx_path));
+        assert!(missing_columns(&header, &required).is_empty());
     }
 
     #[test]
-    fn test_is_path_valid_invalid_extension() {
-        let test_dir = TempDir::new().unwrap();
-        let txt_path = test_dir.path().join("test.txt");
-        fs::File::create(&txt_path).unwrap();
+    fn test_missing_columns_reports_missing() {
+        let header = vec!["id".to_string(), "note".to_string()];
+        let required = vec!["id".to_string(), "date".to_string(), "amount".to_string()];
 
-        assert!(!is_path_valid(&txt_path));
+        assert_eq!(missing_columns(&header, &required), vec!["date", "amount"]);
     }
 
     #[test]
-    fn test_is_path_valid_directory() {
-        let test_dir = TempDir::new().unwrap();
-        assert!(!is_path_valid(test_dir.path()));
+    fn test_must_have_parses_comma_list() {
+        let args = Args::parse_from(["csv_combine", "--must-have", "id,date,amount"]);
+        assert_eq!(args.must_have, vec!["id", "date", "amount"]);
     }
 
     #[test]
-    fn test_read_csv_file() {
+    fn test_parse_terminator() {
+        assert!(matches!(parse_terminator("crlf").unwrap(), csv::Terminator::CRLF));
+        assert!(matches!(parse_terminator("LF").unwrap(), csv::Terminator::Any(b'\n')));
+        assert!(matches!(parse_terminator("|").unwrap(), csv::Terminator::Any(b'|')));
+        assert!(parse_terminator("nope").is_err());
+    }
+
+    #[test]
+    fn test_write_combined_csv_crlf_round_trip() {
         let test_dir = TempDir::new().unwrap();
-        let csv_path = test_dir.path().join("test.csv");
+        let output_path = test_dir.path().join("output.csv");
 
-        let csv_content = "Name,Age,City\nAlice,30,New York\nBob,25,Los Angeles\nCharlie,35,Chicago";
-        let mut file = fs::File::create(&csv_path).unwrap();
-        file.write_all(csv_content.as_bytes()).unwrap();
+        let header = vec!["Name".to_string(), "Age".to_string()];
+        let data = vec![vec!["Alice".to_string(), "30".to_string()]];
+        let options = CsvWriteOptions {
+            terminator: Some(csv::Terminator::CRLF),
+            ..Default::default()
+        };
 
-        let result = read_csv_file(&csv_path).unwrap();
+        write_combined_csv(output_path.to_str().unwrap(), &header, &data, &options).unwrap();
 
-        assert_eq!(result.len(), 4);
-        assert_eq!(result[0], vec!["Name", "Age", "City"]);
-        assert_eq!(result[1], vec!["Alice", "30", "New York"]);
-        assert_eq!(result[2], vec!["Bob", "25", "Los Angeles"]);
-        assert_eq!(result[3], vec!["Charlie", "35", "Chicago"]);
+        let written = fs::read_to_string(&output_path).unwrap();
+        assert_eq!(written, "Name,Age\r\nAlice,30\r\n");
+
+        // The reader accepts CRLF regardless of the output setting
+        let result = read_csv_file(&output_path, &ReadOptions::default()).unwrap();
+        assert_eq!(result, vec![header, data[0].clone()]);
     }
 
     #[test]
-    fn test_read_csv_file_empty() {
+    fn test_write_combined_csv_with_comment() {
         let test_dir = TempDir::new().unwrap();
-        let csv_path = test_dir.path().join("empty.csv");
-        fs::File::create(&csv_path).unwrap();
+        let output_path = test_dir.path().join("output.csv");
 
-        let result = read_csv_file(&csv_path).unwrap();
-        // An empty CSV file still has an empty header row
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0].len(), 0);
+        let args = Args::parse_from([
+            "csv_combine",
+            "--output-comment",
+            "generated by csv_combine\nsource: exports",
+            "--comment-char",
+            ";",
+        ]);
+        let header = vec!["Name".to_string()];
+        let data = vec![vec!["Alice".to_string()]];
+
+        write_combined_csv(&output_path, &header, &data, &WriteOptions::from_args(&args).csv).unwrap();
+
+        let written = fs::read_to_string(&output_path).unwrap();
+        assert_eq!(written, "; generated by csv_combine\n; source: exports\nName\nAlice\n");
     }
 
     #[test]
-    fn test_write_combined_csv() {
+    fn test_normalize_unicode_headers_group_together() {
         let test_dir = TempDir::new().unwrap();
-        let output_path = test_dir.path().join("output.csv");
+        let nfc_path = test_dir.path().join("nfc.csv");
+        let nfd_path = test_dir.path().join("nfd.csv");
 
-        let header = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
-        let data = vec![
-            vec!["Alice".to_string(), "30".to_string(), "New York".to_string()],
-            vec!["Bob".to_string(), "25".to_string(), "Los Angeles".to_string()],
-        ];
+        // Visually identical headers: precomposed vs combining accent, NBSP vs space
+        fs::write(&nfc_path, "Caf\u{e9},Unit Price\nLatte,4.50").unwrap();
+        fs::write(&nfd_path, "Cafe\u{301},Unit\u{a0}Price\nMocha,5.00").unwrap();
 
-        write_combined_csv(
-            output_path.to_str().unwrap(),
-            &header,
-            &data,
-        ).unwrap();
+        let raw1 = get_file_contents(&nfc_path, &ReadOptions::default()).unwrap();
+        let raw2 = get_file_contents(&nfd_path, &ReadOptions::default()).unwrap();
+        assert!(!headers_are_compatible(&raw1[0], &raw2[0]));
 
-        assert!(output_path.exists());
+        let options = ReadOptions {
+            normalize_unicode: true,
+            ..Default::default()
+        };
+        let data1 = get_file_contents(&nfc_path, &options).unwrap();
+        let data2 = get_file_contents(&nfd_path, &options).unwrap();
 
-        let result = read_csv_file(&output_path).unwrap();
-        assert_eq!(result.len(), 3);
-        assert_eq!(result[0], header);
-        assert_eq!(result[1], data[0]);
-        assert_eq!(result[2], data[1]);
+        assert_eq!(data1[0], data2[0]);
+        assert_eq!(data2[0], vec!["Caf\u{e9}", "Unit Price"]);
+        assert!(headers_are_compatible(&data1[0], &data2[0]));
     }
 
     #[test]
-    fn test_get_files_from_directory() {
+    fn test_delimiter_rule_matching() {
+        let substring = DelimiterRule::parse("vendorA=;").unwrap();
+        assert_eq!(substring.delimiter, b';');
+        assert!(substring.matches("2024_vendorA_export.csv"));
+        assert!(!substring.matches("vendorB.csv"));
+
+        let glob = DelimiterRule::parse("*_eu.csv=\\t").unwrap();
+        assert_eq!(glob.delimiter, b'\t');
+        assert!(glob.matches("sales_eu.csv"));
+        assert!(!glob.matches("sales_us.csv"));
+
+        assert!(DelimiterRule::parse("vendorA").is_err());
+        assert!(DelimiterRule::parse("vendorA=;;").is_err());
+    }
+
+    #[test]
+    fn test_read_csv_file_with_delimiter_map() {
         let test_dir = TempDir::new().unwrap();
+        let comma_path = test_dir.path().join("vendorB.csv");
+        let semicolon_path = test_dir.path().join("vendorA.csv");
+        fs::write(&comma_path, "Name,Price\nWidget,\"1,50\"").unwrap();
+        fs::write(&semicolon_path, "Name;Price\nGadget;2,75").unwrap();
+
+        let options = ReadOptions {
+            delimiter_map: vec![DelimiterRule::parse("vendorA=;").unwrap()],
+            ..Default::default()
+        };
 
-        // Create test files
-        fs::File::create(test_dir.path().join("file1.csv")).unwrap();
-        fs::File::create(test_dir.path().join("file2.xlsx")).unwrap();
-        fs::File::create(test_dir.path().join("file3.txt")).unwrap(); // Should be ignored
+        let comma = read_csv_file(&comma_path, &options).unwrap();
+        let semicolon = read_csv_file(&semicolon_path, &options).unwrap();
 
-        let files = get_files(test_dir.path()).unwrap();
+        assert_eq!(comma, vec![vec!["Name", "Price"], vec!["Widget", "1,50"]]);
+        assert_eq!(semicolon, vec![vec!["Name", "Price"], vec!["Gadget", "2,75"]]);
+    }
 
-        assert_eq!(files.len(), 2);
-        assert!(files.iter().any(|f| f.file_name().unwrap() == "file1.csv"));
-        assert!(files.iter().any(|f| f.file_name().unwrap() == "file2.xlsx"));
-        assert!(!files.iter().any(|f| f.file_name().unwrap() == "file3.txt"));
+    #[test]
+    fn test_count_data_rows() {
+        let file1 = vec![
+            vec!["Name".to_string()],
+            vec!["Alice".to_string()],
+            vec!["Bob".to_string()],
+        ];
+        let header_only = vec![vec!["Name".to_string()]];
+        let no_rows: Vec<Vec<String>> = Vec::new();
+
+        assert_eq!(count_data_rows([&file1, &header_only, &no_rows]), 2);
     }
 
     #[test]
-    fn test_get_files_single_file() {
+    fn test_read_csv_file_malformed_row_fails_by_default() {
         let test_dir = TempDir::new().unwrap();
-        let csv_path = test_dir.path().join("single.csv");
-        fs::File::create(&csv_path).unwrap();
-
-        let files = get_files(&csv_path).unwrap();
+        let csv_path = test_dir.path().join("corrupt.csv");
+        fs::write(&csv_path, "Name,Age\nAlice,30\nBroken,row,extra\nBob,25").unwrap();
 
-        assert_eq!(files.len(), 1);
-        assert_eq!(files[0], csv_path);
+        assert!(read_csv_file(&csv_path, &ReadOptions::default()).is_err());
     }
 
     #[test]
-    fn test_get_file_contents_csv() {
+    fn test_read_csv_file_skip_bad_rows() {
         let test_dir = TempDir::new().unwrap();
-        let csv_path = test_dir.path().join("test.csv");
+        let csv_path = test_dir.path().join("corrupt.csv");
+        let mut content = b"Name,Age\nAlice,30\nBroken,row,extra\n".to_vec();
+        content.extend_from_slice(b"Bad\xff,1\nBob,25");
+        fs::write(&csv_path, content).unwrap();
+
+        let options = ReadOptions {
+            skip_bad_rows: true,
+            ..Default::default()
+        };
+        let result = read_csv_file(&csv_path, &options).unwrap();
 
-        let csv_content = "A,B,C\n1,2,3\n4,5,6";
-        let mut file = fs::File::create(&csv_path).unwrap();
-        file.write_all(csv_content.as_bytes()).unwrap();
+        assert_eq!(
+            result,
+            vec![vec!["Name", "Age"], vec!["Alice", "30"], vec!["Bob", "25"]]
+        );
+    }
 
-        let result = get_file_contents(&csv_path).unwrap();
+    #[test]
+    fn test_sample_rows_is_reproducible() {
+        let rows: Vec<Vec<String>> = (0..100).map(|i| vec![i.to_string()]).collect();
+        let args = Args::parse_from(["csv_combine", "--sample-n", "10", "--seed", "42"]);
+
+        let first = sample_rows(rows.clone(), &args, &mut ChaCha8Rng::seed_from_u64(42));
+        let second = sample_rows(rows.clone(), &args, &mut ChaCha8Rng::seed_from_u64(42));
+
+        assert_eq!(first.len(), 10);
+        assert_eq!(first, second);
+        // Original row order is preserved
+        let values: Vec<usize> = first.iter().map(|row| row[0].parse().unwrap()).collect();
+        assert!(values.windows(2).all(|pair| pair[0] < pair[1]));
+    }
 
-        assert_eq!(result.len(), 3);
-        assert_eq!(result[0], vec!["A", "B", "C"]);
-        assert_eq!(result[1], vec!["1", "2", "3"]);
-        assert_eq!(result[2], vec!["4", "5", "6"]);
+    #[test]
+    fn test_sample_rows_fraction() {
+        let rows: Vec<Vec<String>> = (0..40).map(|i| vec![i.to_string()]).collect();
+        let args = Args::parse_from(["csv_combine", "--sample-fraction", "0.25"]);
+
+        let sampled = sample_rows(rows.clone(), &args, &mut ChaCha8Rng::seed_from_u64(1));
+        assert_eq!(sampled.len(), 10);
+
+        let unsampled = sample_rows(rows.clone(), &Args::default(), &mut ChaCha8Rng::seed_from_u64(1));
+        assert_eq!(unsampled, rows);
+
+        assert!(Args::try_parse_from(["csv_combine", "--sample-fraction", "1.5"]).is_err());
     }
 
     #[test]
-    fn test_csv_with_quotes_and_commas() {
-        let test_dir = TempDir::new().unwrap();
-        let csv_path = test_dir.path().join("complex.csv");
+    fn test_join_on_conflicts() {
+        // Joined rows skip deduplication and spilling, so these are refused
+        assert!(Args::try_parse_from(["csv_combine", "--join-on", "id", "--dedup"]).is_err());
+        assert!(Args::try_parse_from(["csv_combine", "--join-on", "id", "--max-memory", "1000"]).is_err());
+        assert!(Args::try_parse_from(["csv_combine", "--join-on", "id", "--join-type", "inner"]).is_ok());
+    }
 
-        let csv_content = "Name,Description,Price\nProduct1,\"A product, with comma\",10.99\nProduct2,\"Another \"\"quoted\"\" item\",20.50";
-        let mut file = fs::File::create(&csv_path).unwrap();
-        file.write_all(csv_content.as_bytes()).unwrap();
+    #[test]
+    fn test_to_title_case() {
+        assert_eq!(to_title_case("customer name"), "Customer Name");
+        assert_eq!(to_title_case("UNIT_PRICE"), "Unit_Price");
+        assert_eq!(to_title_case("id"), "Id");
+    }
 
-        let result = read_csv_file(&csv_path).unwrap();
+    #[test]
+    fn test_canonicalize_casing_first() {
+        let mut header1 = vec!["Name".to_string(), "AGE".to_string()];
+        let mut header2 = vec!["name".to_string(), "age".to_string(), "City".to_string()];
 
-        assert_eq!(result.len(), 3);
-        assert_eq!(result[0], vec!["Name", "Description", "Price"]);
-        assert_eq!(result[1], vec!["Product1", "A product, with comma", "10.99"]);
-        assert_eq!(result[2], vec!["Product2", "Another \"quoted\" item", "20.50"]);
+        canonicalize_casing([&mut header1, &mut header2], CanonicalCasing::First);
+
+        assert_eq!(header1, vec!["Name", "AGE"]);
+        assert_eq!(header2, vec!["Name", "AGE", "City"]);
+        assert_eq!(merge_headers(&[header1, header2]), vec!["Name", "AGE", "City"]);
     }
 
     #[test]
-    fn test_generate_header_hash() {
-        let header1 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
-        let header2 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
-        let header3 = vec!["Name".to_string(), "Age".to_string()];
+    fn test_canonicalize_casing_title() {
+        let mut header1 = vec!["unit price".to_string()];
+        let mut header2 = vec!["UNIT PRICE".to_string()];
 
-        let hash1 = generate_header_hash(&header1);
-        let hash2 = generate_header_hash(&header2);
-        let hash3 = generate_header_hash(&header3);
+        canonicalize_casing([&mut header1, &mut header2], CanonicalCasing::Title);
 
-        // Same headers should produce same hash
-        assert_eq!(hash1, hash2);
-        // Different headers should produce different hash
-        assert_ne!(hash1, hash3);
-        // Hash should be a hex string
-        assert!(hash1.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(header1, vec!["Unit Price"]);
+        assert_eq!(header2, vec!["Unit Price"]);
     }
 
     #[test]
-    fn test_grouping_files_by_headers() {
-        let test_dir = TempDir::new().unwrap();
+    fn test_header_rejection() {
+        let header = vec!["id".to_string(), "date".to_string(), "amount".to_string()];
 
-        // Create files with same headers
-        let file1_content = "Name,Age\nAlice,30\nBob,25";
-        let file2_content = "Name,Age\nCharlie,35\nDave,40";
+        assert_eq!(header_rejection(&header, &Args::default()), None);
 
-        let mut file1 = fs::File::create(test_dir.path().join("file1.csv")).unwrap();
-        file1.write_all(file1_content.as_bytes()).unwrap();
+        let args = Args::parse_from(["csv_combine", "--max-columns", "2"]);
+        assert_eq!(
+            header_rejection(&header, &args).unwrap(),
+            "header has 3 columns, more than --max-columns 2"
+        );
 
-        let mut file2 = fs::File::create(test_dir.path().join("file2.csv")).unwrap();
-        file2.write_all(file2_content.as_bytes()).unwrap();
+        let args = Args::parse_from(["csv_combine", "--max-columns", "3", "--must-have", "id,sku"]);
+        assert_eq!(
+            header_rejection(&header, &args).unwrap(),
+            "missing required columns: sku"
+        );
+    }
+
+    #[test]
+    fn test_select_sheets() {
+        let sheets = vec![
+            "Summary".to_string(),
+            "Data_2024".to_string(),
+            "Data_2023".to_string(),
+        ];
 
-        let data1 = get_file_contents(test_dir.path().join("file1.csv")).unwrap();
-        let data2 = get_file_contents(test_dir.path().join("file2.csv")).unwrap();
+        let default = select_sheets(&sheets, &ReadOptions::default());
+        assert_eq!(default, vec!["Summary"]);
 
-        // Both files should have the same header
-        assert_eq!(data1[0], data2[0]);
-        assert_eq!(data1[0], vec!["Name", "Age"]);
+        let mut options = ReadOptions {
+            sheet_pattern: Some(glob::Pattern::new("Data*").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(select_sheets(&sheets, &options), vec!["Data_2024"]);
+
+        options.sheet_pattern_mode = SheetPatternMode::Combine;
+        assert_eq!(select_sheets(&sheets, &options), vec!["Data_2024", "Data_2023"]);
+
+        options.sheet_pattern = Some(glob::Pattern::new("Missing*").unwrap());
+        assert!(select_sheets(&sheets, &options).is_empty());
+
+        let all = ReadOptions {
+            combine_sheets: true,
+            ..Default::default()
+        };
+        assert_eq!(select_sheets(&sheets, &all), vec!["Summary", "Data_2024", "Data_2023"]);
     }
 
     #[test]
-    fn test_grouping_files_with_different_headers() {
+    fn test_stack_sheets_skips_mismatched_headers() {
+        let to_rows = |values: &[&[&str]]| -> Vec<Vec<String>> {
+            values
+                .iter()
+                .map(|row| row.iter().map(|v| v.to_string()).collect())
+                .collect()
+        };
+        let sheets = vec![
+            ("Jan".to_string(), to_rows(&[&["Name", "Qty"], &["Apple", "1"]])),
+            ("Feb".to_string(), to_rows(&[&["Name", "Qty"], &["Pear", "2"]])),
+            ("Notes".to_string(), to_rows(&[&["Comment"], &["ignore me"]])),
+        ];
+
+        let data = stack_sheets(Path::new("book.xlsx"), sheets);
+        assert_eq!(
+            data,
+            to_rows(&[&["Name", "Qty"], &["Apple", "1"], &["Pear", "2"]])
+        );
+    }
+
+    #[test]
+    fn test_combine_header_only_files() {
         let test_dir = TempDir::new().unwrap();
+        let file1 = test_dir.path().join("file1.csv");
+        let file2 = test_dir.path().join("file2.csv");
+        fs::write(&file1, "Name,Age\n").unwrap();
+        fs::write(&file2, "Name,Age,City").unwrap();
+
+        let data1 = get_file_contents(&file1, &ReadOptions::default()).unwrap();
+        let data2 = get_file_contents(&file2, &ReadOptions::default()).unwrap();
+        assert_eq!(data1.len(), 1);
+        assert_eq!(data2.len(), 1);
+
+        let headers = vec![data1[0].clone(), data2[0].clone()];
+        let groups = group_files(&headers, DEFAULT_OVERLAP_THRESHOLD);
+        assert_eq!(groups, vec![vec![0, 1]]);
+
+        let merged_header = merge_headers(&headers);
+        let mut all_data = map_rows_to_header(&data1[0], &merged_header, &data1[1..]);
+        all_data.extend(map_rows_to_header(&data2[0], &merged_header, &data2[1..]));
+        assert!(all_data.is_empty());
+
+        let output_path = test_dir.path().join("combined.csv");
+        write_combined_csv(&output_path, &merged_header, &all_data, &CsvWriteOptions::default()).unwrap();
+
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "Name,Age,City\n");
+        let result = read_csv_file(&output_path, &ReadOptions::default()).unwrap();
+        assert_eq!(result, vec![vec!["Name", "Age", "City"]]);
+    }
 
-        // Create files with different headers
-        let file1_content = "Name,Age\nAlice,30";
-        let file2_content = "Product,Price\nWidget,10.99";
+    #[test]
+    fn test_resolve_duplicate_columns() {
+        let header: Vec<String> = ["id", "name", "id", "id", "id_2"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
 
-        let mut file1 = fs::File::create(test_dir.path().join("file1.csv")).unwrap();
-        file1.write_all(file1_content.as_bytes()).unwrap();
+        let err = resolve_duplicate_columns(header.clone(), false).unwrap_err();
+        assert!(err.to_string().contains("duplicate columns: id"));
 
-        let mut file2 = fs::File::create(test_dir.path().join("file2.csv")).unwrap();
-        file2.write_all(file2_content.as_bytes()).unwrap();
+        let renamed = resolve_duplicate_columns(header, true).unwrap();
+        assert_eq!(renamed, vec!["id", "name", "id_3", "id_4", "id_2"]);
 
-        let data1 = get_file_contents(test_dir.path().join("file1.csv")).unwrap();
-        let data2 = get_file_contents(test_dir.path().join("file2.csv")).unwrap();
+        let unique = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(resolve_duplicate_columns(unique.clone(), false).unwrap(), unique);
+    }
 
-        // Files should have different headers
-        assert_ne!(data1[0], data2[0]);
-        assert_eq!(data1[0], vec!["Name", "Age"]);
-        assert_eq!(data2[0], vec!["Product", "Price"]);
+    #[test]
+    fn test_source_name() {
+        let test_dir = TempDir::new().unwrap();
+        let nested = test_dir.path().join("2024").join("sales.csv");
+        fs::create_dir_all(nested.parent().unwrap()).unwrap();
+        fs::write(&nested, "a").unwrap();
 
-        // Should generate different hashes
-        let hash1 = generate_header_hash(&data1[0]);
-        let hash2 = generate_header_hash(&data2[0]);
-        assert_ne!(hash1, hash2);
+        assert_eq!(
+            source_name(&nested, test_dir.path(), false),
+            nested.display().to_string()
+        );
+        assert_eq!(
+            source_name(&nested, test_dir.path(), true),
+            Path::new("2024").join("sales.csv").display().to_string()
+        );
+        // A single input file is named relative to its directory
+        assert_eq!(source_name(&nested, &nested, true), "sales.csv");
+    }
+
+    #[test]
+    fn test_transpose_key_values() {
+        let to_rows = |values: &[&[&str]]| -> Vec<Vec<String>> {
+            values
+                .iter()
+                .map(|row| row.iter().map(|v| v.to_string()).collect())
+                .collect()
+        };
+        let data = to_rows(&[
+            &["field", "value"],
+            &["Name", "Alice"],
+            &["Age", "30"],
+            &["Name", "Bob"],
+            &["City", "Paris"],
+        ]);
+
+        assert_eq!(
+            transpose_key_values(&data, "field", "value").unwrap(),
+            to_rows(&[
+                &["Name", "Age", "City"],
+                &["Alice", "30", ""],
+                &["Bob", "", "Paris"],
+            ])
+        );
+        assert!(transpose_key_values(&data, "key", "value").is_none());
     }
 
     #[test]
-    fn test_headers_are_compatible_exact_match() {
-        let header1 = vec!["Name".to_string(), "Age".to_string()];
-        let header2 = vec!["Name".to_string(), "Age".to_string()];
+    fn test_render_cell_preserves_excel_display() {
+        let date = Data::DateTime(ExcelDateTime::new(45352.0, ExcelDateTimeType::DateTime, false));
+        let time = Data::DateTime(ExcelDateTime::new(45352.5, ExcelDateTimeType::DateTime, false));
+        let duration = Data::DateTime(ExcelDateTime::new(1.5, ExcelDateTimeType::TimeDelta, false));
+
+        assert_eq!(render_cell(&date, false, ExcelDateSystem::Auto), "45352");
+        assert_eq!(render_cell(&date, true, ExcelDateSystem::Auto), "2024-03-01");
+        assert_eq!(render_cell(&time, true, ExcelDateSystem::Auto), "2024-03-01 12:00:00");
+        assert_eq!(render_cell(&duration, true, ExcelDateSystem::Auto), "36:00:00");
+        assert_eq!(render_cell(&Data::Float(12.0), true, ExcelDateSystem::Auto), "12");
+        assert_eq!(render_cell(&Data::Float(0.1 + 0.2), true, ExcelDateSystem::Auto), "0.3");
+        assert_eq!(render_cell(&Data::Float(0.1 + 0.2), false, ExcelDateSystem::Auto), "0.30000000000000004");
+        assert_eq!(render_cell(&Data::Bool(true), true, ExcelDateSystem::Auto), "TRUE");
+        assert_eq!(render_cell(&Data::String("$1.00".to_string()), true, ExcelDateSystem::Auto), "$1.00");
+    }
 
-        assert!(headers_are_compatible(&header1, &header2));
+    #[test]
+    fn test_render_cell_1904_date_system() {
+        // 2024-03-01 in a workbook using the 1904 date system
+        let declared = Data::DateTime(ExcelDateTime::new(43890.0, ExcelDateTimeType::DateTime, true));
+        assert_eq!(render_cell(&declared, true, ExcelDateSystem::Auto), "2024-03-01");
+        // Serials are left as stored outside date formatting
+        assert_eq!(render_cell(&declared, false, ExcelDateSystem::Auto), "43890");
+        assert_eq!(render_cell(&declared, true, ExcelDateSystem::Excel1900), "2020-02-29");
+
+        // The same serial where the workbook does not declare its system
+        let undeclared = Data::DateTime(ExcelDateTime::new(43890.0, ExcelDateTimeType::DateTime, false));
+        assert_eq!(render_cell(&undeclared, true, ExcelDateSystem::Auto), "2020-02-29");
+        assert_eq!(render_cell(&undeclared, true, ExcelDateSystem::Excel1904), "2024-03-01");
+        assert_eq!(render_cell(&undeclared, false, ExcelDateSystem::Excel1904), "43890");
+
+        let args = Args::parse_from(["csv_combine", "--excel-date-system", "1904"]);
+        assert_eq!(args.excel_date_system, ExcelDateSystem::Excel1904);
     }
 
     #[test]
-    fn test_headers_are_compatible_superset() {
-        let header1 = vec!["Name".to_string(), "Age".to_string()];
-        let header2 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+    fn test_finalize_header_case_fold() {
+        let merged: Vec<String> = ["order id", "Name", "NAME"].iter().map(|s| s.to_string()).collect();
+
+        let args = Args::parse_from(["csv_combine", "--columns-case-fold", "title"]);
+        assert!(finalize_header(&args, &merged).is_err());
+
+        let args = Args::parse_from([
+            "csv_combine",
+            "--columns-case-fold",
+            "upper",
+            "--rename-duplicates",
+        ]);
+        assert_eq!(
+            finalize_header(&args, &merged).unwrap(),
+            vec!["ORDER ID", "NAME", "NAME_2"]
+        );
+    }
 
-        // 2 common out of 3 total = 66.7% overlap - should be compatible
-        assert!(headers_are_compatible(&header1, &header2));
+    #[test]
+    fn test_normalize_headers() {
+        for name in ["Customer Name", "customer_name", "CustomerName", "customer-name", "CUSTOMER NAME"] {
+            assert_eq!(HeaderStyle::Snake.apply(name), "customer_name");
+            assert_eq!(HeaderStyle::Camel.apply(name), "customerName");
+            assert_eq!(HeaderStyle::Title.apply(name), "Customer Name");
+        }
+        assert_eq!(HeaderStyle::Snake.apply("HTTPStatusCode"), "http_status_code");
+        assert_eq!(HeaderStyle::Snake.apply("address2Line"), "address2_line");
+        assert_eq!(HeaderStyle::Camel.apply("--"), "--");
+
+        let merged: Vec<String> = ["Order ID", "orderId"].iter().map(|s| s.to_string()).collect();
+        let args = Args::parse_from(["csv_combine", "--normalize-headers", "snake", "--rename-duplicates"]);
+        assert_eq!(finalize_header(&args, &merged).unwrap(), vec!["order_id", "order_id_2"]);
+
+        // On read the two spellings become one column instead
+        let args = Args::parse_from(["csv_combine", "--normalize-headers", "snake", "--normalize-headers-on-read"]);
+        let mut header = merged.clone();
+        clean_header(&mut header, &ReadOptions::from_args(&args).unwrap());
+        assert_eq!(header, vec!["order_id", "order_id"]);
+        assert_eq!(finalize_header(&args, &["order_id".to_string()]).unwrap(), vec!["order_id"]);
     }
 
     #[test]
-    fn test_headers_are_compatible_partial_overlap() {
-        let header1 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
-        let header2 = vec!["Name".to_string(), "Age".to_string(), "Country".to_string()];
+    fn test_keep_tail_rows() {
+        let mut data: Vec<Vec<String>> = ["ts", "1", "2", "3", "4"]
+            .iter()
+            .map(|v| vec![v.to_string()])
+            .collect();
+
+        keep_tail_rows(&mut data, 10);
+        assert_eq!(data.len(), 5);
+
+        keep_tail_rows(&mut data, 2);
+        assert_eq!(data, vec![vec!["ts"], vec!["3"], vec!["4"]]);
 
-        // 2 common out of 4 total = 50% overlap - should be compatible
-        assert!(headers_are_compatible(&header1, &header2));
+        keep_tail_rows(&mut data, 0);
+        assert_eq!(data, vec![vec!["ts"]]);
     }
 
     #[test]
-    fn test_headers_are_not_compatible() {
-        let header1 = vec!["Name".to_string(), "Age".to_string()];
-        let header2 = vec!["Product".to_string(), "Price".to_string()];
+    fn test_threshold_report() {
+        let headers = vec![
+            vec!["id".to_string(), "name".to_string()],
+            vec!["id".to_string(), "name".to_string(), "city".to_string()],
+            vec!["sku".to_string()],
+        ];
+
+        let report = threshold_report(&headers, 0, MergeStrategy::default(), SimilarityMetric::Jaccard);
+        assert_eq!(report[0], "Overlap scores of 3 file pairs:");
+        assert_eq!(report[1], "  0.0-0.1:     2 ##");
+        assert_eq!(report[7], "  0.6-0.7:     1 #");
+        assert_eq!(
+            report[11],
+            "Suggested --overlap-threshold 0.33 (largest gap: 0.00 to 0.67), giving 2 groups"
+        );
 
-        // 0 common out of 4 total = 0% overlap - not compatible
-        assert!(!headers_are_compatible(&header1, &header2));
+        // The group count follows the run's grouping options
+        let report = threshold_report(&headers, 3, MergeStrategy::default(), SimilarityMetric::Jaccard);
+        assert!(report[11].ends_with("giving 3 groups"));
+
+        assert!(
+            threshold_report(&headers[..1], 0, MergeStrategy::default(), SimilarityMetric::Jaccard)[0]
+                .starts_with("Not enough")
+        );
     }
 
     #[test]
-    fn test_headers_are_not_compatible_low_overlap() {
-        let header1 = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
-        let header2 = vec!["Name".to_string(), "Product".to_string(), "Price".to_string()];
+    fn test_join_prefixes() {
+        let header = |cols: &[&str]| vec![cols.iter().map(|col| col.to_string()).collect::<Vec<_>>()];
+        let file_data = vec![
+            (PathBuf::from("in/2024/orders.csv"), header(&["id", "amount"])),
+            (PathBuf::from("in/2025/orders.csv"), header(&["id", "amount"])),
+            (PathBuf::from("in/notes.csv"), header(&["note"])),
+        ];
+        let input = Path::new("in");
+
+        let prefixes = join_prefixes(join::ColumnPrefix::Source, &[0, 1, 2], &file_data, input, "id").unwrap();
+        assert_eq!(prefixes, vec![Some("2024_orders".to_string()), Some("2025_orders".to_string()), None]);
+        let err = join_prefixes(join::ColumnPrefix::Filename, &[0, 1], &file_data, input, "id").unwrap_err();
+        assert!(err.to_string().contains("'orders' (try --prefix-columns-with source)"));
 
-        // 1 common out of 5 total = 20% overlap - not compatible
-        assert!(!headers_are_compatible(&header1, &header2));
+        // Only the extension differs, so no hint helps
+        let file_data = vec![
+            (PathBuf::from("in/orders.csv"), header(&["id", "amount"])),
+            (PathBuf::from("in/orders.xlsx"), header(&["id", "amount"])),
+        ];
+        let err = join_prefixes(join::ColumnPrefix::Source, &[0, 1], &file_data, input, "id").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "in/orders.csv and in/orders.xlsx both get the column prefix 'orders'"
+        );
     }
 
     #[test]
-    fn test_merge_headers_identical() {
+    fn test_long_headers() {
+        let names = vec!["a.csv".to_string(), "b.csv".to_string()];
         let headers = vec![
-            vec!["Name".to_string(), "Age".to_string()],
-            vec!["Name".to_string(), "Age".to_string()],
+            vec!["Name".to_string(), "City".to_string()],
+            vec!["Sku".to_string()],
         ];
+        assert_eq!(
+            long_headers(&names, &headers),
+            vec![
+                vec!["a.csv", "1", "Name"],
+                vec!["a.csv", "2", "City"],
+                vec!["b.csv", "1", "Sku"],
+            ]
+        );
+    }
 
-        let merged = merge_headers(&headers);
-        assert_eq!(merged, vec!["Name", "Age"]);
+    #[test]
+    fn test_group_rng() {
+        let rows: Vec<Vec<String>> = (0..50).map(|n| vec![n.to_string()]).collect();
+        let args = Args::parse_from(["csv_combine", "--sample-n", "5"]);
+        let sample = |idx| sample_rows(rows.clone(), &args, &mut group_rng(7, idx));
+
+        // The first group samples as the run's seed always has
+        assert_eq!(sample(0), sample_rows(rows.clone(), &args, &mut ChaCha8Rng::seed_from_u64(7)));
+        assert_eq!(sample(3), sample(3));
+        assert_ne!(sample(0), sample(1));
     }
 
     #[test]
-    fn test_merge_headers_superset() {
+    fn test_select_groups() {
         let headers = vec![
-            vec!["Name".to_string(), "Age".to_string()],
-            vec!["Name".to_string(), "Age".to_string(), "City".to_string()],
+            vec!["sku".to_string()],
+            vec!["id".to_string(), "name".to_string()],
+            vec!["id".to_string(), "name".to_string()],
         ];
+        let groups = vec![vec![0], vec![1, 2]];
+        let hash = generate_header_hash(&headers[1]);
 
-        let merged = merge_headers(&headers);
-        assert_eq!(merged, vec!["Name", "Age", "City"]);
+        let only = vec![hash, "0000".to_string()];
+        assert_eq!(select_groups(groups.clone(), &headers, &only, ColumnOrder::FirstSeen), vec![vec![1, 2]]);
+        assert!(select_groups(groups, &headers, &["0000".to_string()], ColumnOrder::FirstSeen).is_empty());
     }
 
     #[test]
-    fn test_merge_headers_different_order() {
+    fn test_single_group_error() {
+        let names = vec!["a.csv".to_string(), "b.csv".to_string(), "c.csv".to_string()];
         let headers = vec![
-            vec!["Name".to_string(), "Age".to_string(), "City".to_string()],
-            vec!["Age".to_string(), "Name".to_string(), "Country".to_string()],
+            vec!["sku".to_string()],
+            vec!["id".to_string(), "name".to_string()],
+            vec!["id".to_string(), "name".to_string()],
         ];
 
-        let merged = merge_headers(&headers);
-        // Should preserve order from first header, then add new columns
-        assert_eq!(merged, vec!["Name", "Age", "City", "Country"]);
+        let lines = single_group_error(&[vec![0], vec![1, 2]], &names, &headers);
+        assert_eq!(
+            lines,
+            vec![
+                "3 files form 2 groups, but --expect-single-group was given. \
+                 Files incompatible with the 2 file group of b.csv:",
+                "  a.csv: sku",
+            ]
+        );
     }
 
     #[test]
-    fn test_map_rows_to_header_same_headers() {
-        let old_header = vec!["Name".to_string(), "Age".to_string()];
-        let new_header = vec!["Name".to_string(), "Age".to_string()];
-        let rows = vec![
-            vec!["Alice".to_string(), "30".to_string()],
-            vec!["Bob".to_string(), "25".to_string()],
+    fn test_coalesce_columns() {
+        let rules = vec![CoalesceRule::parse("Phone:Phone1, Phone2,Phone3").unwrap()];
+        assert_eq!(rules[0].sources, vec!["Phone1", "Phone2", "Phone3"]);
+        assert!(CoalesceRule::parse("Phone").is_err());
+
+        let merged = vec!["Name".to_string(), "Phone2".to_string(), "Phone1".to_string()];
+        let header = add_coalesce_targets(merged, &rules);
+        assert_eq!(header, vec!["Name", "Phone2", "Phone1", "Phone"]);
+        assert_eq!(add_coalesce_targets(vec!["Name".to_string()], &rules), vec!["Name"]);
+
+        let mut rows = vec![
+            vec!["Alice".to_string(), "555-2".to_string(), " ".to_string(), "".to_string()],
+            vec!["Bob".to_string(), "555-4".to_string(), "555-3".to_string(), "".to_string()],
+            vec!["Carol".to_string(), "".to_string(), "".to_string(), "".to_string()],
+        ];
+        coalesce_columns(&header, &mut rows, &rules);
+        assert_eq!(rows[0][3], "555-2");
+        assert_eq!(rows[1][3], "555-3");
+        assert_eq!(rows[2][3], "");
+    }
+
+    #[test]
+    fn test_dedup_row_columns() {
+        let header: Vec<String> = ["Email", "Email2", "Email3"].iter().map(|s| s.to_string()).collect();
+        let mut rows = vec![vec!["a@x.com".to_string(), " a@x.com".to_string(), "b@x.com".to_string()]];
+
+        dedup_row_columns(&header, &mut rows, &header);
+        assert_eq!(rows, vec![vec!["a@x.com", "", "b@x.com"]]);
+    }
+
+    #[test]
+    fn test_approve_groups() {
+        let plans = vec!["files: a.csv".to_string(), "files: b.csv".to_string()];
+
+        let mut output = Vec::new();
+        let answers = approve_groups(&plans, &mut "maybe\ns\np\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(answers, Some(vec![false, true]));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Group 1 of 2: files: a.csv\n[p]roceed, [s]kip this group or [a]bort? "));
+        // The unrecognised answer asks again
+        assert_eq!(output.matches("[a]bort?").count(), 3);
+
+        assert_eq!(approve_groups(&plans, &mut "p\na\n".as_bytes(), &mut Vec::new()).unwrap(), None);
+        assert_eq!(approve_groups(&plans, &mut "p\n".as_bytes(), &mut Vec::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_filter_rows() {
+        let args = Args::parse_from(["csv_combine", "--where", "Age >= 18 AND \"Source File\" != 'b.csv'"]);
+        let header = vec!["Name".to_string(), "Age".to_string(), "Source File".to_string()];
+        let mut rows = vec![
+            vec!["Alice".to_string(), "30".to_string(), "a.csv".to_string()],
+            vec!["Bob".to_string(), "9".to_string(), "a.csv".to_string()],
+            vec!["Carol".to_string(), "40".to_string(), "b.csv".to_string()],
+        ];
+        filter_rows(&args, &header, &mut rows);
+        assert_eq!(rows, vec![vec!["Alice", "30", "a.csv"]]);
+
+        assert!(Args::try_parse_from(["csv_combine", "--where", "Age >"]).is_err());
+    }
+
+    #[test]
+    fn test_regrouping_problems() {
+        let args = Args::parse_from(["csv_combine"]);
+        let names: Vec<String> = ["a_part1.csv", "a_part2.csv", "b.csv"].map(String::from).to_vec();
+        let header = |cols: &str| cols.split(',').map(String::from).collect::<Vec<String>>();
+        let headers = vec![header("id,name"), header("id,name"), header("sku,price")];
+        let expected = vec![header("id,name"), header("sku,price")];
+
+        // Parts of one output group together
+        assert!(regrouping_problems(&names, &headers, &[0, 0, 1], &expected, &args).is_empty());
+
+        // Two written groups whose outputs would combine
+        let expected = vec![header("id,name"), header("id,name"), header("sku,price")];
+        let problems = regrouping_problems(&names, &headers, &[0, 1, 2], &expected, &args);
+        assert_eq!(
+            problems,
+            vec![
+                "3 output files form 2 groups, not the 3 written",
+                "outputs of different groups group together: a_part1.csv, a_part2.csv",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hash_columns() {
+        let header = vec!["Name".to_string(), "Email".to_string()];
+        let mut rows = vec![
+            vec!["Alice".to_string(), "a@example.com".to_string()],
+            vec!["Bob".to_string(), String::new()],
+            vec!["Alice".to_string(), "a@example.com".to_string()],
         ];
+        hash_columns(&header, &mut rows, &["Email".to_string()], "pepper");
 
-        let mapped = map_rows_to_header(&old_header, &new_header, &rows);
+        assert_eq!(rows[0][1].len(), 64);
+        assert_eq!(rows[0], rows[2]);
+        assert_eq!(rows[1], vec!["Bob", ""]);
 
-        assert_eq!(mapped.len(), 2);
-        assert_eq!(mapped[0], vec!["Alice", "30"]);
-        assert_eq!(mapped[1], vec!["Bob", "25"]);
+        let mut unsalted = vec![vec!["Alice".to_string(), "abc".to_string()]];
+        hash_columns(&header, &mut unsalted, &["Email".to_string()], "");
+        assert_eq!(unsalted[0][1], "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
     }
 
     #[test]
-    fn test_map_rows_to_header_with_new_columns() {
-        let old_header = vec!["Name".to_string(), "Age".to_string()];
-        let new_header = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
+    fn test_schema_checks_values_before_hashing() {
+        let test_dir = TempDir::new().unwrap();
+        let output = test_dir.path().to_str().unwrap();
+        let header = vec!["name".to_string(), "age".to_string()];
         let rows = vec![
             vec!["Alice".to_string(), "30".to_string()],
-            vec!["Bob".to_string(), "25".to_string()],
+            vec!["Bob".to_string(), "n/a".to_string()],
         ];
+        let args = Args::parse_from([
+            "csv_combine",
+            "--schema",
+            "age:int",
+            "--hash-columns",
+            "age",
+            "--output",
+            output,
+        ]);
+
+        let kept = enforce_schema_and_hash(&args, "combined_1.csv", &header, rows, &WriteOptions::default()).unwrap();
+        // The int passed --schema, then was hashed; its digest is no int
+        let mut expected = vec![vec!["Alice".to_string(), "30".to_string()]];
+        hash_columns(&header, &mut expected, &args.hash_columns, "");
+        assert_eq!(kept, expected);
+
+        let rejects = fs::read_to_string(test_dir.path().join("combined_1_rejects.csv")).unwrap();
+        assert!(!rejects.contains("n/a"));
+        assert!(rejects.starts_with("name,age,type_error\nBob,"));
+        assert!(rejects.contains("is not a valid int"));
+    }
+
+    #[test]
+    fn test_unmatched_columns() {
+        let header = |cols: &str| cols.split(',').map(String::from).collect::<Vec<String>>();
+        let headers = vec![header("id,quantity,notes"), header("id,quantity"), header("id,quantty")];
+
+        assert_eq!(
+            unmatched_columns(&headers),
+            vec![
+                ("notes".to_string(), 0, None),
+                ("quantty".to_string(), 2, Some("quantity".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pin_columns() {
+        let merged: Vec<String> = ["name", "date", "amount"].iter().map(|s| s.to_string()).collect();
+        let pinned = vec!["id".to_string(), "date".to_string()];
 
-        let mapped = map_rows_to_header(&old_header, &new_header, &rows);
+        assert_eq!(
+            pin_columns(merged.clone(), &pinned),
+            vec!["id", "date", "name", "amount"]
+        );
+        assert_eq!(pin_columns(merged.clone(), &[]), merged);
 
-        assert_eq!(mapped.len(), 2);
-        assert_eq!(mapped[0], vec!["Alice", "30", ""]);
-        assert_eq!(mapped[1], vec!["Bob", "25", ""]);
+        // Pinned columns missing from a file come out empty
+        let rows = map_rows_to_header(
+            &merged,
+            &pin_columns(merged.clone(), &pinned),
+            &[vec!["Widget".to_string(), "2024-03-01".to_string(), "5".to_string()]],
+        );
+        assert_eq!(rows, vec![vec!["", "2024-03-01", "Widget", "5"]]);
     }
 
     #[test]
-    fn test_map_rows_to_header_reordered_columns() {
-        let old_header = vec!["Name".to_string(), "Age".to_string(), "City".to_string()];
-        let new_header = vec!["City".to_string(), "Name".to_string(), "Age".to_string()];
-        let rows = vec![vec!["Alice".to_string(), "30".to_string(), "NYC".to_string()]];
+    fn test_read_columns_projects_csv() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("wide.csv");
+        fs::write(&path, "id,a,b,name,c\n1,x,y,Alice,z\n2,x,y,Bob\n").unwrap();
+
+        let options = ReadOptions {
+            read_columns: vec!["name".to_string(), "id".to_string()],
+            skip_bad_rows: true,
+            ..Default::default()
+        };
+        // The short second row is still detected against the full header width
+        let data = get_file_contents(&path, &options).unwrap();
+        assert_eq!(data, vec![vec!["id", "name"], vec!["1", "Alice"]]);
+
+        let options = ReadOptions {
+            read_columns: vec!["missing".to_string()],
+            ..Default::default()
+        };
+        assert!(get_file_contents(&path, &options).is_err());
+    }
 
-        let mapped = map_rows_to_header(&old_header, &new_header, &rows);
+    #[test]
+    fn test_no_files_found() {
+        let path = Path::new("wrong/folder");
+        assert!(no_files_found(path, &Args::default()).is_ok());
 
-        assert_eq!(mapped.len(), 1);
-        assert_eq!(mapped[0], vec!["NYC", "Alice", "30"]);
+        let args = Args::parse_from(["csv_combine", "--halt-on-empty"]);
+        let err = no_files_found(path, &args).unwrap_err();
+        assert!(err.to_string().contains("wrong/folder"));
     }
 
     #[test]
-    fn test_map_rows_to_header_mixed_columns() {
-        let old_header = vec!["Name".to_string(), "Age".to_string()];
-        let new_header = vec![
-            "Name".to_string(),
-            "Age".to_string(),
-            "City".to_string(),
-            "Country".to_string(),
+    fn test_rtrim_and_padded_columns() {
+        let header = vec!["code".to_string(), "name".to_string(), "note".to_string()];
+        let mut rows = vec![
+            vec!["A1".to_string(), "Alice   ".to_string(), "ok ".to_string()],
+            vec!["B2".to_string(), "Bob     ".to_string(), "fine".to_string()],
         ];
-        let rows = vec![vec!["Alice".to_string(), "30".to_string()]];
 
-        let mapped = map_rows_to_header(&old_header, &new_header, &rows);
+        assert_eq!(padded_columns(&header, &rows), vec!["name"]);
+
+        rtrim_columns(&header, &mut rows, &["name".to_string(), "note".to_string()]);
+        assert_eq!(rows[0], vec!["A1", "Alice", "ok"]);
+        assert_eq!(rows[1], vec!["B2", "Bob", "fine"]);
+        assert!(padded_columns(&header, &rows).is_empty());
+    }
+
+    #[test]
+    fn test_load_table_order() {
+        let test_dir = TempDir::new().unwrap();
+        let columns_path = test_dir.path().join("columns.txt");
+        fs::write(&columns_path, "id\nname\nloaded_at\n").unwrap();
+
+        let header_a = vec!["name".to_string(), "id".to_string()];
+        let header_b = vec!["id".to_string(), "extra".to_string()];
+        let headers = [header_a.as_slice(), header_b.as_slice()];
+
+        let err = load_table_order(&columns_path, headers, false).unwrap_err();
+        assert!(err.to_string().contains("loaded_at"));
+        assert_eq!(
+            load_table_order(&columns_path, headers, true).unwrap(),
+            vec!["id", "name", "loaded_at"]
+        );
+    }
+
+    #[test]
+    fn test_drop_index_column() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("pandas.csv");
+        let options = ReadOptions {
+            drop_index_column: true,
+            ..Default::default()
+        };
+
+        fs::write(&path, "Unnamed: 0,Name\n0,Alice\n1,Bob\n").unwrap();
+        let data = get_file_contents(&path, &options).unwrap();
+        assert_eq!(data, vec![vec!["Name"], vec!["Alice"], vec!["Bob"]]);
+
+        // Not a contiguous sequence, so it is real data
+        fs::write(&path, ",Name\n0,Alice\n5,Bob\n").unwrap();
+        let data = get_file_contents(&path, &options).unwrap();
+        assert_eq!(data[0], vec!["", "Name"]);
+    }
 
-        assert_eq!(mapped.len(), 1);
-        assert_eq!(mapped[0], vec!["Alice", "30", "", ""]);
+    #[test]
+    fn test_add_group_id() {
+        let args = Args::parse_from(["csv_combine", "--add-group-id", "--group-id-column", "schema"]);
+        let merged = vec!["Name".to_string()];
+        let mut rows = vec![vec!["Alice".to_string()]];
+
+        add_group_id(&mut rows, "3bdceadd");
+        assert_eq!(finalize_header(&args, &merged).unwrap(), vec!["Name", "schema"]);
+        assert_eq!(rows, vec![vec!["Alice", "3bdceadd"]]);
     }
 }
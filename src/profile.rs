@@ -0,0 +1,120 @@
+use log::info;
+use std::time::Duration;
+
+/// Number of slowest files listed by `--profile`
+const SLOWEST_FILES: usize = 5;
+
+/// Collects per-phase and per-file timings for `--profile`
+#[derive(Default)]
+pub struct Profiler {
+    /// Phases in the order they were first recorded
+    phases: Vec<(&'static str, Duration)>,
+    files: Vec<(String, Duration)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `elapsed` to the named phase
+    pub fn add(&mut self, phase: &'static str, elapsed: Duration) {
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => self.phases.push((phase, elapsed)),
+        }
+    }
+
+    /// Records how long a single input file took to read
+    pub fn add_file(&mut self, name: String, elapsed: Duration) {
+        self.files.push((name, elapsed));
+    }
+
+    /// Adds the timings of a profiler that ran alongside this one, such as a
+    /// group processed on another thread
+    pub fn merge(&mut self, other: Profiler) {
+        for (phase, elapsed) in other.phases {
+            self.add(phase, elapsed);
+        }
+        self.files.extend(other.files);
+    }
+
+    /// Logs the phase breakdown and the slowest files
+    pub fn report(&self) {
+        for line in self.summary() {
+            info!("{}", line);
+        }
+    }
+
+    fn summary(&self) -> Vec<String> {
+        let total: Duration = self.phases.iter().map(|(_, elapsed)| *elapsed).sum();
+        let total_secs = total.as_secs_f64().max(f64::EPSILON);
+
+        let mut lines = vec![format!("Profile: {:.3}s total", total.as_secs_f64())];
+        for (phase, elapsed) in &self.phases {
+            lines.push(format!(
+                "  {:<10} {:>9.3}s ({:.1}%)",
+                phase,
+                elapsed.as_secs_f64(),
+                elapsed.as_secs_f64() / total_secs * 100.0
+            ));
+        }
+
+        let mut slowest: Vec<&(String, Duration)> = self.files.iter().collect();
+        slowest.sort_by_key(|(_, elapsed)| std::cmp::Reverse(*elapsed));
+        if !slowest.is_empty() {
+            lines.push("Slowest files to read:".to_string());
+        }
+        for (name, elapsed) in slowest.into_iter().take(SLOWEST_FILES) {
+            lines.push(format!("  {:>9.3}s {}", elapsed.as_secs_f64(), name));
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_accumulates_phases_and_sorts_files() {
+        let mut profiler = Profiler::new();
+        profiler.add("reading", Duration::from_millis(3000));
+        profiler.add("mapping", Duration::from_millis(500));
+        profiler.add("mapping", Duration::from_millis(500));
+        profiler.add_file("small.csv".to_string(), Duration::from_millis(100));
+        profiler.add_file("big.xlsx".to_string(), Duration::from_millis(2900));
+
+        assert_eq!(
+            profiler.summary(),
+            vec![
+                "Profile: 4.000s total",
+                "  reading        3.000s (75.0%)",
+                "  mapping        1.000s (25.0%)",
+                "Slowest files to read:",
+                "      2.900s big.xlsx",
+                "      0.100s small.csv",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut profiler = Profiler::new();
+        profiler.add("reading", Duration::from_millis(1000));
+        let mut group = Profiler::new();
+        group.add("mapping", Duration::from_millis(250));
+        group.add("reading", Duration::from_millis(750));
+
+        profiler.merge(group);
+        assert_eq!(
+            profiler.summary(),
+            vec![
+                "Profile: 2.000s total",
+                "  reading        1.750s (87.5%)",
+                "  mapping        0.250s (12.5%)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use log::info;
+use std::time::{Duration, Instant};
+
+/// Accumulates per-file progress and periodically logs files/sec and rows/sec
+pub struct ThroughputLogger {
+    interval: Duration,
+    started: Instant,
+    last_report: Instant,
+    files: usize,
+    rows: usize,
+}
+
+impl ThroughputLogger {
+    pub fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            interval,
+            started: now,
+            last_report: now,
+            files: 0,
+            rows: 0,
+        }
+    }
+
+    /// Records a processed file and logs throughput when the interval has elapsed
+    pub fn record(&mut self, rows: usize) {
+        if let Some(line) = self.record_at(rows, Instant::now()) {
+            info!("{}", line);
+        }
+    }
+
+    fn record_at(&mut self, rows: usize, now: Instant) -> Option<String> {
+        self.files += 1;
+        self.rows += rows;
+
+        if now.duration_since(self.last_report) < self.interval {
+            return None;
+        }
+        self.last_report = now;
+        Some(self.summary_at(now))
+    }
+
+    /// Logs the overall throughput since the logger was created
+    pub fn finish(&self) {
+        info!("{}", self.summary_at(Instant::now()));
+    }
+
+    fn summary_at(&self, now: Instant) -> String {
+        let elapsed = now.duration_since(self.started).as_secs_f64().max(f64::EPSILON);
+        format!(
+            "Throughput: {} files, {} rows in {:.1}s ({:.1} files/sec, {:.0} rows/sec)",
+            self.files,
+            self.rows,
+            elapsed,
+            self.files as f64 / elapsed,
+            self.rows as f64 / elapsed
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_only_after_interval() {
+        let mut logger = ThroughputLogger::new(Duration::from_secs(10));
+        let start = logger.started;
+
+        assert!(logger.record_at(100, start + Duration::from_secs(1)).is_none());
+        assert!(logger.record_at(100, start + Duration::from_secs(5)).is_none());
+
+        let line = logger.record_at(200, start + Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            line,
+            "Throughput: 3 files, 400 rows in 10.0s (0.3 files/sec, 40 rows/sec)"
+        );
+
+        // The interval restarts after each report
+        assert!(logger.record_at(1, start + Duration::from_secs(15)).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+use clap::ValueEnum;
+
+/// Format of the `--combine-report` run summary
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    /// A self-contained HTML page
+    Html,
+}
+
+/// What one output was made of, for the run report
+pub struct GroupSummary {
+    /// File or sheet name the group was written under
+    pub output: String,
+    pub header: Vec<String>,
+    pub sources: Vec<String>,
+    pub rows: usize,
+    /// Number of non-empty cells per column
+    pub filled: Vec<usize>,
+}
+
+impl GroupSummary {
+    pub fn new(
+        output: String,
+        header: &[String],
+        sources: Vec<String>,
+        rows: &[Vec<String>],
+    ) -> Self {
+        let mut filled = vec![0; header.len()];
+        for row in rows {
+            for (count, value) in filled.iter_mut().zip(row) {
+                if !value.trim().is_empty() {
+                    *count += 1;
+                }
+            }
+        }
+
+        Self {
+            output,
+            header: header.to_vec(),
+            sources,
+            rows: rows.len(),
+            filled,
+        }
+    }
+}
+
+/// What the run did with a discovered file, for `--include-empty-files-in-manifest`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileStatus {
+    /// Part of an output
+    Included,
+    /// No header row
+    Empty,
+    /// Reading failed
+    Unreadable,
+    /// Not a supported format, or content that is not what its extension says
+    Unsupported,
+    /// Left out by a header check such as `--max-columns` or `--must-have`
+    Rejected,
+}
+
+impl FileStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileStatus::Included => "included",
+            FileStatus::Empty => "empty",
+            FileStatus::Unreadable => "unreadable",
+            FileStatus::Unsupported => "unsupported",
+            FileStatus::Rejected => "rejected",
+        }
+    }
+}
+
+/// A discovered file and what became of it
+pub struct FileEntry {
+    pub source: String,
+    pub status: FileStatus,
+    /// Why the file was left out, empty when included
+    pub detail: String,
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin:.5em 0 2em}\
+th,td{border:1px solid #ccc;padding:.25em .6em;text-align:left}\
+th{background:#f3f3f3}.bar{background:#4a90d9;height:.6em}";
+
+/// Renders the run summary as a standalone HTML page. A table of `files` and
+/// their status is added when there are any.
+pub fn render_html(groups: &[GroupSummary], files: &[FileEntry]) -> String {
+    let total_rows: usize = groups.iter().map(|group| group.rows).sum();
+    let total_sources: usize = groups.iter().map(|group| group.sources.len()).sum();
+
+    let mut overview = String::new();
+    for group in groups {
+        overview.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&group.output),
+            group.sources.len(),
+            group.header.len(),
+            group.rows
+        ));
+    }
+
+    let mut details = String::new();
+    for group in groups {
+        details.push_str(&format!("<h2>{}</h2>\n", escape(&group.output)));
+        details.push_str("<p>Sources:</p>\n<ul>\n");
+        for source in &group.sources {
+            details.push_str(&format!("<li>{}</li>\n", escape(source)));
+        }
+        details.push_str("</ul>\n<table>\n<tr><th>Column</th><th>Filled</th><th></th></tr>\n");
+        for (col, filled) in group.header.iter().zip(&group.filled) {
+            let percent = if group.rows == 0 {
+                0.0
+            } else {
+                *filled as f64 / group.rows as f64 * 100.0
+            };
+            details.push_str(&format!(
+                "<tr><td>{}</td><td>{} ({:.1}%)</td><td><div class=\"bar\" style=\"width:{:.0}px\"></div></td></tr>\n",
+                escape(col),
+                filled,
+                percent,
+                percent
+            ));
+        }
+        details.push_str("</table>\n");
+    }
+
+    if !files.is_empty() {
+        details.push_str("<h2>Files</h2>\n<table>\n<tr><th>File</th><th>Status</th><th>Detail</th></tr>\n");
+        for file in files {
+            details.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape(&file.source),
+                file.status.name(),
+                escape(&file.detail)
+            ));
+        }
+        details.push_str("</table>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>CSV Combine report</title>\n\
+         <style>{}</style>\n</head>\n<body>\n<h1>CSV Combine report</h1>\n\
+         <p>{} outputs from {} source files, {} data rows.</p>\n\
+         <table>\n<tr><th>Output</th><th>Sources</th><th>Columns</th><th>Rows</th></tr>\n{}</table>\n\
+         {}</body>\n</html>\n",
+        STYLE,
+        groups.len(),
+        total_sources,
+        total_rows,
+        overview,
+        details
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_summary_fill_counts() {
+        let header = vec!["Name".to_string(), "City".to_string()];
+        let rows = vec![
+            vec!["Alice".to_string(), "Paris".to_string()],
+            vec!["Bob".to_string(), " ".to_string()],
+        ];
+
+        let summary = GroupSummary::new("combined_1.csv".to_string(), &header, vec![], &rows);
+        assert_eq!(summary.rows, 2);
+        assert_eq!(summary.filled, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_render_html_escapes_values() {
+        let summary = GroupSummary::new(
+            "combined_1.csv".to_string(),
+            &["<b>Name</b>".to_string()],
+            vec!["a&b.csv".to_string()],
+            &[vec!["Alice".to_string()]],
+        );
+
+        let html = render_html(&[summary], &[]);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<p>1 outputs from 1 source files, 1 data rows.</p>"));
+        assert!(html.contains("<td>&lt;b&gt;Name&lt;/b&gt;</td><td>1 (100.0%)</td>"));
+        assert!(html.contains("<li>a&amp;b.csv</li>"));
+        assert!(!html.contains("<h2>Files</h2>"));
+    }
+
+    #[test]
+    fn test_render_html_lists_files() {
+        let files = [
+            FileEntry {
+                source: "a.csv".to_string(),
+                status: FileStatus::Included,
+                detail: String::new(),
+            },
+            FileEntry {
+                source: "b.csv".to_string(),
+                status: FileStatus::Empty,
+                detail: "no header row".to_string(),
+            },
+        ];
+
+        let html = render_html(&[], &files);
+        assert!(html.contains("<tr><td>a.csv</td><td>included</td><td></td></tr>"));
+        assert!(html.contains("<tr><td>b.csv</td><td>empty</td><td>no header row</td></tr>"));
+    }
+}
@@ -0,0 +1,218 @@
+//! Combining CSV files end to end: reading, grouping, merging and writing one
+//! output per group, as a `csv_combine` run without options does. Embedders get
+//! a [`RunResult`] describing the outcome instead of log lines.
+
+use crate::csv_io::{self, CsvReadOptions, CsvWriteOptions};
+use crate::headers::{
+    ColumnOrder, DEFAULT_OVERLAP_THRESHOLD, MergeStrategy, SimilarityMetric, generate_header_hash, group_files_by,
+    map_rows_to_header, merge_headers_by,
+};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How [`combine_files`] groups and reads its inputs
+#[derive(Debug, Clone)]
+pub struct CombineOptions {
+    /// Share of columns two headers must have in common to be grouped
+    pub overlap_threshold: f64,
+    /// Columns two headers must also have in common, 0 for no minimum
+    pub min_shared_columns: usize,
+    /// What each file is compared against when joining a group
+    pub merge_strategy: MergeStrategy,
+    /// How two headers are scored against `overlap_threshold`
+    pub similarity_metric: SimilarityMetric,
+    /// Order of the columns in each merged header
+    pub column_order: ColumnOrder,
+    /// How the input CSVs are parsed
+    pub read: CsvReadOptions,
+    /// How the outputs are written
+    pub write: CsvWriteOptions,
+}
+
+impl Default for CombineOptions {
+    fn default() -> Self {
+        Self {
+            overlap_threshold: DEFAULT_OVERLAP_THRESHOLD,
+            min_shared_columns: 0,
+            merge_strategy: MergeStrategy::default(),
+            similarity_metric: SimilarityMetric::default(),
+            column_order: ColumnOrder::default(),
+            read: CsvReadOptions::default(),
+            write: CsvWriteOptions::default(),
+        }
+    }
+}
+
+/// One group of compatible files and the output it was written to
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupResult {
+    /// Hash of the merged header, as used in the output name
+    pub hash: String,
+    /// The merged header
+    pub header: Vec<String>,
+    /// Member files in the order their rows were written
+    pub sources: Vec<PathBuf>,
+    /// Data rows written
+    pub rows: usize,
+    /// The CSV file the group was written to
+    pub output: PathBuf,
+}
+
+/// Why an input was left out of every group
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    /// No header row
+    Empty,
+    /// Reading failed, with the error
+    Unreadable(String),
+}
+
+/// An input left out of every group and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedFile {
+    /// The input as given to [`combine_files`]
+    pub path: PathBuf,
+    /// Why it was left out
+    pub reason: SkipReason,
+}
+
+/// What [`combine_files`] did
+#[derive(Debug, Clone)]
+pub struct RunResult {
+    /// Groups in output order
+    pub groups: Vec<GroupResult>,
+    /// Inputs in no group, in input order
+    pub skipped: Vec<SkippedFile>,
+    /// Time taken by the whole run
+    pub elapsed: Duration,
+}
+
+impl RunResult {
+    /// Data rows written across all groups
+    pub fn total_rows(&self) -> usize {
+        self.groups.iter().map(|group| group.rows).sum()
+    }
+}
+
+/// Reads the CSV `paths`, groups them by header and writes each group to
+/// `output_dir` as `combined_<hash>.csv`, or `single_<hash>.csv` for a group of
+/// one file. Unreadable and empty inputs are skipped and listed in the result;
+/// failing to write an output is an error.
+pub fn combine_files(
+    paths: &[PathBuf],
+    output_dir: &Path,
+    options: &CombineOptions,
+) -> Result<RunResult> {
+    let started = Instant::now();
+    let mut skipped = Vec::new();
+    let mut file_data: Vec<(&PathBuf, Vec<Vec<String>>)> = Vec::new();
+    for path in paths {
+        match csv_io::read_csv(path, &options.read) {
+            Ok(data) if data.first().is_some_and(|header| !header.is_empty()) => {
+                file_data.push((path, data))
+            }
+            Ok(_) => skipped.push(SkippedFile {
+                path: path.clone(),
+                reason: SkipReason::Empty,
+            }),
+            Err(e) => skipped.push(SkippedFile {
+                path: path.clone(),
+                reason: SkipReason::Unreadable(format!("{:#}", e)),
+            }),
+        }
+    }
+
+    let headers: Vec<Vec<String>> = file_data.iter().map(|(_, data)| data[0].clone()).collect();
+    let groups = group_files_by(
+        &headers,
+        options.overlap_threshold,
+        options.min_shared_columns,
+        options.merge_strategy,
+        options.similarity_metric,
+    );
+
+    let mut results = Vec::new();
+    for group in groups {
+        let group_headers: Vec<Vec<String>> =
+            group.iter().map(|&idx| headers[idx].clone()).collect();
+        let header = merge_headers_by(&group_headers, options.column_order);
+        let hash = generate_header_hash(&header);
+        let prefix = if group.len() == 1 {
+            "single"
+        } else {
+            "combined"
+        };
+        let output = output_dir.join(format!("{}_{}.csv", prefix, hash));
+
+        let mut rows = Vec::new();
+        for &idx in &group {
+            let data = &file_data[idx].1;
+            rows.extend(map_rows_to_header(&data[0], &header, &data[1..]));
+        }
+        csv_io::write_combined_csv(&output, &header, &rows, &options.write)?;
+        results.push(GroupResult {
+            hash,
+            header,
+            sources: group.iter().map(|&idx| file_data[idx].0.clone()).collect(),
+            rows: rows.len(),
+            output,
+        });
+    }
+
+    Ok(RunResult {
+        groups: results,
+        skipped,
+        elapsed: started.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_combine_files_result() {
+        let test_dir = TempDir::new().unwrap();
+        let dir = test_dir.path();
+        let paths: Vec<PathBuf> = ["a.csv", "b.csv", "c.csv", "empty.csv", "missing.csv"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+        fs::write(&paths[0], "Name,Age\nAlice,30\n").unwrap();
+        fs::write(&paths[1], "Name,Age,City\nBob,25,Rome\nCarol,41,Oslo\n").unwrap();
+        fs::write(&paths[2], "Sku,Price\nA1,9.99\n").unwrap();
+        fs::write(&paths[3], "").unwrap();
+
+        let result = combine_files(&paths, dir, &CombineOptions::default()).unwrap();
+        assert_eq!(result.groups.len(), 2);
+        assert_eq!(result.total_rows(), 4);
+
+        let combined = &result.groups[0];
+        assert_eq!(combined.header, vec!["Name", "Age", "City"]);
+        assert_eq!(combined.sources, vec![paths[0].clone(), paths[1].clone()]);
+        assert_eq!(combined.rows, 3);
+        assert_eq!(
+            combined.output,
+            dir.join(format!("combined_{}.csv", combined.hash))
+        );
+        assert_eq!(
+            fs::read_to_string(&combined.output).unwrap(),
+            "Name,Age,City\nAlice,30,\nBob,25,Rome\nCarol,41,Oslo\n"
+        );
+        assert!(
+            result.groups[1]
+                .output
+                .ends_with(format!("single_{}.csv", result.groups[1].hash))
+        );
+
+        assert_eq!(result.skipped.len(), 2);
+        assert_eq!(result.skipped[0].reason, SkipReason::Empty);
+        assert!(matches!(
+            &result.skipped[1],
+            SkippedFile { path, reason: SkipReason::Unreadable(_) } if *path == paths[4]
+        ));
+    }
+}
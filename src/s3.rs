@@ -0,0 +1,261 @@
+use anyhow::{Context, Result};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{ByteStream, Length};
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use log::*;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Files at least this large are sent as a multipart upload of parts this size.
+/// S3 requires every part but the last to be at least 5 MiB.
+const PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// An `--output s3://bucket/prefix/` target
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3Target {
+    pub bucket: String,
+    /// Key prefix, empty or ending in `/`
+    pub prefix: String,
+}
+
+impl S3Target {
+    /// Parses `s3://bucket/prefix`, returning `None` for paths that are not S3 URLs
+    pub fn parse(output: &Path) -> Option<Result<Self>> {
+        let rest = output.to_str()?.strip_prefix("s3://")?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Some(Err(anyhow::anyhow!(
+                "missing bucket name in {}",
+                output.display()
+            )));
+        }
+
+        let prefix = prefix.trim_matches('/');
+        Some(Ok(Self {
+            bucket: bucket.to_string(),
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("{}/", prefix)
+            },
+        }))
+    }
+}
+
+/// Temporary directory the combined outputs are written to before being
+/// uploaded, removed when dropped even if the run fails
+pub fn staging_dir() -> Result<TempDir> {
+    tempfile::Builder::new()
+        .prefix("csv_combine_s3_")
+        .tempdir()
+        .context("Failed to create a staging directory for the S3 upload")
+}
+
+/// Object keys for `files` under `dir`: the target prefix followed by each
+/// file's path relative to `dir`, with `/` separators
+fn object_keys(dir: &Path, files: &[PathBuf], target: &S3Target) -> Result<Vec<String>> {
+    files
+        .iter()
+        .map(|file| {
+            let relative = file
+                .strip_prefix(dir)
+                .with_context(|| format!("{} is not in {}", file.display(), dir.display()))?;
+            Ok(format!(
+                "{}{}",
+                target.prefix,
+                relative.to_string_lossy().replace('\\', "/")
+            ))
+        })
+        .collect()
+}
+
+/// Uploads `files`, written under the staging directory `dir`, to `target`.
+/// Credentials come from the standard AWS chain (environment, profile,
+/// instance metadata).
+pub async fn upload_files(dir: &Path, files: &[PathBuf], target: &S3Target) -> Result<()> {
+    let keys = object_keys(dir, files, target)?;
+    let config = aws_config::load_from_env().await;
+    let client = Client::new(&config);
+
+    for (file, key) in files.iter().zip(&keys) {
+        upload_file(&client, file, &target.bucket, key)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to upload {} to s3://{}/{}",
+                    file.display(),
+                    target.bucket,
+                    key
+                )
+            })?;
+        info!("Uploaded s3://{}/{}", target.bucket, key);
+    }
+
+    info!(
+        "Uploaded {} files to s3://{}/{}",
+        files.len(),
+        target.bucket,
+        target.prefix
+    );
+    Ok(())
+}
+
+/// `(offset, length)` of each multipart part of a file of `size` bytes
+fn part_ranges(size: u64) -> Vec<(u64, u64)> {
+    (0..size)
+        .step_by(PART_SIZE as usize)
+        .map(|offset| (offset, PART_SIZE.min(size - offset)))
+        .collect()
+}
+
+async fn upload_file(client: &Client, path: &Path, bucket: &str, key: &str) -> Result<()> {
+    let size = std::fs::metadata(path)?.len();
+    if size < PART_SIZE {
+        client
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .body(ByteStream::from_path(path).await?)
+            .send()
+            .await?;
+        return Ok(());
+    }
+
+    let upload = client
+        .create_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await?;
+    let upload_id = upload
+        .upload_id()
+        .context("S3 returned no multipart upload id")?;
+    match upload_parts(client, path, size, bucket, key, upload_id).await {
+        Ok(parts) => {
+            client
+                .complete_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts))
+                        .build(),
+                )
+                .send()
+                .await?;
+            Ok(())
+        }
+        Err(e) => {
+            // Uploaded parts are billed until the upload is aborted
+            if let Err(abort) = client
+                .abort_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .send()
+                .await
+            {
+                warn!("Failed to abort the multipart upload of {}: {}", key, abort);
+            }
+            Err(e)
+        }
+    }
+}
+
+async fn upload_parts(
+    client: &Client,
+    path: &Path,
+    size: u64,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+) -> Result<Vec<CompletedPart>> {
+    let mut parts = Vec::new();
+    for (offset, length) in part_ranges(size) {
+        let part_number = parts.len() as i32 + 1;
+        let body = ByteStream::read_from()
+            .path(path)
+            .offset(offset)
+            .length(Length::Exact(length))
+            .build()
+            .await?;
+        let part = client
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(body)
+            .send()
+            .await?;
+        debug!(
+            "Uploaded part {} of {} ({} bytes)",
+            part_number, key, length
+        );
+        parts.push(
+            CompletedPart::builder()
+                .set_e_tag(part.e_tag().map(String::from))
+                .part_number(part_number)
+                .build(),
+        );
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_s3_target() {
+        assert_eq!(
+            S3Target::parse(Path::new("s3://reports/combined/2024/"))
+                .unwrap()
+                .unwrap(),
+            S3Target {
+                bucket: "reports".to_string(),
+                prefix: "combined/2024/".to_string(),
+            }
+        );
+        assert_eq!(
+            S3Target::parse(Path::new("s3://reports"))
+                .unwrap()
+                .unwrap()
+                .prefix,
+            ""
+        );
+        assert!(S3Target::parse(Path::new("s3:///prefix")).unwrap().is_err());
+        assert!(S3Target::parse(Path::new("output/combined")).is_none());
+    }
+
+    #[test]
+    fn test_object_keys() {
+        let staging = staging_dir().unwrap();
+        let dir = staging.path();
+        let target = S3Target::parse(Path::new("s3://reports/daily"))
+            .unwrap()
+            .unwrap();
+        let files = vec![dir.join("sales_combined.csv"), dir.join("parts/a_001.csv")];
+        assert_eq!(
+            object_keys(dir, &files, &target).unwrap(),
+            vec!["daily/sales_combined.csv", "daily/parts/a_001.csv"]
+        );
+        // Only staged files are uploaded
+        assert!(object_keys(dir, &[PathBuf::from("cache.json")], &target).is_err());
+
+        let path = dir.to_path_buf();
+        drop(staging);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_part_ranges() {
+        assert!(part_ranges(0).is_empty());
+        assert_eq!(part_ranges(PART_SIZE), vec![(0, PART_SIZE)]);
+        assert_eq!(
+            part_ranges(2 * PART_SIZE + 5),
+            vec![(0, PART_SIZE), (PART_SIZE, PART_SIZE), (2 * PART_SIZE, 5)]
+        );
+    }
+}
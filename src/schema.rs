@@ -0,0 +1,348 @@
+use crate::datetime::{SourceZone, parse_to_utc};
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde_json::{Map, Value, json};
+
+/// Format of the schema written by `--emit-schema`
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SchemaFormat {
+    /// A `CREATE TABLE` statement
+    Sql,
+    /// A JSON Schema describing one row
+    Json,
+}
+
+impl SchemaFormat {
+    /// Extension replacing `.csv` in the data file name
+    pub fn extension(self) -> &'static str {
+        match self {
+            SchemaFormat::Sql => "sql",
+            SchemaFormat::Json => "schema.json",
+        }
+    }
+}
+
+/// Column type inferred from the values of an output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    Timestamp,
+    Text,
+}
+
+impl ColumnType {
+    /// Name of the type in a `--data-dictionary`
+    pub fn name(self) -> &'static str {
+        match self {
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Date => "date",
+            ColumnType::Timestamp => "timestamp",
+            ColumnType::Text => "text",
+        }
+    }
+}
+
+/// Narrowest types first, so the first candidate left standing wins
+const CANDIDATES: [ColumnType; 5] = [
+    ColumnType::Integer,
+    ColumnType::Float,
+    ColumnType::Boolean,
+    ColumnType::Date,
+    ColumnType::Timestamp,
+];
+
+fn matches_type(value: &str, column_type: ColumnType) -> bool {
+    match column_type {
+        ColumnType::Integer => value.parse::<i64>().is_ok(),
+        // Rust also parses "inf" and "NaN", which are not numbers in a CSV
+        ColumnType::Float => {
+            value.parse::<f64>().is_ok() && value.chars().any(|c| c.is_ascii_digit())
+        }
+        ColumnType::Boolean => {
+            value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false")
+        }
+        ColumnType::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+        ColumnType::Timestamp => parse_to_utc(value, SourceZone::Utc, None).is_some(),
+        ColumnType::Text => true,
+    }
+}
+
+/// Infers each column's type from its non-empty values. Columns with no values are text.
+pub fn infer_types(header: &[String], rows: &[Vec<String>]) -> Vec<ColumnType> {
+    (0..header.len())
+        .map(|idx| {
+            let mut candidates = CANDIDATES.to_vec();
+            let mut seen = false;
+            for value in rows.iter().filter_map(|row| row.get(idx)) {
+                let value = value.trim();
+                if value.is_empty() {
+                    continue;
+                }
+                seen = true;
+                candidates.retain(|&candidate| matches_type(value, candidate));
+                if candidates.is_empty() {
+                    break;
+                }
+            }
+
+            match candidates.first() {
+                Some(&column_type) if seen => column_type,
+                _ => ColumnType::Text,
+            }
+        })
+        .collect()
+}
+
+/// Columns of the `--data-dictionary` CSV
+pub const DICTIONARY_HEADER: [&str; 8] = [
+    "output",
+    "column",
+    "type",
+    "source_files",
+    "fill_rate",
+    "min_length",
+    "max_length",
+    "sample_values",
+];
+
+/// Distinct values listed per column in a `--data-dictionary`
+const DICTIONARY_SAMPLES: usize = 3;
+
+/// Describes the first `header.len()` columns of an output, one
+/// [`DICTIONARY_HEADER`] row each: its inferred type, how many of the
+/// `source_counts` files have it, the share of rows with a value, the shortest
+/// and longest value in characters, and its first distinct values
+pub fn data_dictionary(
+    output: &str,
+    header: &[String],
+    source_counts: &[usize],
+    rows: &[Vec<String>],
+) -> Vec<Vec<String>> {
+    let types = infer_types(header, rows);
+    header
+        .iter()
+        .enumerate()
+        .map(|(idx, col)| {
+            let values: Vec<&str> = rows
+                .iter()
+                .filter_map(|row| row.get(idx))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+                .collect();
+            let lengths = values.iter().map(|value| value.chars().count());
+            let mut samples: Vec<&str> = Vec::new();
+            for value in &values {
+                if samples.len() == DICTIONARY_SAMPLES {
+                    break;
+                }
+                if !samples.contains(value) {
+                    samples.push(value);
+                }
+            }
+            let fill_rate = if rows.is_empty() {
+                0.0
+            } else {
+                values.len() as f64 / rows.len() as f64
+            };
+
+            vec![
+                output.to_string(),
+                col.clone(),
+                types[idx].name().to_string(),
+                source_counts
+                    .get(idx)
+                    .copied()
+                    .unwrap_or_default()
+                    .to_string(),
+                format!("{:.2}", fill_rate),
+                lengths
+                    .clone()
+                    .min()
+                    .map(|len| len.to_string())
+                    .unwrap_or_default(),
+                lengths.max().map(|len| len.to_string()).unwrap_or_default(),
+                samples.join(" | "),
+            ]
+        })
+        .collect()
+}
+
+/// Renders the schema of an output named `table` in the requested format
+pub fn render_schema(
+    format: SchemaFormat,
+    table: &str,
+    header: &[String],
+    types: &[ColumnType],
+) -> String {
+    match format {
+        SchemaFormat::Sql => create_table_sql(table, header, types),
+        SchemaFormat::Json => json_schema(table, header, types),
+    }
+}
+
+fn create_table_sql(table: &str, header: &[String], types: &[ColumnType]) -> String {
+    let columns: Vec<String> = header
+        .iter()
+        .zip(types)
+        .map(|(col, column_type)| {
+            let sql_type = match column_type {
+                ColumnType::Integer => "BIGINT",
+                ColumnType::Float => "DOUBLE PRECISION",
+                ColumnType::Boolean => "BOOLEAN",
+                ColumnType::Date => "DATE",
+                ColumnType::Timestamp => "TIMESTAMP",
+                ColumnType::Text => "TEXT",
+            };
+            format!("    {} {}", quote_identifier(col), sql_type)
+        })
+        .collect();
+
+    format!(
+        "CREATE TABLE {} (\n{}\n);\n",
+        quote_identifier(table),
+        columns.join(",\n")
+    )
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn json_schema(table: &str, header: &[String], types: &[ColumnType]) -> String {
+    let mut properties = Map::new();
+    for (col, column_type) in header.iter().zip(types) {
+        let property = match column_type {
+            ColumnType::Integer => json!({ "type": ["integer", "null"] }),
+            ColumnType::Float => json!({ "type": ["number", "null"] }),
+            ColumnType::Boolean => json!({ "type": ["boolean", "null"] }),
+            ColumnType::Date => json!({ "type": ["string", "null"], "format": "date" }),
+            ColumnType::Timestamp => json!({ "type": ["string", "null"], "format": "date-time" }),
+            ColumnType::Text => json!({ "type": ["string", "null"] }),
+        };
+        properties.insert(col.clone(), property);
+    }
+
+    let schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": table,
+        "type": "object",
+        "properties": Value::Object(properties),
+    });
+    let mut rendered = serde_json::to_string_pretty(&schema).unwrap_or_default();
+    rendered.push('\n');
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_infer_types() {
+        let header = to_strings(&["id", "price", "active", "day", "at", "name", "blank"]);
+        let rows = vec![
+            to_strings(&[
+                "1",
+                "9.5",
+                "true",
+                "2024-03-01",
+                "2024-03-01 09:30:00",
+                "Alice",
+                "",
+            ]),
+            to_strings(&[
+                "2",
+                "10",
+                "FALSE",
+                "2024-03-02",
+                "2024-03-01T06:30:00-08:00",
+                "42",
+                "",
+            ]),
+            to_strings(&["", "", "", "", "", "", ""]),
+        ];
+
+        assert_eq!(
+            infer_types(&header, &rows),
+            vec![
+                ColumnType::Integer,
+                ColumnType::Float,
+                ColumnType::Boolean,
+                ColumnType::Date,
+                ColumnType::Timestamp,
+                ColumnType::Text,
+                ColumnType::Text,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_data_dictionary() {
+        let header = to_strings(&["id", "city", "note"]);
+        let rows = vec![
+            to_strings(&["1", "Paris", ""]),
+            to_strings(&["2", "Rome", ""]),
+            to_strings(&["3", "Paris", ""]),
+            to_strings(&["4", "", "", "a.csv"]),
+        ];
+
+        assert_eq!(
+            data_dictionary("combined_1.csv", &header, &[2, 2, 1], &rows),
+            vec![
+                to_strings(&[
+                    "combined_1.csv",
+                    "id",
+                    "integer",
+                    "2",
+                    "1.00",
+                    "1",
+                    "1",
+                    "1 | 2 | 3"
+                ]),
+                to_strings(&[
+                    "combined_1.csv",
+                    "city",
+                    "text",
+                    "2",
+                    "0.75",
+                    "4",
+                    "5",
+                    "Paris | Rome"
+                ]),
+                to_strings(&["combined_1.csv", "note", "text", "1", "0.00", "", "", ""]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_schema() {
+        let header = to_strings(&["id", "say \"hi\""]);
+        let types = [ColumnType::Integer, ColumnType::Text];
+
+        assert_eq!(
+            render_schema(SchemaFormat::Sql, "combined_1", &header, &types),
+            "CREATE TABLE \"combined_1\" (\n    \"id\" BIGINT,\n    \"say \"\"hi\"\"\" TEXT\n);\n"
+        );
+
+        let json: Value = serde_json::from_str(&render_schema(
+            SchemaFormat::Json,
+            "combined_1",
+            &header,
+            &types,
+        ))
+        .unwrap();
+        assert_eq!(json["title"], "combined_1");
+        assert_eq!(json["properties"]["id"]["type"], json!(["integer", "null"]));
+        let keys: Vec<&String> = json["properties"].as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["id", "say \"hi\""]);
+    }
+}
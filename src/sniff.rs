@@ -0,0 +1,114 @@
+/// Bytes inspected by `--validate-content`
+pub const SAMPLE_LEN: usize = 8192;
+
+/// Checks the start of a `.csv` file for content that is clearly not CSV,
+/// returning a description such as "HTML" when it is not plausible
+pub fn non_csv_content(sample: &[u8], delimiter: u8) -> Option<&'static str> {
+    const SIGNATURES: [(&[u8], &str); 5] = [
+        (b"PK\x03\x04", "a zip archive (possibly a renamed .xlsx)"),
+        (b"%PDF", "a PDF document"),
+        (b"\x1f\x8b", "gzip-compressed data"),
+        (
+            b"\xd0\xcf\x11\xe0",
+            "a legacy Office document (possibly a renamed .xls)",
+        ),
+        (b"\xff\xfe", "UTF-16 text"),
+    ];
+    for (signature, kind) in SIGNATURES {
+        if sample.starts_with(signature) {
+            return Some(kind);
+        }
+    }
+
+    let text = String::from_utf8_lossy(sample);
+    let start = text
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .to_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        return Some("HTML");
+    }
+    if start.starts_with("<?xml") {
+        return Some("XML");
+    }
+
+    let control = sample
+        .iter()
+        .filter(|&&b| b == 0 || (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r')))
+        .count();
+    if control * 20 > sample.len() {
+        return Some("binary data");
+    }
+
+    if !consistent_widths(sample, delimiter) {
+        return Some("text without a consistent column layout");
+    }
+    None
+}
+
+/// True when at least half of the sampled records have the header's field count.
+/// The last record is ignored as the sample may cut it short.
+fn consistent_widths(sample: &[u8], delimiter: u8) -> bool {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(sample);
+    let mut widths: Vec<usize> = reader
+        .byte_records()
+        .map_while(|record| record.ok())
+        .map(|record| record.len())
+        .collect();
+    if sample.len() >= SAMPLE_LEN {
+        widths.pop();
+    }
+
+    let Some((&header_width, rest)) = widths.split_first() else {
+        return true;
+    };
+    if rest.is_empty() {
+        return true;
+    }
+    rest.iter().filter(|&&width| width == header_width).count() * 2 >= rest.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts_csv() {
+        assert_eq!(
+            non_csv_content(b"\xef\xbb\xbfName,Age\nAlice,30\nBob,25\n", b','),
+            None
+        );
+        assert_eq!(non_csv_content(b"", b','), None);
+        assert_eq!(non_csv_content(b"a;b\n1;2\n", b';'), None);
+    }
+
+    #[test]
+    fn test_rejects_mislabeled_content() {
+        assert_eq!(
+            non_csv_content(
+                b"  <!DOCTYPE html><html><body>Not found</body></html>",
+                b','
+            ),
+            Some("HTML")
+        );
+        assert_eq!(
+            non_csv_content(b"PK\x03\x04\x14\x00\x06\x00", b','),
+            Some("a zip archive (possibly a renamed .xlsx)")
+        );
+        assert_eq!(
+            non_csv_content(b"\x00\x01\x02\x03abc\x00\x00\x05", b','),
+            Some("binary data")
+        );
+        assert_eq!(
+            non_csv_content(
+                b"Dear team,\nthe export failed, again, today\nThanks\nBob\n",
+                b','
+            ),
+            Some("text without a consistent column layout")
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{Context, Result};
+use log::*;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// A CSV export split byte-wise into numbered parts: `data.csv.001`, `data.csv.002`, ...
+#[derive(Debug, PartialEq)]
+pub struct SplitSet {
+    /// The file the parts stand for, next to them, which inputs are named after
+    pub logical: PathBuf,
+    /// The parts in order
+    pub parts: Vec<PathBuf>,
+}
+
+/// Splits `data.csv.002` into `data.csv` and 2. The suffix must be all digits,
+/// at least 3 of them, after a `.csv` name.
+fn split_part(path: &Path) -> Option<(PathBuf, u32)> {
+    let suffix = path.extension()?.to_str()?;
+    if suffix.len() < 3 || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let logical = path.with_extension("");
+    if !logical
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+    {
+        return None;
+    }
+    Some((logical, suffix.parse().ok()?))
+}
+
+/// Finds the split sets among the files under `search_path`. Sets with a gap in
+/// their numbering, or not starting at 1, are warned about and left out.
+pub fn find_split_sets(search_path: &Path) -> Result<Vec<SplitSet>> {
+    let mut parts: BTreeMap<PathBuf, BTreeMap<u32, PathBuf>> = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(search_path) {
+        let entry = entry?;
+        if let Some((logical, index)) =
+            split_part(entry.path()).filter(|_| entry.file_type().is_file())
+        {
+            parts
+                .entry(logical)
+                .or_default()
+                .insert(index, entry.into_path());
+        }
+    }
+
+    let mut sets = Vec::new();
+    for (logical, parts) in parts {
+        if !parts.keys().copied().eq(1..=parts.len() as u32) {
+            let numbers: Vec<String> = parts.keys().map(u32::to_string).collect();
+            warn!(
+                "Not reassembling {}: its parts are numbered {}, not 1 to {}",
+                logical.display(),
+                numbers.join(", "),
+                parts.len()
+            );
+            continue;
+        }
+        sets.push(SplitSet {
+            logical,
+            parts: parts.into_values().collect(),
+        });
+    }
+    Ok(sets)
+}
+
+/// Sets reassembled by [`reassemble`] into a temporary directory, which is
+/// removed when this is dropped. The input directory is never written to.
+#[derive(Debug)]
+pub struct Reassembled {
+    dir: TempDir,
+    /// The logical file of each set and the copy its parts were concatenated into
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Reassembled {
+    /// The logical files of the reassembled sets, to be read as inputs
+    pub fn logical_files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(logical, _)| logical.as_path())
+    }
+
+    /// Where to read `path` from: the reassembled copy for a logical file,
+    /// otherwise `path` itself
+    pub fn read_path<'a>(&'a self, path: &'a Path) -> &'a Path {
+        self.files
+            .iter()
+            .find(|(logical, _)| logical == path)
+            .map_or(path, |(_, copy)| copy.as_path())
+    }
+}
+
+/// Concatenates each set's parts into a temporary copy, so the header of part 1
+/// applies to every part. A set whose logical file already exists is skipped,
+/// as that file is read anyway.
+pub fn reassemble(sets: &[SplitSet]) -> Result<Reassembled> {
+    let mut reassembled = Reassembled {
+        dir: tempfile::Builder::new()
+            .prefix("csv_combine_split_")
+            .tempdir()
+            .context("Failed to create a directory for reassembled files")?,
+        files: Vec::new(),
+    };
+    for (idx, set) in sets.iter().enumerate() {
+        if set.logical.exists() {
+            warn!(
+                "Not reassembling {} parts into {}: the file already exists",
+                set.parts.len(),
+                set.logical.display()
+            );
+            continue;
+        }
+
+        let context = || format!("Failed to reassemble {}", set.logical.display());
+        // One directory per set, as sets in different folders can share a name
+        let copy_dir = reassembled.dir.path().join(idx.to_string());
+        std::fs::create_dir(&copy_dir).with_context(context)?;
+        let copy = copy_dir.join(set.logical.file_name().unwrap_or_default());
+        let mut output = File::create(&copy).with_context(context)?;
+        for part in &set.parts {
+            std::io::copy(&mut File::open(part).with_context(context)?, &mut output)
+                .with_context(context)?;
+        }
+        reassembled.files.push((set.logical.clone(), copy));
+        info!(
+            "Reassembled {} parts into {}",
+            set.parts.len(),
+            set.logical.display()
+        );
+    }
+    Ok(reassembled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reassemble_split_sets() {
+        let test_dir = TempDir::new().unwrap();
+        let dir = test_dir.path();
+        fs::write(dir.join("data.csv.001"), "Name,Ci").unwrap();
+        fs::write(dir.join("data.csv.002"), "ty\nAlice,Paris\nBo").unwrap();
+        fs::write(dir.join("data.csv.003"), "b,Rome\n").unwrap();
+        // Part 2 is missing
+        fs::write(dir.join("gap.csv.001"), "a\n").unwrap();
+        fs::write(dir.join("gap.csv.003"), "b\n").unwrap();
+        fs::write(dir.join("photo.jpg.001"), "").unwrap();
+
+        let sets = find_split_sets(dir).unwrap();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].logical, dir.join("data.csv"));
+
+        let reassembled = reassemble(&sets).unwrap();
+        let logical = dir.join("data.csv");
+        assert_eq!(
+            reassembled.logical_files().collect::<Vec<_>>(),
+            vec![logical.as_path()]
+        );
+        let copy = reassembled.read_path(&logical).to_path_buf();
+        assert_eq!(
+            fs::read_to_string(&copy).unwrap(),
+            "Name,City\nAlice,Paris\nBob,Rome\n"
+        );
+        // The input directory is left untouched
+        assert!(!logical.exists());
+        assert!(!copy.starts_with(dir));
+        let other = dir.join("gap.csv.001");
+        assert_eq!(reassembled.read_path(&other), other);
+
+        drop(reassembled);
+        assert!(!copy.exists());
+    }
+}
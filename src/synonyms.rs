@@ -0,0 +1,140 @@
+//! Suggesting header aliases from similarly named columns.
+
+use std::collections::{HashMap, HashSet};
+
+/// Name similarity at which two columns are suggested as synonyms by default
+pub const DEFAULT_SYNONYM_SIMILARITY: f64 = 0.8;
+
+/// A suggested rename of `source` to `canonical`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AliasSuggestion {
+    pub source: String,
+    pub canonical: String,
+    /// Name similarity of the two columns, 0..1
+    pub similarity: f64,
+}
+
+/// Lowercases a column name and drops everything but letters and digits, so
+/// `Customer Name`, `customer_name` and `CustomerName` compare equal
+fn normalize_name(name: &str) -> Vec<char> {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Returns how alike two column names are, from 0 (nothing alike) to 1 (equal
+/// after ignoring case, spaces and punctuation), as one minus the edit distance
+/// over the longer name's length
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize_name(a), normalize_name(b));
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+
+    // Levenshtein distance, one row at a time
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+/// Clusters the column names of all headers by [`name_similarity`]. Names are
+/// visited from the most to the least widely used; each becomes an alias of the
+/// most similar earlier canonical name at or above `min_similarity`, or a
+/// canonical name itself. Two columns of the same header are never synonyms.
+pub fn suggest_aliases(headers: &[Vec<String>], min_similarity: f64) -> Vec<AliasSuggestion> {
+    let mut usage: HashMap<&str, usize> = HashMap::new();
+    let mut first_seen: Vec<&str> = Vec::new();
+    for header in headers {
+        let mut in_header = HashSet::new();
+        for col in header.iter().filter(|col| in_header.insert(*col)) {
+            let count = usage.entry(col.as_str()).or_insert(0);
+            if *count == 0 {
+                first_seen.push(col.as_str());
+            }
+            *count += 1;
+        }
+    }
+    // Stable, so equally used names keep their first-seen order
+    first_seen.sort_by_key(|name| std::cmp::Reverse(usage[name]));
+
+    let appears_with = |a: &str, b: &str| {
+        headers
+            .iter()
+            .any(|header| header.iter().any(|col| col == a) && header.iter().any(|col| col == b))
+    };
+
+    let mut canonical: Vec<&str> = Vec::new();
+    let mut suggestions = Vec::new();
+    for name in first_seen {
+        let best = canonical
+            .iter()
+            .filter(|other| !appears_with(name, other))
+            .map(|other| (*other, name_similarity(name, other)))
+            .filter(|(_, similarity)| *similarity >= min_similarity)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match best {
+            Some((other, similarity)) => suggestions.push(AliasSuggestion {
+                source: name.to_string(),
+                canonical: other.to_string(),
+                similarity,
+            }),
+            None => canonical.push(name),
+        }
+    }
+
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(cols: &[&str]) -> Vec<String> {
+        cols.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_name_similarity() {
+        assert_eq!(name_similarity("Customer Name", "customer_name"), 1.0);
+        assert_eq!(name_similarity("qty", "qtys"), 0.75);
+        assert!(name_similarity("price", "region") < 0.5);
+        assert_eq!(name_similarity("", "--"), 0.0);
+    }
+
+    #[test]
+    fn test_suggest_aliases() {
+        let headers = vec![
+            header(&["customer_name", "quantity", "price"]),
+            header(&["customer_name", "quantity", "price"]),
+            header(&["Customer Name", "Quantity", "price"]),
+            // Both are columns of one file, so neither is the other's synonym
+            header(&["order_id", "order_ids"]),
+        ];
+
+        assert_eq!(
+            suggest_aliases(&headers, DEFAULT_SYNONYM_SIMILARITY),
+            vec![
+                AliasSuggestion {
+                    source: "Customer Name".to_string(),
+                    canonical: "customer_name".to_string(),
+                    similarity: 1.0,
+                },
+                AliasSuggestion {
+                    source: "Quantity".to_string(),
+                    canonical: "quantity".to_string(),
+                    similarity: 1.0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Where `--xlsx-template` output goes: a sheet, the first when unnamed, and the
+/// top-left cell of the header
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateAnchor {
+    pub sheet: Option<String>,
+    /// 1-based, as Excel counts
+    pub col: u32,
+    pub row: u32,
+}
+
+impl Default for TemplateAnchor {
+    fn default() -> Self {
+        Self {
+            sheet: None,
+            col: 1,
+            row: 1,
+        }
+    }
+}
+
+impl TemplateAnchor {
+    /// Parses `[SHEET!]CELL`, e.g. `Data!B3` or `A1`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (sheet, cell) = match spec.rsplit_once('!') {
+            Some((sheet, cell)) => (
+                Some(sheet.trim().trim_matches('\'').to_string()),
+                cell.trim(),
+            ),
+            None => (None, spec.trim()),
+        };
+        if sheet.as_deref() == Some("") {
+            return Err(format!("missing sheet name in '{}'", spec));
+        }
+
+        let split = cell
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(cell.len());
+        let (letters, digits) = cell.split_at(split);
+        let invalid = || format!("expected a cell such as B3, got '{}'", cell);
+        if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(invalid());
+        }
+        let col = letters
+            .to_ascii_uppercase()
+            .bytes()
+            .try_fold(0u32, |col, b| {
+                col.checked_mul(26)?.checked_add(u32::from(b - b'A') + 1)
+            })
+            .ok_or_else(invalid)?;
+        let row = digits
+            .parse::<u32>()
+            .ok()
+            .filter(|&row| row > 0)
+            .ok_or_else(invalid)?;
+
+        Ok(Self { sheet, col, row })
+    }
+}
+
+/// Copies `template` to `output` with the header and rows written from `anchor`.
+/// Other sheets, styles and charts of the template are kept. Values that are
+/// plain numbers are written as numbers, so formulas and charts over them work.
+pub fn write_into_template(
+    template: &Path,
+    output: &Path,
+    anchor: &TemplateAnchor,
+    header: &[String],
+    rows: &[Vec<String>],
+) -> Result<()> {
+    let mut book = umya_spreadsheet::reader::xlsx::read(template)
+        .with_context(|| format!("Failed to read --xlsx-template {}", template.display()))?;
+    let sheet = match &anchor.sheet {
+        Some(name) => book.sheet_by_name_mut(name).map_err(|_| {
+            anyhow::anyhow!(
+                "--xlsx-template {} has no sheet '{}'",
+                template.display(),
+                name
+            )
+        })?,
+        None => book.sheet_mut(0)?,
+    };
+
+    for (col_idx, col) in header.iter().enumerate() {
+        sheet
+            .cell_mut((anchor.col + col_idx as u32, anchor.row))
+            .set_value_string(col);
+    }
+    for (row_idx, row) in rows.iter().enumerate() {
+        for (col_idx, value) in row
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| !value.is_empty())
+        {
+            let cell =
+                sheet.cell_mut((anchor.col + col_idx as u32, anchor.row + row_idx as u32 + 1));
+            match value.parse::<f64>() {
+                // Only values that read back the same, so 007 stays text
+                Ok(number) if number.to_string() == *value => cell.set_value_number(number),
+                _ => cell.set_value_string(value),
+            };
+        }
+    }
+
+    umya_spreadsheet::writer::xlsx::write(&book, output)
+        .with_context(|| format!("Failed to write {}", output.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{Reader, open_workbook_auto};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_anchor() {
+        assert_eq!(
+            TemplateAnchor::parse("A1").unwrap(),
+            TemplateAnchor::default()
+        );
+        assert_eq!(
+            TemplateAnchor::parse("'Raw Data'!ab12").unwrap(),
+            TemplateAnchor {
+                sheet: Some("Raw Data".to_string()),
+                col: 28,
+                row: 12,
+            }
+        );
+        assert!(TemplateAnchor::parse("Data!A0").is_err());
+        assert!(TemplateAnchor::parse("12").is_err());
+        assert!(TemplateAnchor::parse("!B2").is_err());
+    }
+
+    #[test]
+    fn test_write_into_template_keeps_other_sheets() {
+        let test_dir = TempDir::new().unwrap();
+        let template = test_dir.path().join("report.xlsx");
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        workbook
+            .add_worksheet()
+            .set_name("Summary")
+            .unwrap()
+            .write_string(0, 0, "Totals")
+            .unwrap();
+        workbook.add_worksheet().set_name("Data").unwrap();
+        workbook.save(&template).unwrap();
+
+        let output = test_dir.path().join("report_combined.xlsx");
+        let header = vec!["Name".to_string(), "Qty".to_string()];
+        let rows = vec![
+            vec!["Alice".to_string(), "3".to_string()],
+            vec!["007".to_string(), String::new()],
+        ];
+        write_into_template(
+            &template,
+            &output,
+            &TemplateAnchor::parse("Data!B2").unwrap(),
+            &header,
+            &rows,
+        )
+        .unwrap();
+
+        let mut book = open_workbook_auto(&output).unwrap();
+        assert_eq!(book.sheet_names(), vec!["Summary", "Data"]);
+        let summary = book.worksheet_range("Summary").unwrap();
+        assert_eq!(summary.get_value((0, 0)).unwrap().to_string(), "Totals");
+
+        let data = book.worksheet_range("Data").unwrap();
+        let cell = |row: u32, col: u32| data.get_value((row, col)).map(|value| value.to_string());
+        assert_eq!(cell(1, 1).as_deref(), Some("Name"));
+        assert_eq!(data.get_value((2, 2)), Some(&calamine::Data::Float(3.0)));
+        assert_eq!(cell(3, 1).as_deref(), Some("007"));
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use rust_xlsxwriter::Workbook;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Excel's limits for sheet names and rows per sheet
+const MAX_SHEET_NAME_LEN: usize = 31;
+const MAX_ROWS: usize = 1_048_576;
+
+/// Collects one worksheet per group for `--format xlsx-workbook`
+pub struct WorkbookWriter {
+    workbook: Workbook,
+    sheet_names: HashSet<String>,
+}
+
+impl WorkbookWriter {
+    pub fn new() -> Self {
+        Self {
+            workbook: Workbook::new(),
+            sheet_names: HashSet::new(),
+        }
+    }
+
+    /// Adds a worksheet holding the header and rows, returning the sheet name used
+    pub fn add_sheet(&mut self, name: &str, header: &[String], rows: &[Vec<String>]) -> Result<String> {
+        if rows.len() + 1 > MAX_ROWS {
+            return Err(anyhow::anyhow!(
+                "Sheet '{}' would have {} rows, more than Excel's limit of {}",
+                name,
+                rows.len() + 1,
+                MAX_ROWS
+            ));
+        }
+
+        let sheet_name = self.unique_sheet_name(name);
+        let worksheet = self.workbook.add_worksheet();
+        worksheet.set_name(&sheet_name)?;
+
+        for (col_idx, col) in header.iter().enumerate() {
+            worksheet.write_string(0, col_idx as u16, col)?;
+        }
+        for (row_idx, row) in rows.iter().enumerate() {
+            for (col_idx, value) in row.iter().enumerate() {
+                if !value.is_empty() {
+                    worksheet.write_string(row_idx as u32 + 1, col_idx as u16, value)?;
+                }
+            }
+        }
+
+        Ok(sheet_name)
+    }
+
+    pub fn save(mut self, path: impl AsRef<Path>) -> Result<()> {
+        if self.sheet_names.is_empty() {
+            // Excel requires at least one sheet
+            self.workbook.add_worksheet();
+        }
+        self.workbook.save(path.as_ref())?;
+        Ok(())
+    }
+
+    /// Strips characters Excel rejects, truncates to 31 characters and
+    /// suffixes a counter when the name is already taken
+    fn unique_sheet_name(&mut self, name: &str) -> String {
+        let cleaned: String = name
+            .chars()
+            .map(|c| match c {
+                '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+                c => c,
+            })
+            .collect();
+        let cleaned = cleaned.trim_matches('\'');
+        let base: String = if cleaned.is_empty() {
+            "Sheet".to_string()
+        } else {
+            cleaned.chars().take(MAX_SHEET_NAME_LEN).collect()
+        };
+
+        let mut candidate = base.clone();
+        let mut counter = 2;
+        while self.sheet_names.contains(&candidate.to_lowercase()) {
+            let suffix = format!("_{}", counter);
+            let keep = MAX_SHEET_NAME_LEN - suffix.len();
+            candidate = format!("{}{}", base.chars().take(keep).collect::<String>(), suffix);
+            counter += 1;
+        }
+
+        self.sheet_names.insert(candidate.to_lowercase());
+        candidate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{open_workbook_auto, Reader};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_unique_sheet_name() {
+        let mut writer = WorkbookWriter::new();
+
+        assert_eq!(writer.unique_sheet_name("combined_abc"), "combined_abc");
+        assert_eq!(writer.unique_sheet_name("combined_abc"), "combined_abc_2");
+        assert_eq!(writer.unique_sheet_name("a/b:c"), "a_b_c");
+
+        let long = "x".repeat(40);
+        assert_eq!(writer.unique_sheet_name(&long).len(), 31);
+        assert_eq!(writer.unique_sheet_name(&long), format!("{}_2", "x".repeat(29)));
+    }
+
+    #[test]
+    fn test_workbook_round_trip() {
+        let test_dir = TempDir::new().unwrap();
+        let path = test_dir.path().join("combined.xlsx");
+
+        let mut writer = WorkbookWriter::new();
+        writer
+            .add_sheet(
+                "combined_1",
+                &["Name".to_string(), "Age".to_string()],
+                &[vec!["Alice".to_string(), "30".to_string()]],
+            )
+            .unwrap();
+        writer
+            .add_sheet("single_2", &["Product".to_string()], &[vec!["Widget".to_string()]])
+            .unwrap();
+        writer.save(&path).unwrap();
+
+        let mut workbook = open_workbook_auto(&path).unwrap();
+        assert_eq!(workbook.sheet_names(), vec!["combined_1", "single_2"]);
+
+        let range = workbook.worksheet_range("combined_1").unwrap();
+        let rows: Vec<Vec<String>> = range
+            .rows()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect();
+        assert_eq!(rows, vec![vec!["Name", "Age"], vec!["Alice", "30"]]);
+    }
+}